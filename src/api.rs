@@ -0,0 +1,167 @@
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::info;
+
+use crate::Config;
+
+/// Request every page of a paginated Canvas endpoint, following the `Link` header.
+pub(crate) async fn get_paginated<T: DeserializeOwned>(
+    url: &str,
+    query: &[(&str, String)],
+    config: &Config,
+) -> Result<Vec<T>> {
+    let mut next_page_exists = true;
+    let mut page = 1;
+    let mut responses: Vec<T> = Vec::new();
+    while next_page_exists {
+        let mut form = query.to_vec();
+        form.push(("page", page.to_string()));
+
+        info!("Requesting from \"{url}\", page {page}");
+        let response = config.cache.get(&config.client, url, &form).await?;
+
+        let untyped: serde_json::Value =
+            serde_json::from_str(&response.body).context("Failed to parse invalid JSON body.")?;
+        info!("Parsed into untyped JSON");
+
+        info!("Attempting to parse JSON into structured data type...");
+
+        let mut structured = serde_json::from_str(&response.body)
+            .with_context(|| format!("Unable to parse response to data type: {:#?}", untyped))?;
+        responses.append(&mut structured);
+
+        next_page_exists = response
+            .link
+            .context("Failed to get link header.")?
+            .contains("next");
+        page += 1;
+    }
+
+    Ok(responses)
+}
+
+/// Request a Canvas endpoint which is not paginated, through the cache.
+pub(crate) async fn get<T: DeserializeOwned>(url: &str, config: &Config) -> Result<T> {
+    info!("Requesting from \"{url}\"");
+    let response = config.cache.get(&config.client, url, &[]).await?;
+    serde_json::from_str(&response.body)
+        .with_context(|| format!("Unable to parse response from \"{url}\""))
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQLError>,
+}
+
+/// An error reported by a GraphQL request, or by a mutation in its `errors` field.
+#[derive(Debug, Deserialize)]
+pub(crate) struct GraphQLError {
+    pub message: String,
+}
+
+/// Run a query or mutation against Canvas' GraphQL API, returning its `data`.
+pub(crate) async fn graphql<T: DeserializeOwned>(
+    query: &str,
+    variables: serde_json::Value,
+    config: &Config,
+) -> Result<T> {
+    info!("Sending GraphQL request with {variables}");
+    let response: GraphQLResponse<T> = config
+        .client
+        .post(format!("{}/api/graphql", config.base_url))
+        .json(&serde_json::json!({ "query": query, "variables": variables }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Unable to parse GraphQL response")?;
+
+    check_errors(&response.errors)?;
+    response.data.context("GraphQL response has no data")
+}
+
+pub(crate) fn check_errors(errors: &[GraphQLError]) -> Result<()> {
+    if !errors.is_empty() {
+        bail!(
+            "GraphQL request failed: {}",
+            errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>()
+                .join("; ")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::{json, Value};
+
+    use super::get_paginated;
+    use crate::{transport::memory_config, MemoryTransport};
+
+    const URL: &str = "https://canvas.test/api/v1/courses/1/users";
+
+    fn users(count: u64) -> Value {
+        (1..=count).map(|id| json!({ "id": id })).collect()
+    }
+
+    fn pages(transport: &MemoryTransport) -> Vec<String> {
+        transport
+            .requests()
+            .iter()
+            .flat_map(|(_, url)| url.query_pairs().find(|(key, _)| key == "page"))
+            .map(|(_, page)| page.into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn query_is_sent_with_every_page() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(2));
+        transport.respond(Method::GET, "/api/v1/courses/1/users", users(4));
+        let config = memory_config(transport.clone(), directory.path());
+
+        let query = [("enrollment_type[]", "student".to_owned())];
+        let _: Vec<Value> = get_paginated(URL, &query, &config).await.unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|(_, url)| url
+            .query()
+            .unwrap()
+            .contains("enrollment_type%5B%5D=student")));
+    }
+
+    #[tokio::test]
+    async fn empty_lists_take_one_request() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(2));
+        transport.respond(Method::GET, "/api/v1/courses/1/users", json!([]));
+        let config = memory_config(transport.clone(), directory.path());
+
+        let users: Vec<Value> = get_paginated(URL, &[], &config).await.unwrap();
+
+        assert!(users.is_empty());
+        assert_eq!(pages(&transport), ["1"]);
+    }
+
+    #[tokio::test]
+    async fn missing_endpoints_fail() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(2));
+        let config = memory_config(transport, directory.path());
+
+        let result: anyhow::Result<Vec<Value>> = get_paginated(URL, &[], &config).await;
+
+        assert!(result.is_err());
+    }
+}
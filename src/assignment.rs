@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    api::{check_errors, get, get_paginated, graphql, GraphQLError},
+    spec::Criterion,
+    Config, Progress,
+};
+
+const SET_POST_POLICY: &str = "
+mutation SetAssignmentPostPolicy($assignmentId: ID!, $postManually: Boolean!) {
+  setAssignmentPostPolicy(input: {assignmentId: $assignmentId, postManually: $postManually}) {
+    errors { message }
+  }
+}";
+
+const POST_GRADES: &str = "
+mutation PostAssignmentGrades($assignmentId: ID!, $sectionIds: [ID!], $gradedOnly: Boolean) {
+  postAssignmentGrades(input: {assignmentId: $assignmentId, sectionIds: $sectionIds, gradedOnly: $gradedOnly}) {
+    progress { _id }
+    errors { message }
+  }
+}";
+
+const HIDE_GRADES: &str = "
+mutation HideAssignmentGrades($assignmentId: ID!, $sectionIds: [ID!]) {
+  hideAssignmentGrades(input: {assignmentId: $assignmentId, sectionIds: $sectionIds}) {
+    progress { _id }
+    errors { message }
+  }
+}";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Assignment {
+    pub id: u64,
+    pub name: String,
+    pub points_possible: Option<f32>,
+    pub due_at: Option<DateTime<Utc>>,
+    /// When students can start submitting
+    pub unlock_at: Option<DateTime<Utc>>,
+    /// When submissions stop being accepted, even late
+    pub lock_at: Option<DateTime<Utc>>,
+    /// Whether grades stay hidden from students until they are posted
+    #[serde(default)]
+    pub post_manually: bool,
+    /// Whether student names are hidden from graders, who see anonymous ids instead
+    #[serde(default)]
+    pub anonymous_grading: bool,
+    /// Whether several graders give provisional grades which a moderator selects from
+    #[serde(default)]
+    pub moderated_grading: bool,
+    /// Set on group assignments
+    pub group_category_id: Option<u64>,
+    /// Whether a grade given to one member of a group applies only to them
+    #[serde(default)]
+    pub grade_group_students_individually: bool,
+    /// HTML shown to students
+    pub description: Option<String>,
+    #[serde(default)]
+    pub submission_types: Vec<String>,
+    /// File extensions accepted for `online_upload` submissions, or any if empty
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+    #[serde(default)]
+    pub published: bool,
+    /// Criteria of the rubric attached to the assignment
+    pub rubric: Option<Vec<Criterion>>,
+    pub rubric_settings: Option<RubricSettings>,
+    pub assignment_group_id: Option<u64>,
+    /// Order within the assignment group
+    #[serde(default)]
+    pub position: u64,
+    /// Whether the assignment counts towards students' totals
+    #[serde(default)]
+    pub omit_from_final_grade: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RubricSettings {
+    pub id: u64,
+}
+
+/// The result of a mutation which may report errors instead of failing the request.
+#[derive(Debug, Deserialize)]
+struct Payload {
+    progress: Option<PayloadProgress>,
+    errors: Option<Vec<GraphQLError>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayloadProgress {
+    #[serde(rename = "_id")]
+    id: String,
+}
+
+impl Payload {
+    async fn progress(self, config: &Config) -> Result<Progress> {
+        let id = self.progress.context("Canvas did not start a job")?.id;
+        Progress::get(id.parse()?, config).await
+    }
+}
+
+impl Assignment {
+    pub async fn get(assignment_id: u64, config: &Config) -> Result<Self> {
+        get(
+            &format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}",
+                config.base_url, config.course_id
+            ),
+            config,
+        )
+        .await
+        .with_context(|| format!("Unable to get assignment {assignment_id}"))
+    }
+
+    /// Create an assignment from `assignment[...]` form fields.
+    pub async fn create(form: &[(String, String)], config: &Config) -> Result<Self> {
+        info!("Creating assignment");
+        config
+            .client
+            .post(format!(
+                "{}/api/v1/courses/{}/assignments",
+                config.base_url, config.course_id
+            ))
+            .form(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Unable to parse created assignment")
+    }
+
+    /// Change the `assignment[...]` form fields given, leaving the others as they are.
+    pub async fn update(
+        assignment_id: u64,
+        form: &[(String, String)],
+        config: &Config,
+    ) -> Result<Self> {
+        info!("Updating assignment {assignment_id}");
+        config
+            .client
+            .put(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}",
+                config.base_url, config.course_id
+            ))
+            .form(form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Unable to parse updated assignment {assignment_id}"))
+    }
+
+    /// Attach a rubric used for grading, replacing the assignment's current rubric if it has one.
+    pub async fn set_rubric(&self, criteria: &[Criterion], config: &Config) -> Result<()> {
+        let mut form = vec![
+            ("rubric[title]".to_owned(), self.name.clone()),
+            (
+                "rubric_association[association_id]".to_owned(),
+                self.id.to_string(),
+            ),
+            (
+                "rubric_association[association_type]".to_owned(),
+                "Assignment".to_owned(),
+            ),
+            (
+                "rubric_association[use_for_grading]".to_owned(),
+                "true".to_owned(),
+            ),
+            (
+                "rubric_association[purpose]".to_owned(),
+                "grading".to_owned(),
+            ),
+        ];
+        for (i, criterion) in criteria.iter().enumerate() {
+            let key = format!("rubric[criteria][{i}]");
+            form.push((format!("{key}[description]"), criterion.description.clone()));
+            form.push((format!("{key}[points]"), criterion.points.to_string()));
+            for (j, rating) in criterion.ratings.iter().enumerate() {
+                let key = format!("{key}[ratings][{j}]");
+                form.push((format!("{key}[description]"), rating.description.clone()));
+                form.push((format!("{key}[points]"), rating.points.to_string()));
+            }
+        }
+
+        let url = format!(
+            "{}/api/v1/courses/{}/rubrics",
+            config.base_url, config.course_id
+        );
+        let request = match &self.rubric_settings {
+            Some(rubric) => {
+                info!("Updating rubric {} of assignment {}", rubric.id, self.id);
+                config.client.put(format!("{url}/{}", rubric.id))
+            }
+            None => {
+                info!("Creating rubric for assignment {}", self.id);
+                config.client.post(url)
+            }
+        };
+        request.form(&form).send().await?.error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Every assignment in the course.
+    pub async fn list(config: &Config) -> Result<Vec<Self>> {
+        get_paginated(
+            &format!(
+                "{}/api/v1/courses/{}/assignments",
+                config.base_url, config.course_id
+            ),
+            &[],
+            config,
+        )
+        .await
+    }
+
+    /// Set whether grades are posted automatically when graded, or stay hidden until
+    /// posted with [`Assignment::post_grades`].
+    pub async fn set_post_policy(
+        assignment_id: u64,
+        post_manually: bool,
+        config: &Config,
+    ) -> Result<()> {
+        info!(
+            "Setting post policy of assignment {assignment_id} to post manually: {post_manually}"
+        );
+        Self::mutate(
+            SET_POST_POLICY,
+            "setAssignmentPostPolicy",
+            json!({ "assignmentId": assignment_id.to_string(), "postManually": post_manually }),
+            config,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Make grades visible to students, in every section if `section_ids` is empty.
+    ///
+    /// Returns the job posting the grades, which finishes in the background.
+    pub async fn post_grades(
+        assignment_id: u64,
+        section_ids: &[u64],
+        graded_only: bool,
+        config: &Config,
+    ) -> Result<Progress> {
+        info!("Posting grades of assignment {assignment_id}");
+        Self::mutate(
+            POST_GRADES,
+            "postAssignmentGrades",
+            json!({
+                "assignmentId": assignment_id.to_string(),
+                "sectionIds": Self::section_ids(section_ids),
+                "gradedOnly": graded_only,
+            }),
+            config,
+        )
+        .await?
+        .progress(config)
+        .await
+    }
+
+    /// Hide grades from students, in every section if `section_ids` is empty.
+    ///
+    /// Returns the job hiding the grades, which finishes in the background.
+    pub async fn hide_grades(
+        assignment_id: u64,
+        section_ids: &[u64],
+        config: &Config,
+    ) -> Result<Progress> {
+        info!("Hiding grades of assignment {assignment_id}");
+        Self::mutate(
+            HIDE_GRADES,
+            "hideAssignmentGrades",
+            json!({
+                "assignmentId": assignment_id.to_string(),
+                "sectionIds": Self::section_ids(section_ids),
+            }),
+            config,
+        )
+        .await?
+        .progress(config)
+        .await
+    }
+
+    /// Run a mutation, failing if it reports errors in its payload.
+    async fn mutate(
+        query: &str,
+        name: &str,
+        variables: serde_json::Value,
+        config: &Config,
+    ) -> Result<Payload> {
+        let mut data: serde_json::Map<String, serde_json::Value> =
+            graphql(query, variables, config).await?;
+        let payload: Payload = serde_json::from_value(
+            data.remove(name)
+                .with_context(|| format!("Missing {name} payload"))?,
+        )?;
+        check_errors(payload.errors.as_deref().unwrap_or_default())?;
+
+        Ok(payload)
+    }
+
+    /// GraphQL ids are strings, and omitting the sections applies to the whole course.
+    fn section_ids(section_ids: &[u64]) -> Option<Vec<String>> {
+        (!section_ids.is_empty()).then(|| section_ids.iter().map(u64::to_string).collect())
+    }
+}
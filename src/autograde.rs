@@ -0,0 +1,470 @@
+use std::{
+    io::Read,
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    sandbox::{self, SandboxConfig},
+    Comment, Grade, StudentId,
+};
+
+/// How often a running test command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Messages printed by common runtimes when an allocation fails.
+const ALLOCATION_FAILURES: &[&str] = &[
+    "memory allocation of",
+    "Cannot allocate memory",
+    "out of memory",
+    "std::bad_alloc",
+    "MemoryError",
+    "OutOfMemoryError",
+];
+
+/// The `[autograde]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AutogradeConfig {
+    /// Command run with `sh -c` in each student's directory
+    pub command: Option<String>,
+    /// Seconds of wall clock time before the command is killed
+    pub timeout: u64,
+    /// Seconds of CPU time the command may use
+    pub max_cpu_time: u64,
+    /// MiB of address space each process may use
+    pub max_memory: u64,
+    /// Bytes of standard output and error kept from the command
+    pub max_output: usize,
+    pub sandbox: SandboxConfig,
+}
+
+impl Default for AutogradeConfig {
+    fn default() -> Self {
+        Self {
+            command: None,
+            timeout: 60,
+            max_cpu_time: 60,
+            max_memory: 1024,
+            max_output: 1024 * 1024,
+            sandbox: SandboxConfig::default(),
+        }
+    }
+}
+
+/// How a test command finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Exited on its own with the given status code
+    Exited(i32),
+    /// Killed by a signal it did not receive from the autograder
+    Signaled(i32),
+    /// Killed for running longer than the timeout
+    TimedOut,
+    /// Killed for using more CPU time than allowed
+    CpuTimeExceeded,
+    /// Failed to allocate memory or was killed by the kernel's OOM killer
+    OutOfMemory,
+    /// Never ran, as the submission could not be downloaded or the command started
+    Failed,
+}
+
+/// Resources used by a test command and the children it waited for.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceUsage {
+    pub wall_time: Duration,
+    pub cpu_time: Duration,
+    /// Peak resident memory of the largest process in KiB
+    pub max_rss: u64,
+}
+
+impl std::fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.2}s wall time, {:.2}s CPU time, {} KiB peak memory",
+            self.wall_time.as_secs_f32(),
+            self.cpu_time.as_secs_f32(),
+            self.max_rss
+        )
+    }
+}
+
+/// Grade and comments parsed from a test command's output.
+#[derive(Debug)]
+pub struct AutogradeResult {
+    pub user_id: StudentId,
+    pub outcome: Outcome,
+    /// `None` if the output contained no `points:` lines
+    pub grade: Option<Grade>,
+    pub comments: Vec<Comment>,
+    pub tests: Vec<TestResult>,
+    pub stdout: String,
+    pub stderr: String,
+    pub usage: ResourceUsage,
+}
+
+impl AutogradeResult {
+    /// The result of a submission which could not be graded, with the error as its output.
+    pub fn failed(user_id: StudentId, error: &anyhow::Error) -> Self {
+        Self {
+            user_id,
+            outcome: Outcome::Failed,
+            grade: None,
+            comments: Vec::new(),
+            tests: Vec::new(),
+            stdout: String::new(),
+            stderr: format!("{error:#}"),
+            usage: ResourceUsage::default(),
+        }
+    }
+}
+
+/// Runs the configured test command against student submissions.
+#[derive(Debug, Clone)]
+pub struct Autograder {
+    config: AutogradeConfig,
+    command: String,
+}
+
+impl Autograder {
+    pub fn new(config: AutogradeConfig) -> Result<Self> {
+        let command = config
+            .command
+            .clone()
+            .ok_or(anyhow!("Autograde command not configured!"))?;
+
+        Ok(Self { config, command })
+    }
+
+    /// Run the test command in `directory`, parsing its standard output into a grade and
+    /// comments with [`parse_output`].
+    ///
+    /// The command is run in its own process group with `STUDENT_ID`, `ASSIGNMENT_ID` and
+    /// `SUBMISSION_DIR` set, and is killed along with its children when the timeout elapses.
+    /// If the sandbox is enabled, it is also isolated from the network, other processes and
+    /// the rest of the file system.
+    pub async fn run(
+        &self,
+        directory: &Path,
+        user_id: StudentId,
+        assignment_id: u64,
+    ) -> Result<AutogradeResult> {
+        let directory = &directory.canonicalize()?;
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .current_dir(directory)
+            .env("STUDENT_ID", user_id.to_string())
+            .env("ASSIGNMENT_ID", assignment_id.to_string())
+            .env("SUBMISSION_DIR", directory)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        limits::apply(&mut command, &self.config);
+        if self.config.sandbox.enabled {
+            sandbox::apply(&mut command, &self.config.sandbox, directory)?;
+        }
+
+        info!("Running \"{}\" for {user_id}", self.command);
+        let config = self.config.clone();
+        let (outcome, usage, stdout, stderr) =
+            tokio::task::spawn_blocking(move || run_with_timeout(command, &config)).await??;
+        info!("Autograder finished for {user_id}: {:?}, {usage}", outcome);
+
+        let (grade, mut comments, tests) = parse_output(&user_id, &stdout);
+        match outcome {
+            Outcome::Exited(0) => {}
+            Outcome::Exited(code) => comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: format!("Tests exited with status {code}"),
+            }),
+            Outcome::Signaled(signal) => comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: format!("Tests were killed by signal {signal}"),
+            }),
+            Outcome::TimedOut => comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: format!("Tests timed out after {} seconds", self.config.timeout),
+            }),
+            Outcome::CpuTimeExceeded => comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: format!(
+                    "Tests exceeded the CPU time limit of {} seconds",
+                    self.config.max_cpu_time
+                ),
+            }),
+            Outcome::OutOfMemory => comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: format!(
+                    "Tests ran out of memory with a limit of {} MiB",
+                    self.config.max_memory
+                ),
+            }),
+            Outcome::Failed => {}
+        }
+
+        Ok(AutogradeResult {
+            user_id,
+            outcome,
+            grade,
+            comments,
+            tests,
+            stdout,
+            stderr,
+            usage,
+        })
+    }
+}
+
+/// The result of a single test, reported by a `test: <NAME>: <RESULT>` output line.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub result: String,
+    /// Whether the result is `pass`, `passed` or `ok`, ignoring case
+    pub passed: bool,
+}
+
+/// Parse test output into a grade, comments and test results for a student.
+///
+/// Lines of the form `points: <POINTS>` are summed into the grade, lines of the form
+/// `comment: <COMMENT>` become comments, and lines of the form `test: <NAME>: <RESULT>`
+/// become test results. All other lines are ignored.
+pub fn parse_output(
+    user_id: &StudentId,
+    output: &str,
+) -> (Option<Grade>, Vec<Comment>, Vec<TestResult>) {
+    let mut points: Option<f32> = None;
+    let mut comments = Vec::new();
+    let mut tests = Vec::new();
+    for line in output.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("points:") {
+            match value.trim().parse::<f32>() {
+                Ok(value) => *points.get_or_insert(0.0) += value,
+                Err(_) => warn!("Ignoring invalid points line \"{line}\""),
+            }
+        } else if let Some(comment) = line.strip_prefix("comment:") {
+            comments.push(Comment {
+                user_id: user_id.clone(),
+                comment: comment.trim().to_owned(),
+            });
+        } else if let Some(test) = line.strip_prefix("test:") {
+            match test.split_once(':') {
+                Some((name, result)) => {
+                    let result = result.trim();
+                    tests.push(TestResult {
+                        name: name.trim().to_owned(),
+                        result: result.to_owned(),
+                        passed: ["pass", "passed", "ok"]
+                            .iter()
+                            .any(|p| result.eq_ignore_ascii_case(p)),
+                    });
+                }
+                None => warn!("Ignoring invalid test line \"{line}\""),
+            }
+        }
+    }
+
+    (
+        points.map(|grade| Grade {
+            user_id: user_id.clone(),
+            grade,
+        }),
+        comments,
+        tests,
+    )
+}
+
+fn run_with_timeout(
+    mut command: Command,
+    config: &AutogradeConfig,
+) -> Result<(Outcome, ResourceUsage, String, String)> {
+    let started = Instant::now();
+    let mut child = command.spawn().with_context(|| {
+        if config.sandbox.enabled {
+            "Unable to start sandboxed autograde command. Are unprivileged user namespaces enabled?"
+        } else {
+            "Unable to start autograde command"
+        }
+    })?;
+    let stdout = read_capped(child.stdout.take(), config.max_output);
+    let stderr = read_capped(child.stderr.take(), config.max_output);
+
+    let deadline = started + Duration::from_secs(config.timeout);
+    let mut timed_out = false;
+    let (status, mut usage) = loop {
+        if let Some(finished) = try_wait(&mut child)? {
+            break finished;
+        }
+        if !timed_out && Instant::now() >= deadline {
+            child.kill()?;
+            timed_out = true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    usage.wall_time = started.elapsed();
+    // Background processes left behind would otherwise keep the output pipes open
+    #[cfg(unix)]
+    limits::kill_group(child.id());
+
+    let join = |handle: JoinHandle<Vec<u8>>| {
+        String::from_utf8_lossy(&handle.join().unwrap_or_default()).into_owned()
+    };
+    let (stdout, stderr) = (join(stdout), join(stderr));
+    let outcome = outcome(status, timed_out, &usage, &stderr, config);
+
+    Ok((outcome, usage, stdout, stderr))
+}
+
+#[cfg(unix)]
+fn try_wait(child: &mut Child) -> Result<Option<(ExitStatus, ResourceUsage)>> {
+    Ok(limits::try_wait(child.id())?)
+}
+
+#[cfg(not(unix))]
+fn try_wait(child: &mut Child) -> Result<Option<(ExitStatus, ResourceUsage)>> {
+    Ok(child
+        .try_wait()?
+        .map(|status| (status, ResourceUsage::default())))
+}
+
+fn outcome(
+    status: ExitStatus,
+    timed_out: bool,
+    usage: &ResourceUsage,
+    stderr: &str,
+    config: &AutogradeConfig,
+) -> Outcome {
+    let allocation_failed = ALLOCATION_FAILURES.iter().any(|m| stderr.contains(m));
+    if timed_out {
+        return Outcome::TimedOut;
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return match signal {
+            libc::SIGXCPU => Outcome::CpuTimeExceeded,
+            libc::SIGKILL if usage.cpu_time.as_secs() >= config.max_cpu_time => {
+                Outcome::CpuTimeExceeded
+            }
+            _ if allocation_failed => Outcome::OutOfMemory,
+            signal => Outcome::Signaled(signal),
+        };
+    }
+
+    // A shell reports children killed by a signal through its exit status instead
+    match status.code().unwrap_or(-1) {
+        0 => Outcome::Exited(0),
+        _ if usage.cpu_time.as_secs() >= config.max_cpu_time => Outcome::CpuTimeExceeded,
+        _ if allocation_failed => Outcome::OutOfMemory,
+        code => Outcome::Exited(code),
+    }
+}
+
+/// Read up to `max` bytes on another thread, discarding the rest so the writer never blocks.
+fn read_capped(reader: Option<impl Read + Send + 'static>, max: usize) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut reader) = reader {
+            let _ = reader.by_ref().take(max as u64).read_to_end(&mut buffer);
+            let _ = std::io::copy(&mut reader, &mut std::io::sink());
+        }
+        buffer
+    })
+}
+
+#[cfg(unix)]
+mod limits {
+    use std::{
+        os::unix::process::{CommandExt, ExitStatusExt},
+        process::{Command, ExitStatus},
+        time::Duration,
+    };
+
+    use super::{AutogradeConfig, ResourceUsage};
+
+    /// Start the command in a new process group with CPU and memory limits.
+    pub fn apply(command: &mut Command, config: &AutogradeConfig) {
+        let cpu = config.max_cpu_time as libc::rlim_t;
+        let memory = (config.max_memory * 1024 * 1024) as libc::rlim_t;
+
+        // SAFETY: only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // The soft limit sends SIGXCPU a second before the hard limit's SIGKILL
+                set_limit(libc::RLIMIT_CPU, cpu, cpu + 1)?;
+                set_limit(libc::RLIMIT_AS, memory, memory)?;
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    type Resource = libc::c_int;
+
+    fn set_limit(
+        resource: Resource,
+        soft: libc::rlim_t,
+        hard: libc::rlim_t,
+    ) -> std::io::Result<()> {
+        let limit = libc::rlimit {
+            rlim_cur: soft,
+            rlim_max: hard,
+        };
+        // SAFETY: `limit` is a valid rlimit for the duration of the call
+        if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Kill every process in the group led by `pid`.
+    pub fn kill_group(pid: u32) {
+        // SAFETY: sending a signal has no memory safety requirements
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+
+    /// Reap the process if it has exited, returning its status and resource usage.
+    pub fn try_wait(pid: u32) -> std::io::Result<Option<(ExitStatus, ResourceUsage)>> {
+        let mut status = 0;
+        // SAFETY: an all-zero rusage is valid
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: `status` and `usage` are valid for writes for the duration of the call
+        let result =
+            unsafe { libc::wait4(pid as libc::pid_t, &mut status, libc::WNOHANG, &mut usage) };
+
+        match result {
+            -1 => Err(std::io::Error::last_os_error()),
+            0 => Ok(None),
+            _ => Ok(Some((
+                ExitStatus::from_raw(status),
+                ResourceUsage {
+                    wall_time: Duration::ZERO,
+                    cpu_time: duration(usage.ru_utime) + duration(usage.ru_stime),
+                    max_rss: usage.ru_maxrss as u64,
+                },
+            ))),
+        }
+    }
+
+    fn duration(time: libc::timeval) -> Duration {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    }
+}
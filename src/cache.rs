@@ -0,0 +1,304 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use reqwest::{header, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Parameters which only add associations to a response, so a response cached with more
+/// of them still has everything a request without them needs.
+const INCLUDE_PARAMETER: &str = "include[]";
+
+/// Responses from Canvas saved under the local data directory, so they can be revalidated
+/// with their ETag instead of downloaded again, or read without a connection when offline.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    /// Nothing is cached if `None`
+    directory: Option<PathBuf>,
+    pub offline: bool,
+    /// Held while the index is updated, as pages are cached concurrently
+    index_lock: Arc<Mutex<()>>,
+}
+
+/// The queries cached for each URL, so offline requests can find a response cached with
+/// more parameters without reading every cached response.
+type Index = BTreeMap<String, Vec<Vec<(String, String)>>>;
+
+/// A response body with the headers needed to use it again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    url: String,
+    query: Vec<(String, String)>,
+    etag: Option<String>,
+    pub link: Option<String>,
+    pub body: String,
+}
+
+impl Cache {
+    pub fn new(directory: &Path, offline: bool) -> Self {
+        Self {
+            directory: Some(directory.to_owned()),
+            offline,
+            index_lock: Arc::default(),
+        }
+    }
+
+    /// Send every request to Canvas without saving responses, such as for programs using the
+    /// crate which keep their own data.
+    pub fn disabled() -> Self {
+        Self {
+            directory: None,
+            offline: false,
+            index_lock: Arc::default(),
+        }
+    }
+
+    /// `grading/cache` in the local data directory.
+    pub fn default_directory() -> Result<PathBuf> {
+        Ok(dirs::data_local_dir()
+            .context("Unable to get data dir for system")?
+            .join("grading")
+            .join("cache"))
+    }
+
+    /// Request `url`, sending the cached response's ETag so Canvas can reply that it has not
+    /// changed. When offline, the cached response is used instead.
+    pub(crate) async fn get(
+        &self,
+        client: &ClientWithMiddleware,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> Result<CachedResponse> {
+        let query: Vec<(String, String)> = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        let path = self.path(url, &query);
+
+        if self.offline {
+            return self.find(url, &query).with_context(|| {
+                format!("\"{url}\" is not cached, run `cache refresh` while online")
+            });
+        }
+
+        let cached = path.as_deref().and_then(|path| self.read(path));
+        let mut request = client.get(url).query(&query);
+        if let Some(etag) = cached.as_ref().and_then(|c| c.etag.as_deref()) {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                info!("\"{url}\" has not changed since it was cached");
+                return Ok(cached);
+            }
+        }
+        let response = response.error_for_status()?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned)
+        };
+        let etag = header(header::ETAG);
+        let link = header(header::LINK);
+        let fresh = CachedResponse {
+            url: url.to_owned(),
+            query,
+            etag,
+            link,
+            body: response.text().await?,
+        };
+        if let Some(path) = &path {
+            self.write(path, &fresh)?;
+        }
+
+        Ok(fresh)
+    }
+
+    /// Delete every cached response.
+    pub fn clear(&self) -> Result<()> {
+        if let Some(directory) = self.directory.as_ref().filter(|d| d.exists()) {
+            std::fs::remove_dir_all(directory)
+                .with_context(|| format!("Unable to remove cache {:?}", directory))?;
+        }
+        Ok(())
+    }
+
+    /// The response cached for exactly this request, or else for the same URL and page with
+    /// more `include[]` parameters, such as the same submissions requested with their
+    /// comments. Responses cached with other extra parameters, such as filters, are not used.
+    fn find(&self, url: &str, query: &[(String, String)]) -> Option<CachedResponse> {
+        if let Some(cached) = self.read(&self.path(url, query)?) {
+            return Some(cached);
+        }
+
+        self.read_index()
+            .remove(url)?
+            .iter()
+            .filter(|cached| {
+                query.iter().all(|q| cached.contains(q))
+                    && cached
+                        .iter()
+                        .all(|c| c.0 == INCLUDE_PARAMETER || query.contains(c))
+            })
+            .find_map(|cached| self.read(&self.path(url, cached)?))
+    }
+
+    fn read(&self, path: &Path) -> Option<CachedResponse> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn write(&self, path: &Path, response: &CachedResponse) -> Result<()> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(path, serde_json::to_string(response)?)
+            .with_context(|| format!("Unable to write cache {:?}", path))?;
+
+        let _lock = self.index_lock.lock().unwrap();
+        let mut index = self.read_index();
+        let queries = index.entry(response.url.clone()).or_default();
+        if !queries.contains(&response.query) {
+            queries.push(response.query.clone());
+            let path = directory.join("index.json");
+            std::fs::write(&path, serde_json::to_string(&index)?)
+                .with_context(|| format!("Unable to write cache index {:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_index(&self) -> Index {
+        let Some(directory) = &self.directory else {
+            return Index::default();
+        };
+        std::fs::read_to_string(directory.join("index.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn path(&self, url: &str, query: &[(String, String)]) -> Option<PathBuf> {
+        let mut key = url.to_owned();
+        for (name, value) in query {
+            key.push_str(&format!("&{name}={value}"));
+        }
+        Some(
+            self.directory
+                .as_ref()?
+                .join(format!("{:016x}.json", fnv1a(&key))),
+        )
+    }
+}
+
+/// A hash which, unlike the standard library's, stays the same between builds, so cache
+/// file names do too.
+pub(crate) fn fnv1a(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::memory_config, MemoryTransport};
+
+    const URL: &str = "https://canvas.test/api/v1/courses/1/users";
+
+    fn query(pairs: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (*key, value.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn unchanged_responses_are_revalidated_with_their_etag() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(10));
+        transport.respond(Method::GET, "/api/v1/courses/1/users", json!({ "id": 1 }));
+        let config = memory_config(transport.clone(), directory.path());
+        let cache = &config.cache;
+
+        let fresh = cache.get(&config.client, URL, &[]).await.unwrap();
+        assert!(fresh.etag.is_some());
+
+        // Only a `304 Not Modified` answer would return what is on disk
+        let path = cache.path(URL, &[]).unwrap();
+        let stale = CachedResponse {
+            body: "cached".to_owned(),
+            ..fresh
+        };
+        cache.write(&path, &stale).unwrap();
+        let revalidated = cache.get(&config.client, URL, &[]).await.unwrap();
+        assert_eq!(revalidated.body, "cached");
+
+        transport.respond(Method::GET, "/api/v1/courses/1/users", json!({ "id": 2 }));
+        let changed = cache.get(&config.client, URL, &[]).await.unwrap();
+        assert_eq!(changed.body, r#"{"id":2}"#);
+    }
+
+    #[tokio::test]
+    async fn disabled_caches_request_every_time() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(10));
+        transport.respond(Method::GET, "/api/v1/courses/1/users", json!({ "id": 1 }));
+        let config = memory_config(transport.clone(), directory.path());
+        let cache = Cache::disabled();
+
+        for _ in 0..2 {
+            let response = cache.get(&config.client, URL, &[]).await.unwrap();
+            assert_eq!(response.body, r#"{"id":1}"#);
+        }
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn offline_requests_may_use_responses_with_more_includes() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(10));
+        transport.respond(Method::GET, "/api/v1/courses/1/users", json!([{ "id": 1 }]));
+        let config = memory_config(transport.clone(), directory.path());
+        for cached in [
+            query(&[("include[]", "email"), ("page", "1")]),
+            query(&[("student_ids[]", "1"), ("page", "2")]),
+        ] {
+            config
+                .cache
+                .get(&config.client, URL, &cached)
+                .await
+                .unwrap();
+        }
+
+        let offline = Cache::new(directory.path(), true);
+        let cached = |pairs: &[(&'static str, &str)]| {
+            let query = query(pairs);
+            let (offline, client) = (&offline, &config.client);
+            async move { offline.get(client, URL, &query).await.is_ok() }
+        };
+        assert!(cached(&[("include[]", "email"), ("page", "1")]).await);
+        assert!(cached(&[("page", "1")]).await);
+        // A filtered response may be missing some of the users
+        assert!(!cached(&[("page", "2")]).await);
+        assert!(!cached(&[("include[]", "avatar_url"), ("page", "1")]).await);
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{header, Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+/// Replaces the address of the recorded Canvas instance in cassettes.
+pub(crate) const PLACEHOLDER_URL: &str = "https://canvas.example.com";
+
+const REDACTED: &str = "[redacted]";
+
+/// Fields identifying a person wherever they appear, or holding text students wrote.
+const PII_FIELDS: &[&str] = &[
+    "author_name",
+    "avatar_image_url",
+    "avatar_url",
+    "body",
+    "comment",
+    "email",
+    "integration_id",
+    "login_id",
+    "pronouns",
+    "sis_user_id",
+];
+
+/// Fields which only identify a person on users and comment authors, as assignments and
+/// groups have names too.
+const USER_FIELDS: &[&str] = &["name", "sortable_name", "short_name", "display_name"];
+
+/// Links to pages which can show a student's work, such as submission previews.
+const LINK_FIELDS: &[&str] = &["html_url", "preview_url"];
+
+/// Fields naming a file a student uploaded, which may contain their name.
+const FILENAME_FIELDS: &[&str] = &["filename", "display_name"];
+
+/// Query parameters holding credentials.
+const SECRET_PARAMETERS: &[&str] = &["access_token", "verifier"];
+
+/// Save every request and response to Canvas in a directory, or answer requests from one,
+/// so problems can be reproduced without access to the course.
+#[derive(Debug, Clone)]
+pub enum Cassette {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A request and its response, with credentials and students' personal information removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// Form or JSON bodies, but not uploaded files
+    body: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    content_type: Option<String>,
+    link: Option<String>,
+    /// Where a redirect leads, such as to confirm a file upload
+    location: Option<String>,
+    /// The body if it is JSON
+    json: Option<Value>,
+    /// Whether the body was not JSON, such as a downloaded file, which is replaced with a
+    /// placeholder
+    text: Option<String>,
+}
+
+impl Cassette {
+    /// The middleware recording to or replaying from the cassette's directory.
+    pub(crate) fn middleware(&self) -> Result<Arc<dyn Middleware>> {
+        Ok(match self {
+            Cassette::Record(directory) => Arc::new(Recorder::new(directory)?),
+            Cassette::Replay(directory) => Arc::new(Player::new(directory)?),
+        })
+    }
+}
+
+/// Numbered files in the order interactions were recorded.
+fn interaction_paths(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(directory)
+        .with_context(|| format!("Unable to read cassette directory {:?}", directory))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+struct Recorder {
+    directory: PathBuf,
+    next: AtomicUsize,
+}
+
+impl Recorder {
+    /// Recording continues after interactions already in the directory, so several commands
+    /// can be recorded one after another.
+    fn new(directory: &Path) -> Result<Self> {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("Unable to create cassette directory {:?}", directory))?;
+        Ok(Self {
+            directory: directory.to_owned(),
+            next: AtomicUsize::new(interaction_paths(directory)?.len()),
+        })
+    }
+
+    /// Save the interaction, replacing `origin` with the placeholder address.
+    async fn record(
+        &self,
+        request: RecordedRequest,
+        origin: &str,
+        response: Response,
+    ) -> Result<Response> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = response.bytes().await?;
+
+        let (json, text) = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(mut json) => {
+                scrub(&mut json, origin);
+                (Some(json), None)
+            }
+            Err(_) if bytes.is_empty() => (None, None),
+            Err(_) => (None, Some(REDACTED.to_owned())),
+        };
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned)
+        };
+        let interaction = Interaction {
+            request,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                content_type: header(header::CONTENT_TYPE),
+                link: header(header::LINK).map(|link| scrub_text(&link, origin)),
+                location: header(header::LOCATION).map(|url| scrub_text(&url, origin)),
+                json,
+                text,
+            },
+        };
+
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        let path = self.directory.join(format!("{index:04}.json"));
+        info!(
+            "Recording {} {} to {:?}",
+            interaction.request.method, interaction.request.path, path
+        );
+        std::fs::write(&path, serde_json::to_string_pretty(&interaction)?)
+            .with_context(|| format!("Unable to write cassette {:?}", path))?;
+
+        // The caller gets the original response
+        let mut original = http::Response::new(bytes);
+        *original.status_mut() = status;
+        *original.headers_mut() = headers;
+        Ok(original.into())
+    }
+}
+
+#[async_trait]
+impl Middleware for Recorder {
+    async fn handle(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        // Revalidating against the local cache would record empty `304 Not Modified`
+        // responses, which cannot be replayed without the same cache
+        request.headers_mut().remove(header::IF_NONE_MATCH);
+        let recorded = RecordedRequest::new(&request);
+        // Responses from other transports may not know the URL they answer
+        let origin = request.url().origin().ascii_serialization();
+
+        let response = next.run(request, extensions).await?;
+        Ok(self.record(recorded, &origin, response).await?)
+    }
+}
+
+struct Player {
+    /// Each interaction, and whether it has been replayed
+    interactions: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl Player {
+    fn new(directory: &Path) -> Result<Self> {
+        let interactions = interaction_paths(directory)?
+            .into_iter()
+            .map(|path| {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Unable to read cassette {:?}", path))?;
+                let interaction: Interaction = serde_json::from_str(&contents)
+                    .with_context(|| format!("Unable to parse cassette {:?}", path))?;
+                Ok((interaction, false))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    /// The first matching interaction not replayed yet, or else the last one replayed, such
+    /// as when a job is checked for completion more times than when recorded.
+    fn replay(&self, request: &RecordedRequest) -> Result<Response> {
+        let mut interactions = self.interactions.lock().unwrap();
+        let matches = |i: &Interaction| {
+            i.request.method == request.method
+                && i.request.path == request.path
+                && i.request.query == request.query
+        };
+        let index = interactions
+            .iter()
+            .position(|(i, replayed)| !replayed && matches(i))
+            .or_else(|| interactions.iter().rposition(|(i, _)| matches(i)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No recorded response for {} {}?{}",
+                    request.method,
+                    request.path,
+                    request
+                        .query
+                        .iter()
+                        .map(|(k, v)| format!("{k}={v}"))
+                        .collect::<Vec<_>>()
+                        .join("&")
+                )
+            })?;
+        interactions[index].1 = true;
+
+        info!("Replaying {} {}", request.method, request.path);
+        let recorded = &interactions[index].0.response;
+        let body = match (&recorded.json, &recorded.text) {
+            (Some(json), _) => serde_json::to_string(json)?,
+            (None, Some(text)) => text.clone(),
+            (None, None) => String::new(),
+        };
+        let mut response = http::Response::builder().status(recorded.status);
+        if let Some(content_type) = &recorded.content_type {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+        if let Some(link) = &recorded.link {
+            response = response.header(header::LINK, link);
+        }
+        if let Some(location) = &recorded.location {
+            response = response.header(header::LOCATION, location);
+        }
+
+        Ok(response.body(body)?.into())
+    }
+}
+
+#[async_trait]
+impl Middleware for Player {
+    async fn handle(
+        &self,
+        request: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        Ok(self.replay(&RecordedRequest::new(&request))?)
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &Request) -> Self {
+        let url = request.url();
+        let origin = url.origin().ascii_serialization();
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| !SECRET_PARAMETERS.contains(&key.as_ref()))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        query.sort();
+
+        let body = request.body().and_then(|b| b.as_bytes()).map(|bytes| {
+            match serde_json::from_slice::<Value>(bytes) {
+                Ok(mut json) => {
+                    scrub(&mut json, &origin);
+                    json
+                }
+                // Forms, where comments and the names of uploaded files are the only text
+                // students may be named in
+                Err(_) => Value::Object(
+                    url::form_urlencoded::parse(bytes)
+                        .map(|(key, value)| {
+                            let value = if key.contains("comment") || key == "name" {
+                                REDACTED.to_owned()
+                            } else {
+                                value.into_owned()
+                            };
+                            (key.into_owned(), Value::String(value))
+                        })
+                        .collect(),
+                ),
+            }
+        });
+
+        Self {
+            method: request.method().to_string(),
+            path: url.path().to_owned(),
+            query,
+            body,
+        }
+    }
+}
+
+/// Remove students' personal information and credentials from a JSON body, and replace the
+/// Canvas instance's address.
+fn scrub(value: &mut Value, origin: &str) {
+    match value {
+        Value::Object(object) => {
+            let is_user = ["sortable_name", "short_name", "login_id", "display_name"]
+                .iter()
+                .any(|field| object.contains_key(*field));
+            let is_file = object.contains_key("filename");
+            let id = object.get("id").and_then(Value::as_u64).unwrap_or_default();
+            for (key, value) in object.iter_mut() {
+                let key = key.as_str();
+                let personal = PII_FIELDS.contains(&key)
+                    || LINK_FIELDS.contains(&key)
+                    || (is_user && USER_FIELDS.contains(&key));
+                if let (true, Some(name)) =
+                    (is_file && FILENAME_FIELDS.contains(&key), value.as_str())
+                {
+                    *value = Value::String(redact_filename(id, name));
+                } else if personal && value.is_string() {
+                    *value = Value::String(REDACTED.to_owned());
+                } else if key == "url" && value.is_string() {
+                    // Files on Canvas can be replayed, but other URLs, such as those students
+                    // submit, are their own
+                    let url = scrub_text(value.as_str().unwrap_or_default(), origin);
+                    *value = Value::String(if url.starts_with(PLACEHOLDER_URL) {
+                        url
+                    } else {
+                        REDACTED.to_owned()
+                    });
+                } else {
+                    scrub(value, origin);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| scrub(v, origin)),
+        Value::String(text) => *text = scrub_text(text, origin),
+        _ => {}
+    }
+}
+
+/// A name for a file which keeps its extension, so replayed downloads are handled the same
+/// way, but not the name the student gave it.
+fn redact_filename(id: u64, name: &str) -> String {
+    match Path::new(name).extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("file-{id}.{extension}"),
+        None => format!("file-{id}"),
+    }
+}
+
+/// Replace the Canvas instance's address, and remove credentials from URLs in `text`.
+fn scrub_text(text: &str, origin: &str) -> String {
+    let mut text = text.replace(origin, PLACEHOLDER_URL);
+    for parameter in SECRET_PARAMETERS {
+        let key = format!("{parameter}=");
+        let mut start = 0;
+        while let Some(found) = text[start..].find(&key) {
+            let value = start + found + key.len();
+            let end = text[value..]
+                .find(|c: char| matches!(c, '&' | '"' | '>' | '\'' | ' ') || c.is_whitespace())
+                .map_or(text.len(), |e| value + e);
+            text.replace_range(value..end, "redacted");
+            start = value;
+        }
+    }
+    text
+}
@@ -0,0 +1,476 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::{
+    grade_input::{build_comments, PreparedComments},
+    AccessToken, Assignment, AssignmentGroup, AssignmentOverride, AssignmentSelector, Attachment,
+    Cache, Comment, CommentOptions, Config, Course, Enrollment, Grade, GradeInput, GradeUpload,
+    Include, OverrideDates, OverrideTarget, PeerReview, Progress, ProvisionalGrade, ReviewOptions,
+    ReviewerProgress, StudentId, Submission, SubmissionState, TestResult, User,
+};
+
+type Predicate = Arc<dyn Fn(&Submission) -> bool + Send + Sync>;
+
+/// Access to Canvas for other programs, through handles on its courses and assignments.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use canvas_grading::{AccessToken, CanvasClient, Grade, StudentId, Submission};
+///
+/// let client = CanvasClient::new(AccessToken::new("<ACCESS_TOKEN>"), "https://canvas.example.com")?;
+/// let assignment = client.course(1).assignment(10);
+/// let ungraded = assignment
+///     .submissions()
+///     .filter(Submission::ungraded)
+///     .list()
+///     .await?;
+/// let grades: Vec<Grade> = ungraded
+///     .iter()
+///     .map(|s| Grade { user_id: s.user(), grade: 10.0 })
+///     .collect();
+/// assignment.grades().upload(&grades, &[]).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CanvasClient {
+    config: Config,
+}
+
+/// A course, from [`CanvasClient::course`].
+#[derive(Debug, Clone)]
+pub struct CourseHandle {
+    config: Config,
+}
+
+/// An assignment in a course, from [`CourseHandle::assignment`].
+#[derive(Debug, Clone)]
+pub struct AssignmentHandle {
+    config: Config,
+    id: u64,
+}
+
+/// Submissions to one or more assignments, narrowed down before they are requested with
+/// [`SubmissionQuery::list`] or [`SubmissionQuery::count`].
+#[derive(Clone)]
+pub struct SubmissionQuery {
+    config: Config,
+    /// Every assignment in the course if empty
+    assignment_ids: Vec<u64>,
+    include: Vec<Include>,
+    filters: Vec<Predicate>,
+    one_per_group: bool,
+}
+
+/// Grades and comments on an assignment's submissions, from [`AssignmentHandle::grades`].
+#[derive(Debug, Clone)]
+pub struct GradesHandle {
+    config: Config,
+    assignment_id: u64,
+    group_comment: bool,
+    provisional: bool,
+}
+
+impl CanvasClient {
+    /// A client for the Canvas instance at `base_url`, which does not cache responses unless
+    /// given a cache with [`CanvasClient::with_cache`].
+    pub fn new(access_token: AccessToken, base_url: &str) -> Result<Self> {
+        // Handles replace the course id when created
+        Ok(Self::from_config(Config::new(
+            access_token,
+            0,
+            base_url,
+            Cache::disabled(),
+        )?))
+    }
+
+    /// Cache responses in `cache`, such as one in [`Cache::default_directory`] shared with
+    /// the command line tool.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.config.cache = cache;
+        self
+    }
+
+    /// A client using the settings, including the HTTP client, of an existing config.
+    pub fn from_config(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn course(&self, course_id: u64) -> CourseHandle {
+        let mut config = self.config.clone();
+        config.course_id = course_id;
+        CourseHandle { config }
+    }
+
+    /// The user the access token belongs to.
+    pub async fn current_user(&self) -> Result<User> {
+        User::current(&self.config).await
+    }
+
+    pub async fn progress(&self, progress_id: u64) -> Result<Progress> {
+        Progress::get(progress_id, &self.config).await
+    }
+}
+
+impl CourseHandle {
+    pub fn id(&self) -> u64 {
+        self.config.course_id
+    }
+
+    /// Settings for requests about this course, for functions taking a [`Config`].
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub async fn get(&self) -> Result<Course> {
+        Course::get(&self.config).await
+    }
+
+    pub fn assignment(&self, assignment_id: u64) -> AssignmentHandle {
+        AssignmentHandle {
+            config: self.config.clone(),
+            id: assignment_id,
+        }
+    }
+
+    pub async fn assignments(&self) -> Result<Vec<Assignment>> {
+        Assignment::list(&self.config).await
+    }
+
+    pub async fn assignment_groups(&self) -> Result<Vec<AssignmentGroup>> {
+        AssignmentGroup::list(&self.config).await
+    }
+
+    /// The ids of the assignments matched by each selector, in order.
+    pub async fn resolve(&self, selectors: &[AssignmentSelector]) -> Result<Vec<u64>> {
+        AssignmentSelector::resolve(selectors, &self.config).await
+    }
+
+    pub async fn students(&self) -> Result<Vec<Enrollment>> {
+        Enrollment::students(&self.config).await
+    }
+
+    /// Submissions to every assignment in the course, narrowed down with
+    /// [`SubmissionQuery::assignments`].
+    pub fn submissions(&self) -> SubmissionQuery {
+        SubmissionQuery::new(&self.config, vec![])
+    }
+}
+
+impl AssignmentHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Settings for requests about this assignment's course, for functions taking a
+    /// [`Config`].
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The course the assignment is in.
+    pub fn course(&self) -> CourseHandle {
+        CourseHandle {
+            config: self.config.clone(),
+        }
+    }
+
+    pub async fn get(&self) -> Result<Assignment> {
+        Assignment::get(self.id, &self.config).await
+    }
+
+    /// The latest submission of each student.
+    pub fn submissions(&self) -> SubmissionQuery {
+        SubmissionQuery::new(&self.config, vec![self.id])
+    }
+
+    pub fn grades(&self) -> GradesHandle {
+        GradesHandle {
+            config: self.config.clone(),
+            assignment_id: self.id,
+            group_comment: false,
+            provisional: false,
+        }
+    }
+
+    pub async fn overrides(&self) -> Result<Vec<AssignmentOverride>> {
+        AssignmentOverride::list(self.id, &self.config).await
+    }
+
+    /// Give students or a section new dates, as with [`AssignmentOverride::extend`].
+    pub async fn extend(
+        &self,
+        target: &OverrideTarget,
+        title: Option<&str>,
+        dates: &OverrideDates,
+    ) -> Result<Option<AssignmentOverride>> {
+        AssignmentOverride::extend(self.id, target, title, dates, &self.config).await
+    }
+
+    pub async fn peer_reviews(&self) -> Result<Vec<PeerReview>> {
+        PeerReview::list(self.id, &self.config).await
+    }
+
+    /// Assign peer reviews between the students who submitted, as with [`PeerReview::assign`].
+    pub async fn assign_peer_reviews(
+        &self,
+        avoid_assignments: &[u64],
+        options: ReviewOptions,
+        dry_run: bool,
+    ) -> Result<Vec<(u64, u64)>> {
+        PeerReview::assign(self.id, avoid_assignments, options, dry_run, &self.config).await
+    }
+
+    /// How many of their peer reviews each reviewer completed.
+    pub async fn peer_review_progress(&self) -> Result<Vec<ReviewerProgress>> {
+        Ok(ReviewerProgress::of(&self.peer_reviews().await?))
+    }
+
+    /// Whether grades are hidden from students until they are posted.
+    pub async fn set_post_policy(&self, post_manually: bool) -> Result<()> {
+        Assignment::set_post_policy(self.id, post_manually, &self.config).await
+    }
+
+    /// Make grades visible to students, in every section if `section_ids` is empty.
+    pub async fn post_grades(&self, section_ids: &[u64], graded_only: bool) -> Result<Progress> {
+        Assignment::post_grades(self.id, section_ids, graded_only, &self.config).await
+    }
+
+    /// Hide grades from students, in every section if `section_ids` is empty.
+    pub async fn hide_grades(&self, section_ids: &[u64]) -> Result<Progress> {
+        Assignment::hide_grades(self.id, section_ids, &self.config).await
+    }
+
+    pub async fn select_provisional_grade(&self, provisional_grade_id: u64) -> Result<()> {
+        ProvisionalGrade::select(self.id, provisional_grade_id, &self.config).await
+    }
+
+    pub async fn publish_provisional_grades(&self) -> Result<()> {
+        ProvisionalGrade::publish(self.id, &self.config).await
+    }
+}
+
+impl SubmissionQuery {
+    fn new(config: &Config, assignment_ids: Vec<u64>) -> Self {
+        Self {
+            config: config.clone(),
+            assignment_ids,
+            include: vec![],
+            filters: vec![],
+            one_per_group: false,
+        }
+    }
+
+    /// Only submissions to these assignments, sorted in the order given.
+    pub fn assignments(mut self, assignment_ids: &[u64]) -> Self {
+        self.assignment_ids = assignment_ids.to_vec();
+        self
+    }
+
+    /// Request an association along with each submission.
+    pub fn include(mut self, include: Include) -> Self {
+        if !self.include.contains(&include) {
+            self.include.push(include);
+        }
+        self
+    }
+
+    /// Only submissions meeting the predicate, along with any others given.
+    pub fn filter(
+        mut self,
+        predicate: impl Fn(&Submission) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.filters.push(Arc::new(predicate));
+        self
+    }
+
+    /// Only submissions in the state, requesting what its predicate needs.
+    pub fn state(self, state: SubmissionState) -> Self {
+        state
+            .include()
+            .into_iter()
+            .fold(self, Self::include)
+            .filter(state.predicate())
+    }
+
+    /// Keep one submission per group, as with [`Submission::one_per_group`].
+    pub fn one_per_group(mut self) -> Self {
+        self.one_per_group = true;
+        self.include(Include::Group)
+    }
+
+    fn matches(&self, submission: &Submission) -> bool {
+        self.filters.iter().all(|f| f(submission))
+    }
+
+    /// The latest submission of each student to each assignment. Without assignments, every
+    /// submission in the course, including earlier attempts.
+    pub async fn list(&self) -> Result<Vec<Submission>> {
+        let predicate = |s: &Submission| self.matches(s);
+        let submissions = if self.assignment_ids.is_empty() {
+            Submission::course_submissions(&self.include, &self.config)
+                .await?
+                .into_iter()
+                .filter(predicate)
+                .collect()
+        } else {
+            Submission::for_assignments(
+                &self.assignment_ids,
+                &self.include,
+                &predicate,
+                &self.config,
+            )
+            .await?
+        };
+
+        Ok(if self.one_per_group {
+            Submission::one_per_group(submissions)
+        } else {
+            submissions
+        })
+    }
+
+    /// The number of matching submissions to a single assignment, counting every attempt, or
+    /// else the number [`SubmissionQuery::list`] returns.
+    pub async fn count(&self) -> Result<usize> {
+        match self.assignment_ids[..] {
+            [assignment_id] if !self.one_per_group => {
+                Submission::count_submissions(
+                    assignment_id,
+                    &self.include,
+                    &|s| self.matches(s),
+                    &self.config,
+                )
+                .await
+            }
+            _ => Ok(self.list().await?.len()),
+        }
+    }
+}
+
+impl GradesHandle {
+    /// Send each comment to the student's whole group on group assignments.
+    pub fn group_comment(mut self, group_comment: bool) -> Self {
+        self.group_comment = group_comment;
+        self
+    }
+
+    /// Record grades as this grader's provisional grades on a moderated assignment.
+    pub fn provisional(mut self, provisional: bool) -> Self {
+        self.provisional = provisional;
+        self
+    }
+
+    /// Upload grades and text comments, returning the job applying them if there is one.
+    ///
+    /// Provisional grades are given one submission at a time, so are applied on return.
+    pub async fn upload(&self, grades: &[Grade], comments: &[Comment]) -> Result<Option<Progress>> {
+        if !self.provisional {
+            return Submission::update_grades_with_comments(
+                self.assignment_id,
+                grades,
+                comments,
+                self.group_comment,
+                &self.config,
+            )
+            .await;
+        }
+
+        let mut user_ids: Vec<&StudentId> = grades
+            .iter()
+            .map(|g| &g.user_id)
+            .chain(comments.iter().map(|c| &c.user_id))
+            .collect();
+        user_ids.sort();
+        user_ids.dedup();
+        for user_id in user_ids {
+            Submission::update(
+                self.assignment_id,
+                user_id,
+                grades
+                    .iter()
+                    .rfind(|g| &g.user_id == user_id)
+                    .map(|g| g.grade),
+                comments
+                    .iter()
+                    .find(|c| &c.user_id == user_id)
+                    .map(|c| c.comment.as_str()),
+                true,
+                &self.config,
+            )
+            .await?;
+        }
+
+        Ok(None)
+    }
+
+    /// Upload grade input as the `grade` command does. Keys naming groups are replaced with
+    /// their members, each student's comment is rendered from the assignment's template or
+    /// joined from their lines, and comments already posted are skipped or replaced as
+    /// `options` says. Students with attachments get them in a comment of their own.
+    pub async fn submit(
+        mut self,
+        mut input: GradeInput,
+        tests: &HashMap<StudentId, Vec<TestResult>>,
+        options: &CommentOptions,
+    ) -> Result<GradeUpload> {
+        let assignment = Assignment::get(self.assignment_id, &self.config).await?;
+        input.anonymize(&assignment);
+        self.group_comment = input.resolve_groups(&assignment, &self.config).await?;
+        input.check_keys(&assignment)?;
+
+        let comments = build_comments(
+            &assignment,
+            &input.grades,
+            input.comments,
+            tests,
+            options,
+            &self.config,
+        )
+        .await?;
+        let mut prepared =
+            PreparedComments::new(self.assignment_id, comments, options, &self.config).await?;
+
+        for (user_id, attachments) in input
+            .attachments
+            .into_iter()
+            .into_group_map_by(|a| a.user_id.clone())
+        {
+            let comment = prepared.take(&user_id);
+            self.attach(
+                &user_id,
+                comment.as_ref().map(|c| c.comment.as_str()),
+                &attachments,
+            )
+            .await?;
+        }
+
+        let progress = self.upload(&input.grades, &prepared.comments).await?;
+        prepared
+            .delete_replaced(progress, self.assignment_id, &self.config)
+            .await
+    }
+
+    /// Comment on one student's submission with files attached.
+    pub async fn attach(
+        &self,
+        user_id: &StudentId,
+        comment: Option<&str>,
+        attachments: &[Attachment],
+    ) -> Result<()> {
+        Submission::comment_with_attachments(
+            self.assignment_id,
+            user_id,
+            comment,
+            attachments,
+            &self.config,
+        )
+        .await
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{file::CanvasFile, Config, StudentId};
+
+/// Ends the text of comments posted with this tool, so that `--replace-comments` only deletes
+/// those and not ones written in Canvas. U+2063 INVISIBLE SEPARATOR is not displayed.
+const MARKER: char = '\u{2063}';
+
+/// The text of a comment to post, marked as posted with this tool.
+pub(crate) fn mark(text: &str) -> String {
+    format!("{text}{MARKER}")
+}
+
+/// A comment on a submission, from either the student or a grader.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubmissionComment {
+    pub id: u64,
+    /// None if the author's account no longer exists
+    pub author_id: Option<u64>,
+    #[serde(default)]
+    pub author_name: String,
+    pub comment: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub attachments: Vec<CanvasFile>,
+}
+
+impl SubmissionComment {
+    /// Whether the comment was posted with this tool.
+    pub fn is_marked(&self) -> bool {
+        self.comment.trim_end().ends_with(MARKER)
+    }
+
+    /// The comment's text, without the mark of comments posted with this tool.
+    pub fn text(&self) -> &str {
+        self.comment.trim_end().trim_end_matches(MARKER)
+    }
+
+    pub async fn delete(&self, assignment_id: u64, user_id: u64, config: &Config) -> Result<()> {
+        info!("Deleting comment {} on submission of {user_id}", self.id);
+        config
+            .client
+            .delete(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions/{user_id}/comments/{}",
+                config.base_url, config.course_id, self.id
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// The comments on one student's submission, from oldest to newest.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentThread {
+    pub assignment_id: u64,
+    pub user_id: StudentId,
+    pub comments: Vec<SubmissionComment>,
+}
+
+impl CommentThread {
+    /// Header row for [`CommentThread::write_csv`].
+    pub const CSV_HEADER: [&str; 8] = [
+        "assignment_id",
+        "user_id",
+        "comment_id",
+        "author_id",
+        "author_name",
+        "created_at",
+        "comment",
+        "attachments",
+    ];
+
+    /// Whether the student wrote the most recent comment.
+    pub fn unanswered(&self) -> bool {
+        self.comments
+            .last()
+            .is_some_and(|c| c.author_id.is_some() && c.author_id == self.user_id.user_id())
+    }
+
+    /// Whether `author_id` already posted a comment with the same text, ignoring surrounding
+    /// whitespace.
+    pub fn contains(&self, author_id: u64, text: &str) -> bool {
+        self.comments
+            .iter()
+            .any(|c| c.author_id == Some(author_id) && c.text().trim() == text.trim())
+    }
+
+    /// The most recent comment `author_id` posted with this tool.
+    pub fn latest_marked(&self, author_id: u64) -> Option<&SubmissionComment> {
+        self.comments
+            .iter()
+            .rev()
+            .find(|c| c.author_id == Some(author_id) && c.is_marked())
+    }
+
+    /// Write the thread as an indented plain text block headed by the student's id.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", self.user_id);
+        for comment in &self.comments {
+            text.push_str(&format!(
+                "    [{}] {}: {}\n",
+                comment.created_at,
+                comment.author_name,
+                comment.text().replace('\n', "\n        ")
+            ));
+            for attachment in &comment.attachments {
+                text.push_str(&format!(
+                    "        attachment: {} ({})\n",
+                    attachment.filename(),
+                    attachment.url()
+                ));
+            }
+        }
+
+        text
+    }
+
+    /// Write one CSV record per comment, with attachment URLs separated by spaces.
+    pub fn write_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
+        for comment in &self.comments {
+            writer.write_record([
+                self.assignment_id.to_string(),
+                self.user_id.to_string(),
+                comment.id.to_string(),
+                comment.author_id.map(|a| a.to_string()).unwrap_or_default(),
+                comment.author_name.clone(),
+                comment.created_at.clone(),
+                comment.text().to_owned(),
+                comment
+                    .attachments
+                    .iter()
+                    .map(|a| a.url())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ])?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,17 +1,33 @@
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use reqwest::redirect::Policy;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{fs::File, io::Read, path::PathBuf, sync::Arc};
 use tracing::info;
 
-use crate::{create_client, AccessToken, CLI};
+use crate::{
+    autograde::AutogradeConfig, cassette, client_with, AccessToken, Cache, Cassette, GroupConfig,
+    RepositoryConfig, Transport, CLI,
+};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub access_token: AccessToken,
     pub course_id: u64,
     pub base_url: String,
-    pub client: Client,
+    pub client: ClientWithMiddleware,
+    /// Sends the client's requests, and downloads and uploads files
+    pub transport: Arc<dyn Transport>,
+    /// Downloads files, which are not authenticated but are recorded and replayed like
+    /// requests to the API
+    pub(crate) downloads: ClientWithMiddleware,
+    /// Uploads files like `downloads`, but without following redirects
+    pub(crate) uploads: ClientWithMiddleware,
+    pub autograde: AutogradeConfig,
+    pub groups: GroupConfig,
+    pub repositories: RepositoryConfig,
+    pub cache: Cache,
+    cassette: Option<Cassette>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -19,6 +35,12 @@ struct ConfigFile {
     pub access_token: Option<AccessToken>,
     pub course_id: Option<u64>,
     pub base_url: Option<String>,
+    #[serde(default)]
+    pub autograde: AutogradeConfig,
+    #[serde(default)]
+    pub groups: GroupConfig,
+    #[serde(default)]
+    pub repositories: RepositoryConfig,
 }
 
 impl Config {
@@ -31,28 +53,140 @@ impl Config {
         let config_contents = ConfigFile::read_from_file(&config_file_path)?;
         info!("Config File: {:#?}", config_contents);
 
+        let cassette = match (&command_line_options.record, &command_line_options.replay) {
+            (Some(directory), _) => Some(Cassette::Record(directory.clone())),
+            (None, Some(directory)) => Some(Cassette::Replay(directory.clone())),
+            (None, None) => None,
+        };
+        let replaying = matches!(cassette, Some(Cassette::Replay(_)));
+
+        // Replayed responses never reach Canvas, so need neither credentials nor its address
         let access_token = command_line_options
             .access_token
             .clone()
             .map(AccessToken)
             .or(config_contents.access_token)
+            .or(replaying.then(|| AccessToken::new("replay")))
             .ok_or(anyhow!("Access token not configured!"))?;
+        let base_url = command_line_options
+            .base_url
+            .clone()
+            .or(config_contents.base_url)
+            .or(replaying.then(|| cassette::PLACEHOLDER_URL.to_owned()))
+            .ok_or(anyhow!("Base URL not configured!"))?;
+        // Keep replayed responses out of the cache of the real course
+        let cache_directory = if replaying {
+            std::env::temp_dir().join("grading").join("replay")
+        } else {
+            Cache::default_directory()?
+        };
+
+        let transport: Arc<dyn Transport> = Arc::new(reqwest::Client::new());
+        let (client, downloads, uploads) = clients(
+            &access_token,
+            cassette.as_ref(),
+            transport.clone(),
+            upload_transport()?,
+        )?;
+
         Ok(Self {
             access_token: access_token.to_owned(),
             course_id: command_line_options
                 .course_id
                 .or(config_contents.course_id)
                 .ok_or(anyhow!("Course id not configured!"))?,
-            base_url: command_line_options
-                .base_url
-                .clone()
-                .or(config_contents.base_url)
-                .ok_or(anyhow!("Base URL not configured!"))?,
-            client: create_client(access_token)?,
+            base_url,
+            client,
+            downloads,
+            uploads,
+            transport,
+            autograde: config_contents.autograde,
+            groups: config_contents.groups,
+            repositories: config_contents.repositories,
+            cache: Cache::new(&cache_directory, command_line_options.offline),
+            cassette,
         })
     }
 }
 
+impl Config {
+    /// Settings for a course without reading the config file, with default autograding and
+    /// group settings.
+    pub fn new(
+        access_token: AccessToken,
+        course_id: u64,
+        base_url: &str,
+        cache: Cache,
+    ) -> Result<Self> {
+        let transport: Arc<dyn Transport> = Arc::new(reqwest::Client::new());
+        let (client, downloads, uploads) =
+            clients(&access_token, None, transport.clone(), upload_transport()?)?;
+        Ok(Self {
+            client,
+            downloads,
+            uploads,
+            transport,
+            access_token,
+            course_id,
+            base_url: base_url.to_owned(),
+            autograde: AutogradeConfig::default(),
+            groups: GroupConfig::default(),
+            repositories: RepositoryConfig::default(),
+            cache,
+            cassette: None,
+        })
+    }
+
+    /// Send requests and download and upload files with `transport` instead of the default
+    /// [`reqwest::Client`]. Uploads expect it not to follow redirects, as Canvas confirms an
+    /// upload at the URL it redirects to, which needs the access token.
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Result<Self> {
+        (self.client, self.downloads, self.uploads) = clients(
+            &self.access_token,
+            self.cassette.as_ref(),
+            transport.clone(),
+            transport.clone(),
+        )?;
+        self.transport = transport;
+        Ok(self)
+    }
+}
+
+/// The client for the API and the ones for downloads and uploads, which share the cassette so
+/// all are recorded in order.
+fn clients(
+    access_token: &AccessToken,
+    cassette: Option<&Cassette>,
+    transport: Arc<dyn Transport>,
+    upload_transport: Arc<dyn Transport>,
+) -> Result<(
+    ClientWithMiddleware,
+    ClientWithMiddleware,
+    ClientWithMiddleware,
+)> {
+    let cassette = match cassette {
+        Some(cassette) => {
+            info!("Using cassette {:?}", cassette);
+            Some(cassette.middleware()?)
+        }
+        None => None,
+    };
+    Ok((
+        client_with(Some(access_token), cassette.clone(), transport.clone()),
+        client_with(None, cassette.clone(), transport),
+        client_with(None, cassette, upload_transport),
+    ))
+}
+
+/// The default transport for uploads, which leaves redirects for the caller to follow.
+fn upload_transport() -> Result<Arc<dyn Transport>> {
+    Ok(Arc::new(
+        reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()?,
+    ))
+}
+
 impl ConfigFile {
     pub fn read_from_file(path: &PathBuf) -> Result<Self> {
         let config_file = File::open(path);
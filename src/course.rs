@@ -0,0 +1,24 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{api::get, Config};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Course {
+    pub id: u64,
+    pub name: String,
+    /// Whether totals weight each assignment group by its `group_weight`
+    #[serde(default)]
+    pub apply_assignment_group_weights: bool,
+}
+
+impl Course {
+    pub async fn get(config: &Config) -> Result<Self> {
+        get(
+            &format!("{}/api/v1/courses/{}", config.base_url, config.course_id),
+            config,
+        )
+        .await
+        .with_context(|| format!("Unable to get course {}", config.course_id))
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{api::get_paginated, Config};
+
+/// A student's enrollment in one section of the course.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Enrollment {
+    pub user_id: u64,
+    pub course_section_id: Option<u64>,
+}
+
+impl Enrollment {
+    /// Active student enrollments in the course, one per student and section.
+    pub async fn students(config: &Config) -> Result<Vec<Self>> {
+        get_paginated(
+            &format!(
+                "{}/api/v1/courses/{}/enrollments",
+                config.base_url, config.course_id
+            ),
+            &[
+                ("type[]", "StudentEnrollment".to_owned()),
+                ("state[]", "active".to_owned()),
+            ],
+            config,
+        )
+        .await
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use tracing::{info, warn};
+
+pub const DEFAULT_MAX_BYTES: u64 = 512 * 1024 * 1024;
+pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Directory macOS adds to zip archives to hold resource forks.
+const MACOS_METADATA: &str = "__MACOSX";
+
+/// Limits and layout options for extracting archives.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractOptions {
+    /// Maximum total size of the extracted files, guarding against zip bombs
+    pub max_bytes: u64,
+    /// Maximum number of entries in the archive
+    pub max_entries: usize,
+    /// Move the contents of a single top-level folder up into the extraction directory
+    pub flatten: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            flatten: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// Detect the format of an archive from its file name, returning the name without the
+    /// archive extension.
+    pub fn detect(file_name: &str) -> Option<(Self, &str)> {
+        let lowercase = file_name.to_lowercase();
+        [
+            (".zip", Self::Zip),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar", Self::Tar),
+        ]
+        .into_iter()
+        .find(|(extension, _)| lowercase.ends_with(extension))
+        .map(|(extension, format)| (format, &file_name[..file_name.len() - extension.len()]))
+    }
+}
+
+/// Extract an archive into a sibling directory named after it without its extension,
+/// replacing any previous extraction.
+///
+/// Returns `None` if the file is not a recognized archive. Archives with entries escaping the
+/// extraction directory or exceeding the configured limits are rejected.
+pub fn extract_archive(path: &Path, options: &ExtractOptions) -> Result<Option<PathBuf>> {
+    let Some((format, stem)) = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(ArchiveFormat::detect)
+        .filter(|(_, stem)| !stem.is_empty())
+    else {
+        return Ok(None);
+    };
+    let destination = path.with_file_name(stem);
+    if destination.exists() {
+        std::fs::remove_dir_all(&destination)?;
+    }
+
+    info!(
+        "Extracting {:?} archive {} to {}",
+        format,
+        path.to_str().unwrap(),
+        destination.to_str().unwrap()
+    );
+    let file = File::open(path)?;
+    std::fs::create_dir_all(&destination)?;
+    let mut extractor = Extractor::new(&destination, options);
+    let result = match format {
+        ArchiveFormat::Zip => extractor.zip(file),
+        ArchiveFormat::Tar => extractor.tar(file),
+        ArchiveFormat::TarGz => extractor.tar(GzDecoder::new(file)),
+    }
+    .and_then(|_| {
+        if options.flatten {
+            flatten(&destination)
+        } else {
+            Ok(())
+        }
+    });
+
+    if let Err(e) = result {
+        // Do not leave a partial extraction behind
+        let _ = std::fs::remove_dir_all(&destination);
+        return Err(e.context(format!(
+            "Unable to extract {}",
+            path.to_str().unwrap_or_default()
+        )));
+    }
+
+    Ok(Some(destination))
+}
+
+/// Writes archive entries into a destination while enforcing [`ExtractOptions`] limits.
+struct Extractor<'a> {
+    destination: &'a Path,
+    options: &'a ExtractOptions,
+    entries: usize,
+    bytes: u64,
+}
+
+impl<'a> Extractor<'a> {
+    fn new(destination: &'a Path, options: &'a ExtractOptions) -> Self {
+        Self {
+            destination,
+            options,
+            entries: 0,
+            bytes: 0,
+        }
+    }
+
+    fn zip(&mut self, file: File) -> Result<()> {
+        let mut archive = zip::ZipArchive::new(file).context("Invalid zip archive")?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let name = PathBuf::from(entry.name());
+            if entry.is_symlink() {
+                warn!("Skipping symbolic link {:?}", name);
+                continue;
+            }
+
+            let executable = entry.unix_mode().is_some_and(|m| m & 0o111 != 0);
+            if entry.is_dir() {
+                self.directory(&name)?;
+            } else {
+                self.file(&name, &mut entry, executable)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tar(&mut self, reader: impl Read) -> Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().context("Invalid tar archive")? {
+            let mut entry = entry?;
+            let name = entry.path()?.into_owned();
+            let entry_type = entry.header().entry_type();
+
+            if entry_type.is_dir() {
+                self.directory(&name)?;
+            } else if entry_type.is_file() {
+                let executable = entry.header().mode().is_ok_and(|m| m & 0o111 != 0);
+                self.file(&name, &mut entry, executable)?;
+            } else {
+                warn!("Skipping {:?} entry {:?}", entry_type, name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn directory(&mut self, name: &Path) -> Result<()> {
+        if let Some(path) = self.entry_path(name)? {
+            std::fs::create_dir_all(path)?;
+        }
+
+        Ok(())
+    }
+
+    fn file(&mut self, name: &Path, contents: &mut impl Read, executable: bool) -> Result<()> {
+        let Some(path) = self.entry_path(name)? else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Read at most one byte past the limit to detect archives exceeding it, regardless
+        // of the sizes the archive claims for its entries
+        let remaining = self.options.max_bytes.saturating_sub(self.bytes);
+        let mut file = File::create(&path)?;
+        let written = std::io::copy(&mut contents.take(remaining.saturating_add(1)), &mut file)?;
+        file.flush()?;
+        if written > remaining {
+            bail!(
+                "Archive expands to more than the {} byte limit",
+                self.options.max_bytes
+            );
+        }
+        self.bytes += written;
+
+        #[cfg(unix)]
+        if executable {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+
+        Ok(())
+    }
+
+    /// Where an entry should be written, or `None` if it should be skipped.
+    fn entry_path(&mut self, name: &Path) -> Result<Option<PathBuf>> {
+        self.entries += 1;
+        if self.entries > self.options.max_entries {
+            bail!(
+                "Archive has more than the {} entry limit",
+                self.options.max_entries
+            );
+        }
+
+        let mut relative = PathBuf::new();
+        for component in name.components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {}
+                _ => bail!("Archive entry {:?} escapes the extraction directory", name),
+            }
+        }
+
+        if relative.as_os_str().is_empty() || relative.starts_with(MACOS_METADATA) {
+            return Ok(None);
+        }
+
+        Ok(Some(self.destination.join(relative)))
+    }
+}
+
+/// If `directory` only contains a single folder, replace it with that folder's contents.
+fn flatten(directory: &Path) -> Result<()> {
+    let entries = std::fs::read_dir(directory)?.collect::<Result<Vec<_>, _>>()?;
+    let [entry] = &entries[..] else {
+        return Ok(());
+    };
+    if !entry.file_type()?.is_dir() {
+        return Ok(());
+    }
+
+    let mut temporary = directory.as_os_str().to_owned();
+    temporary.push(".flatten");
+    std::fs::rename(entry.path(), &temporary)?;
+    std::fs::remove_dir(directory)?;
+    std::fs::rename(&temporary, directory)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::TempDir;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    /// A zip archive in `directory` with the given files.
+    fn zip(directory: &Path, files: &[(&str, &[u8])]) -> PathBuf {
+        let path = directory.join("submission.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    /// A tar archive in `directory` with the given entries, whose names are not checked.
+    fn tar(directory: &Path, entries: &[(&str, tar::EntryType, &[u8])]) -> PathBuf {
+        let path = directory.join("submission.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        for (name, entry_type, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *contents).unwrap();
+        }
+        builder.finish().unwrap();
+        path
+    }
+
+    fn limits(max_bytes: u64, max_entries: usize) -> ExtractOptions {
+        ExtractOptions {
+            max_bytes,
+            max_entries,
+            flatten: false,
+        }
+    }
+
+    #[test]
+    fn archives_are_extracted_beside_themselves() {
+        let directory = TempDir::new().unwrap();
+        let path = zip(directory.path(), &[("src/main.rs", b"fn main() {}")]);
+
+        let extracted = extract_archive(&path, &ExtractOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(extracted, directory.path().join("submission"));
+        assert_eq!(
+            std::fs::read(extracted.join("src/main.rs")).unwrap(),
+            b"fn main() {}"
+        );
+    }
+
+    #[test]
+    fn entries_escaping_the_directory_are_rejected() {
+        let directory = TempDir::new().unwrap();
+        for path in [
+            zip(directory.path(), &[("../escaped.txt", b"outside")]),
+            tar(
+                directory.path(),
+                &[("src/../../escaped.txt", tar::EntryType::Regular, b"outside")],
+            ),
+        ] {
+            assert!(extract_archive(&path, &ExtractOptions::default()).is_err());
+            assert!(!directory.path().join("escaped.txt").exists());
+            assert!(!directory.path().join("submission").exists());
+        }
+    }
+
+    #[test]
+    fn symbolic_links_are_skipped() {
+        let directory = TempDir::new().unwrap();
+        let path = directory.path().join("submission.zip");
+        let mut writer = ZipWriter::new(File::create(&path).unwrap());
+        writer
+            .add_symlink("config", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("main.rs", SimpleFileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+        let tar = tar(
+            directory.path(),
+            &[
+                ("config", tar::EntryType::Symlink, b""),
+                ("main.rs", tar::EntryType::Regular, b""),
+            ],
+        );
+
+        for path in [path, tar] {
+            let extracted = extract_archive(&path, &ExtractOptions::default())
+                .unwrap()
+                .unwrap();
+            assert!(extracted.join("main.rs").exists());
+            assert!(std::fs::symlink_metadata(extracted.join("config")).is_err());
+        }
+    }
+
+    #[test]
+    fn archives_expanding_past_the_byte_limit_are_rejected() {
+        let directory = TempDir::new().unwrap();
+        // Compresses to a few kilobytes
+        let zeros = vec![0; 1024 * 1024];
+        let path = zip(directory.path(), &[("a", &zeros), ("b", &zeros)]);
+
+        let error = extract_archive(&path, &limits(1024 * 1024 + 1, 10)).unwrap_err();
+        assert!(format!("{error:#}").contains("byte limit"));
+        assert!(!directory.path().join("submission").exists());
+
+        assert!(extract_archive(&path, &limits(2 * 1024 * 1024, 10)).is_ok());
+        assert!(extract_archive(&path, &limits(u64::MAX, 10)).is_ok());
+    }
+
+    #[test]
+    fn archives_with_too_many_entries_are_rejected() {
+        let directory = TempDir::new().unwrap();
+        let files = ["0", "1", "2", "3", "4"].map(|name| (name, &b""[..]));
+        let path = zip(directory.path(), &files);
+
+        let error = extract_archive(&path, &limits(DEFAULT_MAX_BYTES, 4)).unwrap_err();
+        assert!(format!("{error:#}").contains("entry limit"));
+        assert!(extract_archive(&path, &limits(DEFAULT_MAX_BYTES, 5)).is_ok());
+    }
+}
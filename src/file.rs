@@ -1,14 +1,17 @@
-use std::{io::Cursor, path::Path};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::Submission;
+use crate::{Config, StudentId, Submission};
 
 #[derive(Debug, Clone)]
 pub struct FileSubmission {
-    user_id: u64,
+    user_id: StudentId,
     assignment_id: u64,
     attempt: u64,
     file: CanvasFile,
@@ -37,7 +40,7 @@ impl FileSubmission {
         }
     }
 
-    pub async fn download(&self, directiory: &Path) -> Result<()> {
+    pub async fn download(&self, directiory: &Path, config: &Config) -> Result<PathBuf> {
         let path = directiory.join(self.to_string());
 
         info!(
@@ -45,11 +48,49 @@ impl FileSubmission {
             self.file.url(),
             path.to_str().unwrap()
         );
-        self.file.download(&path).await
+        self.file.download(&path, config).await?;
+
+        Ok(path)
+    }
+
+    pub fn filename(&self) -> &str {
+        self.file.filename()
+    }
+
+    /// Download to `path`, ignoring the naming scheme used by [`FileSubmission::download`].
+    pub async fn download_as(&self, path: &Path, config: &Config) -> Result<()> {
+        info!(
+            "Downloading \"{}\" to {}",
+            self.file.url(),
+            path.to_str().unwrap()
+        );
+        self.file.download(path, config).await
+    }
+
+    /// Path of the file within a directory per student and attempt, e.g. `1_2/attempt_3/file`.
+    pub fn attempt_path(&self, directory: &Path) -> PathBuf {
+        directory
+            .join(format!("{}_{}", self.user_id, self.assignment_id))
+            .join(format!("attempt_{}", self.attempt))
+            .join(self.file.filename())
+    }
+
+    /// Download into the file's [`FileSubmission::attempt_path`], returning that path.
+    pub async fn download_attempt(&self, directory: &Path, config: &Config) -> Result<PathBuf> {
+        let path = self.attempt_path(directory);
+
+        info!(
+            "Downloading \"{}\" to {}",
+            self.file.url(),
+            path.to_str().unwrap()
+        );
+        self.file.download(&path, config).await?;
+
+        Ok(path)
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CanvasFile {
     url: String,
     filename: String,
@@ -64,8 +105,15 @@ impl CanvasFile {
         &self.filename
     }
 
-    pub async fn download(&self, path: &Path) -> Result<()> {
-        let response = reqwest::get(&self.url).await?;
+    /// Download with the config's transport, through its cassette if recording or replaying.
+    /// File URLs carry their own verifier, so the request is not authenticated or cached.
+    pub async fn download(&self, path: &Path, config: &Config) -> Result<()> {
+        let response = config
+            .downloads
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?;
 
         // Create parent directories
         std::fs::create_dir_all(
@@ -0,0 +1,469 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::BufRead,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+
+use crate::{
+    Assignment, Attachment, Comment, CommentTemplate, CommentThread, Config, Grade, Group, Include,
+    Progress, StudentId, Submission, SubmissionComment, TemplateContext, TestResult, User,
+};
+
+/// Grades, comments and comment attachments for one assignment, as read by the `grade`
+/// command.
+#[derive(Debug, Clone, Default)]
+pub struct GradeInput {
+    pub grades: Vec<Grade>,
+    pub comments: Vec<Comment>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// How to handle comments already posted on a submission when uploading.
+#[derive(Debug, Clone, Default)]
+pub struct CommentOptions {
+    /// Post comments even if the same one was already posted by the current user
+    pub allow_duplicates: bool,
+    /// Delete the last comment the current user posted with this tool on each submission
+    /// once the new one is posted
+    pub replace: bool,
+    /// Render each student's comment from this template instead of the assignment's default
+    /// one, if there is one
+    pub template: Option<PathBuf>,
+}
+
+/// What [`GradesHandle::submit`] uploaded, and which comments it left out.
+///
+/// [`GradesHandle::submit`]: crate::GradesHandle::submit
+#[derive(Debug, Default)]
+pub struct GradeUpload {
+    /// The job applying the grades and comments, if it was not waited for
+    pub progress: Option<Progress>,
+    /// Students whose comment was identical to one already posted
+    pub duplicates: Vec<StudentId>,
+    /// Anonymous students whose previous comment could not be replaced
+    pub not_replaced: Vec<StudentId>,
+}
+
+impl GradeInput {
+    /// Read grade input, where each line may start with `<ASSIGNMENT_ID>/` to give the grade,
+    /// comment or attachment for one of several assignments. Lines without one are for the
+    /// only assignment.
+    pub fn parse(reader: impl BufRead, assignment_ids: &[u64]) -> Result<Vec<(u64, Self)>> {
+        let mut input: Vec<(u64, Self)> = assignment_ids
+            .iter()
+            .map(|&id| (id, Self::default()))
+            .collect();
+        for line in reader.lines().map_while(Result::ok) {
+            let line = line.trim();
+            let (assignment_id, line) = match line
+                .split_once('/')
+                .and_then(|(id, rest)| Some((id.parse::<u64>().ok()?, rest)))
+            {
+                Some((id, rest)) => (Some(id), rest),
+                None => (None, line),
+            };
+            let entry = match assignment_id {
+                Some(id) => input.iter_mut().find(|(a, _)| *a == id).with_context(|| {
+                    format!("Grade input for assignment {id}, which was not given")
+                })?,
+                None => match &mut input[..] {
+                    [only] => only,
+                    _ => bail!("Grade input line \"{line}\" needs an <ASSIGNMENT_ID>/ prefix"),
+                },
+            };
+
+            let input = &mut entry.1;
+            if line.is_empty() {
+                continue;
+            } else if let Ok(grade) = Grade::from_str(line) {
+                input.grades.push(grade);
+            } else if let Ok(attachment) = Attachment::from_str(line) {
+                input.attachments.push(attachment);
+            } else {
+                let comment = Comment::from_str(line)
+                    .with_context(|| format!("Unable to parse grade input line \"{line}\""))?;
+                input.comments.push(comment);
+            }
+        }
+
+        Ok(input)
+    }
+
+    /// Join each student's comment lines into a single comment, as uploaded without a
+    /// template.
+    pub fn join_comments(&mut self) {
+        self.comments = join_comments(std::mem::take(&mut self.comments));
+    }
+
+    fn keys_mut(&mut self) -> impl Iterator<Item = &mut StudentId> {
+        self.grades
+            .iter_mut()
+            .map(|g| &mut g.user_id)
+            .chain(self.comments.iter_mut().map(|c| &mut c.user_id))
+            .chain(self.attachments.iter_mut().map(|a| &mut a.user_id))
+    }
+
+    /// Treat keys as anonymous ids on anonymously graded assignments, as ones consisting of
+    /// only digits are parsed as user ids.
+    pub(crate) fn anonymize(&mut self, assignment: &Assignment) {
+        if assignment.anonymous_grading {
+            for user_id in self.keys_mut() {
+                *user_id = user_id.clone().into_anonymous();
+            }
+        }
+    }
+
+    /// Fail on the first key which is not a student of the assignment, such as a group which
+    /// was not found.
+    pub(crate) fn check_keys(&self, assignment: &Assignment) -> Result<()> {
+        let mut keys = self
+            .grades
+            .iter()
+            .map(|g| &g.user_id)
+            .chain(self.comments.iter().map(|c| &c.user_id))
+            .chain(self.attachments.iter().map(|a| &a.user_id));
+        if let Some(key) = keys.find(|k| match k {
+            StudentId::User(_) => false,
+            StudentId::Anonymous(_) => !assignment.anonymous_grading,
+            StudentId::Group(_) => true,
+        }) {
+            bail!("Unknown student or group \"{key}\"");
+        }
+
+        Ok(())
+    }
+
+    /// On group assignments, replace keys of the form `group:<ID>` or `group:<NAME>` with the
+    /// group's members.
+    ///
+    /// With `fan_out` configured, every member is graded explicitly, and keys of students in
+    /// a group also expand to the whole group. Otherwise only the group's first member is
+    /// graded, and Canvas applies the grade to the group unless the assignment grades
+    /// students individually. Returns whether comments should be sent to the whole group.
+    pub(crate) async fn resolve_groups(
+        &mut self,
+        assignment: &Assignment,
+        config: &Config,
+    ) -> Result<bool> {
+        let Some(category_id) = assignment.group_category_id else {
+            return Ok(false);
+        };
+        let groups = Group::in_category(category_id, config).await?;
+        let fan_out = config.groups.fan_out;
+
+        let members = |key: &StudentId| -> Result<Option<Vec<StudentId>>> {
+            let group = match key.user_id() {
+                Some(id) if groups.iter().any(|g| g.contains(id)) => {
+                    if !fan_out {
+                        return Ok(None);
+                    }
+                    groups.iter().find(|g| g.contains(id))
+                }
+                _ => groups.iter().find(|g| g.matches(key)),
+            };
+            let Some(group) = group else {
+                return Ok(None);
+            };
+            if group.members.is_empty() {
+                bail!("Group \"{}\" has no members", group.name);
+            }
+
+            let count = if fan_out { group.members.len() } else { 1 };
+            Ok(Some(
+                group.members[..count]
+                    .iter()
+                    .map(|&id| StudentId::User(id))
+                    .collect(),
+            ))
+        };
+
+        self.grades = expand(
+            std::mem::take(&mut self.grades),
+            |g| &mut g.user_id,
+            &members,
+        )?;
+        self.comments = expand(
+            std::mem::take(&mut self.comments),
+            |c| &mut c.user_id,
+            &members,
+        )?;
+        self.attachments = expand(
+            std::mem::take(&mut self.attachments),
+            |a| &mut a.user_id,
+            &members,
+        )?;
+
+        Ok(!fan_out && !assignment.grade_group_students_individually)
+    }
+}
+
+/// Copy each item to every student its key expands to.
+fn expand<T: Clone>(
+    items: Vec<T>,
+    key: fn(&mut T) -> &mut StudentId,
+    members: &dyn Fn(&StudentId) -> Result<Option<Vec<StudentId>>>,
+) -> Result<Vec<T>> {
+    let mut expanded = Vec::new();
+    for mut item in items {
+        match members(key(&mut item))? {
+            Some(user_ids) => {
+                for user_id in user_ids {
+                    let mut item = item.clone();
+                    *key(&mut item) = user_id;
+                    expanded.push(item);
+                }
+            }
+            None => expanded.push(item),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Join each student's comments into one, a line each.
+fn join_comments(comments: Vec<Comment>) -> Vec<Comment> {
+    comments
+        .into_iter()
+        .sorted_by(|a, b| a.user_id.cmp(&b.user_id))
+        .chunk_by(|c| c.user_id.clone())
+        .into_iter()
+        .map(|(user_id, comments)| Comment {
+            user_id,
+            comment: comments.map(|c| c.comment + "\n").collect(),
+        })
+        .collect()
+}
+
+/// Render each student's comment from the assignment's template if there is one, or else
+/// join their comment lines.
+pub(crate) async fn build_comments(
+    assignment: &Assignment,
+    grades: &[Grade],
+    comments: Vec<Comment>,
+    tests: &HashMap<StudentId, Vec<TestResult>>,
+    options: &CommentOptions,
+    config: &Config,
+) -> Result<Vec<Comment>> {
+    let Some(template) = CommentTemplate::find(assignment.id, options.template.as_deref())? else {
+        return Ok(join_comments(comments));
+    };
+
+    let submissions: HashMap<StudentId, Submission> =
+        Submission::for_assignments(&[assignment.id], &[Include::User], &|_| true, config)
+            .await?
+            .into_iter()
+            .map(|s| (s.user(), s))
+            .collect();
+    let mut lines = comments
+        .into_iter()
+        .into_group_map_by(|c| c.user_id.clone());
+    let user_ids: BTreeSet<StudentId> = grades
+        .iter()
+        .map(|g| g.user_id.clone())
+        .chain(lines.keys().cloned())
+        .chain(tests.keys().cloned())
+        .collect();
+
+    user_ids
+        .into_iter()
+        .map(|user_id| {
+            let submission = submissions.get(&user_id);
+            let context = TemplateContext {
+                user_id: user_id.clone(),
+                name: submission
+                    .and_then(Submission::student_name)
+                    .map(str::to_owned),
+                score: grades
+                    .iter()
+                    .rfind(|g| g.user_id == user_id)
+                    .map(|g| g.grade)
+                    .or(submission.and_then(Submission::score)),
+                points_possible: assignment.points_possible,
+                late_deduction: submission.and_then(Submission::points_deducted),
+                comments: lines
+                    .remove(&user_id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|c| c.comment)
+                    .collect(),
+                tests: tests.get(&user_id).cloned().unwrap_or_default(),
+            };
+
+            Ok(Comment {
+                user_id,
+                comment: template.render(&context)?,
+            })
+        })
+        .collect()
+}
+
+/// Comments ready to upload, and the earlier comments they replace.
+pub(crate) struct PreparedComments {
+    pub comments: Vec<Comment>,
+    /// Comments to delete once their replacements are posted, with the user id of the
+    /// submission they are on
+    replaced: Vec<(u64, SubmissionComment)>,
+    pub duplicates: Vec<StudentId>,
+    pub not_replaced: Vec<StudentId>,
+}
+
+impl PreparedComments {
+    /// Drop comments identical to one the current user already posted on the submission, so
+    /// re-running an upload does not repeat feedback. With `replace`, the previous comment
+    /// the current user posted with this tool on each remaining submission is replaced.
+    pub async fn new(
+        assignment_id: u64,
+        comments: Vec<Comment>,
+        options: &CommentOptions,
+        config: &Config,
+    ) -> Result<Self> {
+        let mut prepared = Self {
+            comments: Vec::new(),
+            replaced: Vec::new(),
+            duplicates: Vec::new(),
+            not_replaced: Vec::new(),
+        };
+        if comments.is_empty() || (options.allow_duplicates && !options.replace) {
+            prepared.comments = comments;
+            return Ok(prepared);
+        }
+
+        let me = User::current(config).await?;
+        let threads: HashMap<StudentId, CommentThread> = Submission::for_assignments(
+            &[assignment_id],
+            &[Include::SubmissionComments],
+            &|_| true,
+            config,
+        )
+        .await?
+        .iter()
+        .map(|s| (s.user(), s.comment_thread()))
+        .collect();
+
+        for comment in comments {
+            let Some(thread) = threads.get(&comment.user_id) else {
+                prepared.comments.push(comment);
+                continue;
+            };
+            if !options.allow_duplicates && thread.contains(me.id, &comment.comment) {
+                prepared.duplicates.push(comment.user_id);
+                continue;
+            }
+            if options.replace {
+                match (thread.latest_marked(me.id), comment.user_id.user_id()) {
+                    (Some(previous), Some(user_id)) => {
+                        prepared.replaced.push((user_id, previous.clone()))
+                    }
+                    (Some(_), None) => prepared.not_replaced.push(comment.user_id.clone()),
+                    (None, _) => {}
+                }
+            }
+            prepared.comments.push(comment);
+        }
+
+        Ok(prepared)
+    }
+
+    /// The comment for a student, taken out to be posted with their attachments instead.
+    pub fn take(&mut self, user_id: &StudentId) -> Option<Comment> {
+        let index = self.comments.iter().position(|c| &c.user_id == user_id)?;
+        Some(self.comments.remove(index))
+    }
+
+    /// Delete the replaced comments once `progress`, the upload posting their replacements,
+    /// has completed, returning it if it was not waited for.
+    pub async fn delete_replaced(
+        self,
+        progress: Option<Progress>,
+        assignment_id: u64,
+        config: &Config,
+    ) -> Result<GradeUpload> {
+        let mut progress = progress;
+        if !self.replaced.is_empty() {
+            if let Some(progress) = progress.take() {
+                progress.wait(config).await?;
+            }
+            for (user_id, comment) in self.replaced {
+                comment.delete(assignment_id, user_id, config).await?;
+            }
+        }
+
+        Ok(GradeUpload {
+            progress,
+            duplicates: self.duplicates,
+            not_replaced: self.not_replaced,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str, assignment_ids: &[u64]) -> Result<Vec<(u64, GradeInput)>> {
+        GradeInput::parse(input.as_bytes(), assignment_ids)
+    }
+
+    #[test]
+    fn lines_are_grades_attachments_or_comments() {
+        let input = parse(
+            "101: 9.5\n\n101: attach: notes.pdf\n102: Well done: see the notes\n",
+            &[10],
+        )
+        .unwrap();
+
+        let [(10, input)] = &input[..] else {
+            panic!("expected input for assignment 10");
+        };
+        assert_eq!(input.grades[0].user_id, StudentId::User(101));
+        assert_eq!(input.grades[0].grade, 9.5);
+        assert_eq!(input.attachments[0].path, PathBuf::from("notes.pdf"));
+        assert_eq!(input.comments[0].comment, "Well done: see the notes");
+    }
+
+    #[test]
+    fn prefixes_name_one_of_several_assignments() {
+        let input = parse("10/101: 7\n11/101: 8\n11/102: Late\n", &[10, 11]).unwrap();
+        let counts: Vec<(u64, usize, usize)> = input
+            .iter()
+            .map(|(id, i)| (*id, i.grades.len(), i.comments.len()))
+            .collect();
+        assert_eq!(counts, [(10, 1, 0), (11, 1, 1)]);
+
+        let error = parse("101: 7\n", &[10, 11]).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("needs an <ASSIGNMENT_ID>/ prefix"));
+        let error = parse("12/101: 7\n", &[10, 11]).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("assignment 12, which was not given"));
+        assert!(parse("No student here\n", &[10]).is_err());
+    }
+
+    #[test]
+    fn comment_lines_are_joined_per_student() {
+        let mut input = parse("102: First\n101: Only\n102: Second\n", &[10])
+            .unwrap()
+            .remove(0)
+            .1;
+        input.join_comments();
+
+        let comments: Vec<(String, &str)> = input
+            .comments
+            .iter()
+            .map(|c| (c.user_id.to_string(), c.comment.as_str()))
+            .collect();
+        assert_eq!(
+            comments,
+            [
+                ("101".to_owned(), "Only\n"),
+                ("102".to_owned(), "First\nSecond\n")
+            ]
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use rust_xlsxwriter::{Color, Format, Workbook};
+use serde::{Deserialize, Serialize};
+
+use crate::{api::get_paginated, Assignment, Config, Submission};
+
+/// A group of assignments, weighted in students' totals if the course applies weights.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssignmentGroup {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub position: u64,
+    /// Percentage of the total
+    #[serde(default)]
+    pub group_weight: f32,
+}
+
+impl AssignmentGroup {
+    pub async fn list(config: &Config) -> Result<Vec<Self>> {
+        get_paginated(
+            &format!(
+                "{}/api/v1/courses/{}/assignment_groups",
+                config.base_url, config.course_id
+            ),
+            &[],
+            config,
+        )
+        .await
+    }
+}
+
+/// Every student's score on every published assignment, with assignment group and total
+/// percentages.
+///
+/// Like Canvas' current score, percentages only count graded assignments, and assignment
+/// group drop rules are not applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gradebook {
+    pub weighted: bool,
+    pub groups: Vec<GradebookGroup>,
+    pub assignments: Vec<GradebookAssignment>,
+    pub students: Vec<StudentGrades>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GradebookGroup {
+    pub id: u64,
+    pub name: String,
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GradebookAssignment {
+    pub id: u64,
+    pub name: String,
+    pub group_id: Option<u64>,
+    pub points_possible: Option<f32>,
+    /// Whether the assignment counts towards totals
+    pub counted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StudentGrades {
+    pub user_id: u64,
+    pub name: String,
+    /// One per assignment, in the order of [`Gradebook::assignments`]
+    pub cells: Vec<GradebookCell>,
+    /// Percentage in each group, in the order of [`Gradebook::groups`]
+    pub groups: Vec<Option<f32>>,
+    pub total: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GradebookCell {
+    pub score: Option<f32>,
+    pub late: bool,
+    pub missing: bool,
+}
+
+/// A cell of the exported table.
+enum Value {
+    Id(u64),
+    Number(f32),
+    Text(String),
+    Empty,
+}
+
+impl Gradebook {
+    pub fn new(
+        groups: &[AssignmentGroup],
+        assignments: &[Assignment],
+        submissions: &[Submission],
+        weighted: bool,
+    ) -> Self {
+        let groups: Vec<&AssignmentGroup> = groups
+            .iter()
+            .sorted_by_key(|g| (g.position, g.id))
+            .collect();
+        let group_position = |id: Option<u64>| {
+            id.and_then(|id| groups.iter().position(|g| g.id == id))
+                .unwrap_or(usize::MAX)
+        };
+        let assignments: Vec<GradebookAssignment> = assignments
+            .iter()
+            .filter(|a| a.published)
+            .sorted_by_key(|a| (group_position(a.assignment_group_id), a.position, a.id))
+            .map(|a| GradebookAssignment {
+                id: a.id,
+                name: a.name.clone(),
+                group_id: a.assignment_group_id,
+                points_possible: a.points_possible,
+                counted: !a.omit_from_final_grade && a.points_possible.is_some_and(|p| p > 0.0),
+            })
+            .collect();
+        let columns: HashMap<u64, usize> = assignments
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (a.id, i))
+            .collect();
+
+        let mut students: HashMap<u64, (String, String, Vec<GradebookCell>)> = HashMap::new();
+        for submission in submissions {
+            let (Some(user_id), Some(&column)) = (
+                submission.user().user_id(),
+                columns.get(&submission.assignment()),
+            ) else {
+                continue;
+            };
+            let (_, _, cells) = students.entry(user_id).or_insert_with(|| {
+                let student = submission.student();
+                let name = student.map_or(user_id.to_string(), |s| s.name.clone());
+                let sortable_name = student
+                    .and_then(|s| s.sortable_name.clone())
+                    .unwrap_or_else(|| name.clone());
+                (
+                    sortable_name,
+                    name,
+                    vec![GradebookCell::default(); assignments.len()],
+                )
+            });
+            cells[column] = GradebookCell {
+                score: submission.score().filter(|_| submission.graded()),
+                late: submission.late(),
+                missing: submission.missing(),
+            };
+        }
+
+        let students = students
+            .into_iter()
+            .sorted_by(|(_, (a, ..)), (_, (b, ..))| a.cmp(b))
+            .map(|(user_id, (_, name, cells))| {
+                let percentage = |in_group: &dyn Fn(&GradebookAssignment) -> bool| {
+                    let (score, points) = assignments
+                        .iter()
+                        .zip(&cells)
+                        .filter(|(a, _)| a.counted && in_group(a))
+                        .filter_map(|(a, c)| Some((c.score?, a.points_possible?)))
+                        .fold((0.0, 0.0), |(s, p), (score, points)| {
+                            (s + score, p + points)
+                        });
+                    (points > 0.0).then(|| 100.0 * score / points)
+                };
+
+                let group_percentages: Vec<Option<f32>> = groups
+                    .iter()
+                    .map(|g| percentage(&|a| a.group_id == Some(g.id)))
+                    .collect();
+                let total = if weighted {
+                    // Groups without graded work are left out and the other weights scaled up
+                    let (sum, weights) = groups
+                        .iter()
+                        .zip(&group_percentages)
+                        .filter_map(|(g, p)| Some((p.as_ref()? * g.group_weight, g.group_weight)))
+                        .fold((0.0, 0.0), |(s, w), (score, weight)| {
+                            (s + score, w + weight)
+                        });
+                    (weights > 0.0).then(|| sum / weights)
+                } else {
+                    percentage(&|_| true)
+                };
+
+                StudentGrades {
+                    user_id,
+                    name,
+                    cells,
+                    groups: group_percentages,
+                    total,
+                }
+            })
+            .collect();
+
+        Self {
+            weighted,
+            groups: groups
+                .iter()
+                .map(|g| GradebookGroup {
+                    id: g.id,
+                    name: g.name.clone(),
+                    weight: g.group_weight,
+                })
+                .collect(),
+            assignments,
+            students,
+        }
+    }
+
+    /// The table exported to CSV and XLSX: one row per student, with a score and a late or
+    /// missing flag column for each assignment, followed by group and total percentages.
+    fn table(&self) -> (Vec<String>, Vec<Vec<Value>>) {
+        let mut header = vec!["user_id".to_owned(), "name".to_owned()];
+        for assignment in &self.assignments {
+            header.push(format!("{} ({})", assignment.name, assignment.id));
+            header.push(format!("{} ({}) flag", assignment.name, assignment.id));
+        }
+        header.extend(self.groups.iter().map(|g| format!("{} %", g.name)));
+        header.push("Total %".to_owned());
+
+        let percentage = |p: &Option<f32>| match p {
+            Some(p) => Value::Number((p * 100.0).round() / 100.0),
+            None => Value::Empty,
+        };
+        let rows = self
+            .students
+            .iter()
+            .map(|student| {
+                let mut row = vec![
+                    Value::Id(student.user_id),
+                    Value::Text(student.name.clone()),
+                ];
+                for cell in &student.cells {
+                    row.push(cell.score.map_or(Value::Empty, Value::Number));
+                    row.push(match (cell.missing, cell.late) {
+                        (true, _) => Value::Text("missing".to_owned()),
+                        (_, true) => Value::Text("late".to_owned()),
+                        _ => Value::Empty,
+                    });
+                }
+                row.extend(student.groups.iter().map(percentage));
+                row.push(percentage(&student.total));
+                row
+            })
+            .collect();
+
+        (header, rows)
+    }
+
+    pub fn write_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
+        let (header, rows) = self.table();
+        writer.write_record(&header)?;
+        for row in rows {
+            writer.write_record(row.iter().map(|value| match value {
+                Value::Id(id) => id.to_string(),
+                Value::Number(n) => n.to_string(),
+                Value::Text(text) => text.clone(),
+                Value::Empty => String::new(),
+            }))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a spreadsheet with the header and names frozen, and late and missing flags
+    /// highlighted.
+    pub fn write_xlsx(&self, path: &Path) -> Result<()> {
+        let (header, rows) = self.table();
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet().set_name("Gradebook")?;
+        let bold = Format::new().set_bold();
+        let late = Format::new().set_background_color(Color::RGB(0xFFF2CC));
+        let missing = Format::new().set_background_color(Color::RGB(0xF4CCCC));
+
+        for (column, title) in header.iter().enumerate() {
+            sheet.write_string_with_format(0, column as u16, title, &bold)?;
+        }
+        for (row, values) in rows.iter().enumerate() {
+            let row = row as u32 + 1;
+            for (column, value) in values.iter().enumerate() {
+                let column = column as u16;
+                match value {
+                    Value::Id(id) => sheet.write_number(row, column, *id as f64)?,
+                    Value::Number(n) => sheet.write_number(row, column, *n as f64)?,
+                    Value::Text(text) if text == "late" => {
+                        sheet.write_string_with_format(row, column, text, &late)?
+                    }
+                    Value::Text(text) if text == "missing" => {
+                        sheet.write_string_with_format(row, column, text, &missing)?
+                    }
+                    Value::Text(text) => sheet.write_string(row, column, text)?,
+                    Value::Empty => sheet,
+                };
+            }
+        }
+        sheet.set_freeze_panes(1, 2)?;
+
+        workbook
+            .save(path)
+            .with_context(|| format!("Unable to write gradebook to {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as Json};
+
+    use super::*;
+
+    const DUE: &str = "2025-02-01T00:00:00Z";
+
+    fn groups() -> Vec<AssignmentGroup> {
+        serde_json::from_value(json!([
+            { "id": 3, "name": "Projects", "position": 3, "group_weight": 50.0 },
+            { "id": 1, "name": "Labs", "position": 1, "group_weight": 25.0 },
+            { "id": 2, "name": "Exams", "position": 2, "group_weight": 75.0 },
+        ]))
+        .unwrap()
+    }
+
+    fn assignment(id: u64, name: &str, group: u64, points: f32, extra: Json) -> Json {
+        let mut assignment = json!({
+            "id": id,
+            "name": name,
+            "assignment_group_id": group,
+            "points_possible": points,
+            "position": id,
+            "published": true,
+        });
+        assignment
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().cloned().unwrap_or_default());
+        assignment
+    }
+
+    fn assignments() -> Vec<Assignment> {
+        serde_json::from_value(json!([
+            assignment(20, "Midterm", 2, 50.0, json!({})),
+            assignment(10, "Lab 1", 1, 10.0, json!({})),
+            assignment(
+                11,
+                "Lab 2",
+                1,
+                10.0,
+                json!({ "omit_from_final_grade": true })
+            ),
+            assignment(12, "Attendance", 1, 0.0, json!({})),
+            assignment(30, "Project", 3, 100.0, json!({})),
+            assignment(40, "Final", 2, 20.0, json!({ "published": false })),
+        ]))
+        .unwrap()
+    }
+
+    /// Ada's graded submission, or a missing one if there is no score.
+    fn submission(assignment_id: u64, score: Option<f32>, submitted_at: &str) -> Json {
+        json!({
+            "id": assignment_id * 10,
+            "user_id": 1,
+            "assignment_id": assignment_id,
+            "user": { "id": 1, "name": "Ada Lovelace", "sortable_name": "Lovelace, Ada" },
+            "grader_id": score.map(|_| 900),
+            "score": score,
+            "workflow_state": if score.is_some() { "graded" } else { "unsubmitted" },
+            "attachments": score.map(|_| json!([])),
+            "submitted_at": score.map(|_| submitted_at),
+            "cached_due_date": DUE,
+        })
+    }
+
+    fn submissions() -> Vec<Submission> {
+        serde_json::from_value(json!([
+            submission(10, Some(8.0), "2025-02-02T00:00:00Z"),
+            submission(11, Some(2.0), DUE),
+            submission(12, Some(1.0), DUE),
+            submission(20, Some(30.0), DUE),
+            submission(30, None, DUE),
+            submission(40, Some(0.0), DUE),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn unpublished_and_omitted_assignments_do_not_count() {
+        let gradebook = Gradebook::new(&groups(), &assignments(), &submissions(), false);
+
+        let columns: Vec<(u64, bool)> = gradebook
+            .assignments
+            .iter()
+            .map(|a| (a.id, a.counted))
+            .collect();
+        assert_eq!(
+            columns,
+            [(10, true), (11, false), (12, false), (20, true), (30, true)]
+        );
+        let student = &gradebook.students[0];
+        assert_eq!(student.groups, [Some(80.0), Some(60.0), None]);
+        // Lab 1 and the midterm, without the unpublished final's zero
+        assert_eq!(student.total, Some(100.0 * 38.0 / 60.0));
+    }
+
+    #[test]
+    fn weights_of_groups_without_graded_work_are_redistributed() {
+        let gradebook = Gradebook::new(&groups(), &assignments(), &submissions(), true);
+
+        let weights: Vec<f32> = gradebook.groups.iter().map(|g| g.weight).collect();
+        assert_eq!(weights, [25.0, 75.0, 50.0]);
+        // Projects are left out rather than counted as zero, which would give 43.33
+        assert_eq!(gradebook.students[0].total, Some(65.0));
+
+        let ungraded: Vec<Submission> = submissions()
+            .into_iter()
+            .filter(|s| s.assignment() == 30)
+            .collect();
+        let gradebook = Gradebook::new(&groups(), &assignments(), &ungraded, true);
+        assert_eq!(gradebook.students[0].total, None);
+    }
+
+    #[test]
+    fn csv_has_a_score_and_flag_column_per_assignment() {
+        let gradebook = Gradebook::new(&groups(), &assignments(), &submissions(), true);
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        gradebook.write_csv(&mut writer).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            csv,
+            "user_id,name,Lab 1 (10),Lab 1 (10) flag,Lab 2 (11),Lab 2 (11) flag,\
+             Attendance (12),Attendance (12) flag,Midterm (20),Midterm (20) flag,\
+             Project (30),Project (30) flag,Labs %,Exams %,Projects %,Total %\n\
+             1,Ada Lovelace,8,late,2,,1,,30,,,missing,80,60,,65\n"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{api::get_paginated, Config, StudentId, User};
+
+/// The `[groups]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// Grade and comment on every member of a group explicitly, instead of one member with
+    /// Canvas applying the grade to the group unless the assignment grades students
+    /// individually
+    pub fan_out: bool,
+}
+
+/// A group of students who submit together on group assignments.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Group {
+    pub id: u64,
+    pub name: String,
+    /// User ids of the members, oldest membership first
+    #[serde(skip)]
+    pub members: Vec<u64>,
+}
+
+impl Group {
+    /// Every group in a group category, with their members.
+    pub async fn in_category(category_id: u64, config: &Config) -> Result<Vec<Self>> {
+        let mut groups: Vec<Self> = get_paginated(
+            &format!(
+                "{}/api/v1/group_categories/{category_id}/groups",
+                config.base_url
+            ),
+            &[],
+            config,
+        )
+        .await?;
+
+        for group in &mut groups {
+            info!("Fetching members of group \"{}\"", group.name);
+            let members: Vec<User> = get_paginated(
+                &format!("{}/api/v1/groups/{}/users", config.base_url, group.id),
+                &[],
+                config,
+            )
+            .await?;
+            group.members = members.into_iter().map(|u| u.id).collect();
+        }
+
+        Ok(groups)
+    }
+
+    /// Whether a `group:<ID>` or `group:<NAME>` key in grade input refers to this group.
+    pub fn matches(&self, key: &StudentId) -> bool {
+        match key {
+            StudentId::Group(key) => *key == self.id.to_string() || *key == self.name,
+            StudentId::User(_) | StudentId::Anonymous(_) => false,
+        }
+    }
+
+    pub fn contains(&self, user_id: u64) -> bool {
+        self.members.contains(&user_id)
+    }
+}
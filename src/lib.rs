@@ -1,19 +1,90 @@
-use std::str::FromStr;
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
-use reqwest::Client;
+use reqwest_middleware::{ClientWithMiddleware, Middleware};
 use serde::{Deserialize, Serialize};
 use tracing::info;
+use transport::TransportMiddleware;
 
+mod api;
+mod assignment;
+mod autograde;
+mod cache;
+mod cassette;
+mod client;
+mod comments;
 mod config;
+mod course;
+mod enrollment;
+mod extract;
 mod file;
+mod grade_input;
+mod gradebook;
+mod group;
+mod moderation;
+mod overrides;
+mod peer_review;
+mod progress;
+mod repository;
+mod sandbox;
+mod selector;
+mod session;
+mod similarity;
+mod spec;
+mod stats;
 mod submission;
+mod template;
+mod transport;
+mod tui;
+mod upload;
+mod user;
 
+pub use assignment::Assignment;
+pub use autograde::{
+    parse_output, AutogradeConfig, AutogradeResult, Autograder, Outcome, ResourceUsage, TestResult,
+};
+pub use cache::Cache;
+pub use cassette::Cassette;
+pub use client::{AssignmentHandle, CanvasClient, CourseHandle, GradesHandle, SubmissionQuery};
+pub use comments::{CommentThread, SubmissionComment};
 pub use config::Config;
+pub use course::Course;
+pub use enrollment::Enrollment;
+pub use extract::{extract_archive, ArchiveFormat, ExtractOptions};
 pub use file::FileSubmission;
-pub use submission::Submission;
+pub use grade_input::{CommentOptions, GradeInput, GradeUpload};
+pub use gradebook::{
+    AssignmentGroup, Gradebook, GradebookAssignment, GradebookCell, GradebookGroup, StudentGrades,
+};
+pub use group::{Group, GroupConfig};
+pub use moderation::ProvisionalGrade;
+pub use overrides::{
+    parse_date, Accommodation, AssignmentOverride, Extension, OverrideDates, OverrideTarget,
+};
+pub use peer_review::{assign_reviewers, PeerReview, ReviewOptions, ReviewerProgress};
+pub use progress::Progress;
+pub use repository::{Manifest, ManifestEntry, RepositoryConfig, RepositorySubmission};
+pub use sandbox::SandboxConfig;
+pub use selector::AssignmentSelector;
+pub use session::{GradingSession, SessionEntry};
+pub use similarity::{
+    tokenize, winnow, Fingerprint, Language, Match, Pair, SimilarityOptions, SimilarityReport,
+    Span, Token,
+};
+pub use spec::{AssignmentRef, AssignmentSpec, Criterion, Rating, SectionDates};
+pub use stats::{Bin, GradeStats, ScoreSummary, SectionStats};
+pub use submission::{Include, Submission};
+pub use template::{markdown_to_text, CommentTemplate, TemplateContext};
+pub use transport::{MemoryTransport, Transport};
+pub use tui::GradingTui;
+pub use upload::upload_comment_file;
+pub use user::User;
 
 /// A struct representing an access token for Canvas. Hides its value from Debug.
 #[derive(Serialize, Deserialize, Clone)]
@@ -26,13 +97,17 @@ impl std::fmt::Debug for AccessToken {
 }
 
 impl AccessToken {
+    pub fn new(secret: &str) -> Self {
+        Self(secret.to_owned())
+    }
+
     pub fn secret(&self) -> &str {
         &self.0
     }
 }
 
 #[derive(Parser, Clone, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_precedence_over_arg = true)]
 pub struct CLI {
     /// Override the Canvas access token from config.
     /// Either this or the option in config MUST BE SET
@@ -49,6 +124,20 @@ pub struct CLI {
     #[arg(long, short)]
     pub base_url: Option<String>,
 
+    /// Read Canvas data from the local cache instead of requesting it
+    #[arg(long, conflicts_with_all = ["record", "replay"])]
+    pub offline: bool,
+
+    /// Save each request to Canvas and its response in <DIR>, without access tokens or
+    /// students' personal information, for reproducing problems with `--replay`
+    #[arg(long, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer requests to Canvas with the responses saved in <DIR> by `--record`, without
+    /// a connection. The access token and base URL are not needed
+    #[arg(long, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+
     /// Generate shell completion
     #[arg(long)]
     generate: Option<Shell>,
@@ -56,8 +145,12 @@ pub struct CLI {
     #[command(subcommand)]
     pub command: Command,
 
-    /// Assignment ID in Canvas
-    pub assignment_id: u64,
+    /// Assignment IDs in Canvas or names of assignment specs, ranges of IDs such as
+    /// `100-110`, or `group:<ID>` and `module:<ID>` for every assignment in an assignment group
+    /// or module. Separated by spaces or commas. Required by every command except `gradebook`
+    /// and `cache clear`
+    #[arg(value_name = "ASSIGNMENT_ID", value_delimiter = ',')]
+    pub assignments: Vec<AssignmentSelector>,
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -65,22 +158,468 @@ pub enum Command {
     /// Read in a results file, parse it and output the result
     Debug,
     /// Download submissions meeting a predicate and print the paths to standard output
-    #[command(subcommand)]
-    Submissions(SubmissionState),
-    /// Upload grades and comments from file
-    Grade,
+    Submissions(SubmissionsArgs),
+    /// Upload grades, comments and comment attachments from file
+    Grade(GradeArgs),
     /// Count the number of submissions meeting a predicate
     #[command(subcommand)]
     Count(SubmissionState),
+    /// Show each student's attempts with their scores and graders
+    History,
+    /// Download submissions meeting a predicate and grade them with the configured test command
+    Autograde(AutogradeArgs),
+    /// Compare the source code of all submissions and report pairs of similar submissions
+    Similarity(SimilarityArgs),
+    /// Print or export the comment thread on each submission
+    Comments(CommentsArgs),
+    /// Export every student's scores on every assignment with weighted totals
+    Gradebook(GradebookArgs),
+    /// Print score statistics, a histogram and submission counts for each section
+    Stats(StatsArgs),
+    /// Show or set whether grades are posted automatically or manually
+    PostingPolicy(PostingPolicyArgs),
+    /// Post grades so students can see them
+    PostGrades(PostGradesArgs),
+    /// Hide grades from students until they are posted
+    HideGrades(HideGradesArgs),
+    /// Review, select and publish provisional grades on a moderated assignment
+    #[command(subcommand)]
+    Moderate(ModerateCommand),
+    /// List, assign and report on peer reviews
+    #[command(subcommand)]
+    PeerReviews(PeerReviewCommand),
+    /// Give students or sections extended due dates, and list existing extensions
+    #[command(subcommand)]
+    Extend(ExtendCommand),
+    /// Grade submissions meeting a predicate by hand in an interactive terminal UI
+    Tui(TuiArgs),
+    /// Refresh or clear the local cache of Canvas data used by `--offline`
+    #[command(subcommand)]
+    Cache(CacheCommand),
+    /// Manage the assignment from a spec file
+    #[command(subcommand)]
+    Assignment(AssignmentCommand),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct TuiArgs {
+    /// Upload the saved grades and comments which have not been uploaded yet, without
+    /// opening the UI
+    #[arg(long)]
+    pub upload: bool,
+
+    /// Which submissions to grade, `ungraded` if not given
+    #[command(subcommand)]
+    pub state: Option<SubmissionState>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum CacheCommand {
+    /// Download the course's assignments and users, and the assignment's submissions
+    Refresh,
+    /// Delete everything cached
+    Clear,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum AssignmentCommand {
+    /// Create or update the assignment in Canvas to match its spec, printing the changes first
+    Apply(ApplyArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ApplyArgs {
+    /// Only print the changes
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ExtendCommand {
+    /// List the assignment's overrides with the students or section and dates of each
+    List,
+    /// Give students new dates
+    Students(ExtendStudentsArgs),
+    /// Give everyone in a section new dates
+    Section(ExtendSectionArgs),
+    /// Give students extensions read from a CSV file with `student,extension` columns
+    Accommodations(AccommodationsArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ExtendStudentsArgs {
+    /// Canvas user ids of the students
+    #[arg(required = true)]
+    pub user_ids: Vec<u64>,
+    #[command(flatten)]
+    pub dates: DateArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ExtendSectionArgs {
+    pub section_id: u64,
+    #[command(flatten)]
+    pub dates: DateArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct DateArgs {
+    /// New due date, as `YYYY-MM-DD HH:MM` in local time or RFC 3339
+    #[arg(long, value_parser = parse_date, required_unless_present = "by")]
+    pub due: Option<chrono::DateTime<chrono::Utc>>,
+    /// Extend the assignment's dates by a time such as `2d` or `36h`, or a factor such as `1.5x`
+    #[arg(long, conflicts_with = "due")]
+    pub by: Option<Extension>,
+    /// New lock date, after which submissions are no longer accepted
+    #[arg(long, value_parser = parse_date)]
+    pub lock: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl DateArgs {
+    pub fn resolve(&self, assignment: &Assignment) -> Result<OverrideDates> {
+        let mut dates = match self.by {
+            Some(extension) => extension
+                .apply(&OverrideDates::of(assignment))
+                .with_context(|| format!("Unable to extend assignment {}", assignment.id))?,
+            None => OverrideDates {
+                unlock_at: None,
+                due_at: self.due,
+                lock_at: assignment.lock_at,
+            },
+        };
+        if self.lock.is_some() {
+            dates.lock_at = self.lock;
+        }
+        if let (Some(due_at), Some(lock_at)) = (dates.due_at, dates.lock_at) {
+            if lock_at < due_at {
+                bail!(
+                    "Lock date {lock_at} is before due date {due_at}, set a later one with --lock"
+                );
+            }
+        }
+
+        Ok(dates)
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AccommodationsArgs {
+    /// CSV file with a `student` column of user ids and an `extension` column such as `1.5x`
+    pub file: PathBuf,
+    /// Apply the accommodations to every assignment in the course with a due date
+    #[arg(long)]
+    pub all_assignments: bool,
 }
 
 #[derive(Subcommand, Clone, Debug)]
+pub enum PeerReviewCommand {
+    /// List each peer review with its reviewer and status
+    List,
+    /// Assign reviewers to every submission
+    Assign(AssignReviewsArgs),
+    /// Print each reviewer's share of completed reviews in the `grade` input format
+    Report(ReviewReportArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AssignReviewsArgs {
+    /// Number of reviews each student should do and receive
+    #[arg(long, short = 'n', default_value_t = 2)]
+    pub count: usize,
+
+    /// Allow pairing students from different sections
+    #[arg(long)]
+    pub any_section: bool,
+
+    /// Avoid pairing students who reviewed each other on another assignment, can be repeated
+    #[arg(long, value_name = "ASSIGNMENT_ID")]
+    pub avoid: Vec<u64>,
+
+    /// Seed for shuffling students, for reproducible assignments
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Print the assignments without creating them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ReviewReportArgs {
+    /// Points given for completing every assigned review
+    #[arg(long, default_value_t = 100.0)]
+    pub points: f32,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ModerateCommand {
+    /// List the provisional grades given to each student
+    List,
+    /// Select provisional grades as the students' final grades
+    Select(SelectArgs),
+    /// Publish the selected grades, which can only be done once
+    Publish,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SelectArgs {
+    /// Provisional grades to select
+    #[arg(required_unless_present = "grader")]
+    pub provisional_grade_ids: Vec<u64>,
+
+    /// Select the provisional grade given by this grader for every student
+    #[arg(long, conflicts_with = "provisional_grade_ids")]
+    pub grader: Option<u64>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct GradeArgs {
+    /// Post grades of graded students once the upload completes
+    #[arg(long)]
+    pub post_after: bool,
+
+    /// Upload the grades as your provisional grades on a moderated assignment
+    #[arg(long, conflicts_with = "post_after")]
+    pub provisional: bool,
+
+    #[command(flatten)]
+    pub comment: CommentArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct PostingPolicyArgs {
+    /// Policy to set, printing the current policy if not given
+    #[arg(value_enum)]
+    pub policy: Option<PostingPolicy>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostingPolicy {
+    /// Grades are visible to students as soon as they are entered
+    Automatic,
+    /// Grades stay hidden until they are posted
+    Manual,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct PostGradesArgs {
+    /// Only post grades for students in a section, can be repeated
+    #[arg(long = "section", value_name = "SECTION_ID")]
+    pub sections: Vec<u64>,
+
+    /// Only post grades for students who have been graded
+    #[arg(long)]
+    pub graded_only: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct HideGradesArgs {
+    /// Only hide grades for students in a section, can be repeated
+    #[arg(long = "section", value_name = "SECTION_ID")]
+    pub sections: Vec<u64>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CommentsArgs {
+    /// Format to write the comments in
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: OutputFormat,
+
+    /// File to write the comments to instead of standard output
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Only include submissions meeting a predicate
+    #[command(subcommand)]
+    pub state: Option<SubmissionState>,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct StatsArgs {
+    /// Format to write the statistics in
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: OutputFormat,
+
+    /// File to write the statistics to instead of standard output
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Number of histogram bins between zero and the points possible
+    #[arg(long, default_value_t = 10)]
+    pub bins: usize,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct GradebookArgs {
+    /// Format to write the gradebook in
+    #[arg(long, short, value_enum, default_value_t)]
+    pub format: GradebookFormat,
+
+    /// File to write the gradebook to instead of standard output, required for XLSX
+    #[arg(long, short, required_if_eq("format", "xlsx"))]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GradebookFormat {
+    #[default]
+    Csv,
+    Json,
+    Xlsx,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+/// How to handle comments already posted on a submission when uploading.
+#[derive(Args, Clone, Debug)]
+pub struct CommentArgs {
+    /// Post comments even if you already posted an identical comment on the submission
+    #[arg(long)]
+    pub allow_duplicate_comments: bool,
+
+    /// Delete the last comment you posted with this tool on each submission once the new one
+    /// is posted
+    #[arg(long)]
+    pub replace_comments: bool,
+
+    /// Render each student's comment from a template file instead of joining their comment
+    /// lines [default: templates/<ASSIGNMENT_ID>.toml in the config directory, if it exists]
+    #[arg(long)]
+    pub template: Option<PathBuf>,
+}
+
+impl CommentArgs {
+    pub fn options(&self) -> CommentOptions {
+        CommentOptions {
+            allow_duplicates: self.allow_duplicate_comments,
+            replace: self.replace_comments,
+            template: self.template.clone(),
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SimilarityArgs {
+    /// Directory of starter code, matches against which are ignored
+    #[arg(long)]
+    pub starter: Option<PathBuf>,
+
+    /// Directory to write the `similarity.json` and `similarity.html` reports to
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Number of tokens in each fingerprinted sequence
+    #[arg(long, short, default_value_t = similarity::DEFAULT_K)]
+    pub k: usize,
+
+    /// Number of consecutive sequences each fingerprint is selected from
+    #[arg(long, short, default_value_t = similarity::DEFAULT_WINDOW)]
+    pub window: usize,
+
+    /// Minimum fraction of shared fingerprints for a pair to be reported
+    #[arg(long, short, default_value_t = 0.1)]
+    pub threshold: f32,
+
+    #[command(flatten)]
+    pub extract: ExtractArgs,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct AutogradeArgs {
+    /// Override the command from config, run with `sh -c` in each student's directory
+    #[arg(long)]
+    pub command: Option<String>,
+
+    /// Override the seconds before the command is killed from config
+    #[arg(long)]
+    pub timeout: Option<u64>,
+
+    /// Run the command in a sandbox without network access or write access outside of the
+    /// student's directory, even if not enabled in config. Linux only
+    #[arg(long)]
+    pub sandbox: bool,
+
+    /// Upload the grades and comments instead of printing them in the `grade` input format
+    #[arg(long)]
+    pub upload: bool,
+
+    #[command(flatten)]
+    pub comment: CommentArgs,
+
+    #[command(flatten)]
+    pub extract: ExtractArgs,
+
+    #[command(subcommand)]
+    pub state: SubmissionState,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SubmissionsArgs {
+    /// Check out submitted git repositories at their last commit before the submission time
+    #[arg(long)]
+    pub at_submission_time: bool,
+
+    /// Download the files of every attempt into attempt-numbered directories
+    #[arg(long)]
+    pub all_attempts: bool,
+
+    #[command(flatten)]
+    pub extract: ExtractArgs,
+
+    #[command(subcommand)]
+    pub state: SubmissionState,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ExtractArgs {
+    /// Extract downloaded zip and tar archives into a sibling directory
+    #[arg(long)]
+    pub extract: bool,
+
+    /// Move the contents of an extracted archive's single top-level folder up a level
+    #[arg(long, requires = "extract")]
+    pub flatten: bool,
+
+    /// Maximum total size in bytes of the files extracted from an archive
+    #[arg(long, default_value_t = extract::DEFAULT_MAX_BYTES)]
+    pub max_extract_bytes: u64,
+
+    /// Maximum number of entries in an extracted archive
+    #[arg(long, default_value_t = extract::DEFAULT_MAX_ENTRIES)]
+    pub max_extract_entries: usize,
+}
+
+impl ExtractArgs {
+    /// Options for extracting archives, or `None` if extraction was not requested.
+    pub fn options(&self) -> Option<ExtractOptions> {
+        self.extract.then_some(ExtractOptions {
+            max_bytes: self.max_extract_bytes,
+            max_entries: self.max_extract_entries,
+            flatten: self.flatten,
+        })
+    }
+}
+
+#[derive(Subcommand, Clone, Debug, Default)]
 pub enum SubmissionState {
     Unsubmitted,
     Submitted,
+    #[default]
     Ungraded,
     Graded,
     GradeNot100,
+    /// The student wrote the most recent submission comment
+    UnansweredComment,
+    /// Submitted after the student's due date, including any extension they were given
+    Late,
+    /// Not submitted by the student's due date, including any extension they were given
+    Missing,
 }
 
 impl SubmissionState {
@@ -91,19 +630,88 @@ impl SubmissionState {
             SubmissionState::Ungraded => Submission::ungraded,
             SubmissionState::Graded => Submission::graded,
             SubmissionState::GradeNot100 => Submission::grade_not_100,
+            SubmissionState::UnansweredComment => Submission::unanswered_comment,
+            SubmissionState::Late => Submission::late,
+            SubmissionState::Missing => Submission::missing,
+        }
+    }
+
+    /// Associations which must be requested with submissions for the predicate to work.
+    pub fn include(&self) -> Vec<Include> {
+        match self {
+            SubmissionState::UnansweredComment => vec![Include::SubmissionComments],
+            _ => vec![],
+        }
+    }
+}
+
+/// Identifies a student: their Canvas user id, or their anonymous id on assignments graded
+/// anonymously, where Canvas hides user ids.
+///
+/// Grade input may also name a group on group assignments as `group:<ID>` or `group:<NAME>`,
+/// which is resolved to the group's members before grading.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StudentId {
+    User(u64),
+    Anonymous(String),
+    /// A group's id or name, as given after `group:`
+    #[serde(skip)]
+    Group(String),
+}
+
+impl StudentId {
+    pub fn user_id(&self) -> Option<u64> {
+        match self {
+            StudentId::User(id) => Some(*id),
+            StudentId::Anonymous(_) | StudentId::Group(_) => None,
+        }
+    }
+
+    /// Treat the id as an anonymous id, as anonymous ids can consist of only digits.
+    pub fn into_anonymous(self) -> Self {
+        match self {
+            StudentId::User(id) => StudentId::Anonymous(id.to_string()),
+            anonymous => anonymous,
         }
     }
 }
 
-impl Default for SubmissionState {
-    fn default() -> Self {
-        Self::Ungraded
+impl std::fmt::Display for StudentId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StudentId::User(id) => write!(f, "{id}"),
+            StudentId::Anonymous(id) => write!(f, "{id}"),
+            StudentId::Group(key) => write!(f, "group:{key}"),
+        }
     }
 }
 
-#[derive(Debug)]
+impl FromStr for StudentId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(group) = s.strip_prefix("group:") {
+            if group.trim().is_empty() {
+                bail!("Missing group id or name in \"{s}\"");
+            }
+            return Ok(StudentId::Group(group.trim().to_owned()));
+        }
+        // Anonymous ids are short strings of letters and digits
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("Invalid student id \"{s}\"");
+        }
+
+        Ok(s.parse()
+            .map(StudentId::User)
+            .unwrap_or_else(|_| StudentId::Anonymous(s.to_owned())))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Grade {
-    pub user_id: u64,
+    pub user_id: StudentId,
     pub grade: f32,
 }
 
@@ -118,15 +726,15 @@ impl FromStr for Grade {
         let grade = parts.next().context("Unable to parse grade from stdin.")?;
 
         Ok(Self {
-            user_id: user_id.parse().context("Unable to parse user id to u64")?,
+            user_id: user_id.parse()?,
             grade: grade.parse().context("Unable to parse grade to f32")?,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Comment {
-    pub user_id: u64,
+    pub user_id: StudentId,
     pub comment: String,
 }
 
@@ -139,27 +747,79 @@ impl FromStr for Comment {
             .context("Unable to parse comment line.")?;
 
         Ok(Self {
-            user_id: user_id.parse().context("Unable to parse user id to u64")?,
+            user_id: user_id.parse()?,
             comment: comment.to_string(),
         })
     }
 }
 
-pub fn create_client(auth_token: AccessToken) -> Result<Client> {
-    info!("Building application reqwest client...");
-    info!("Setting auth header...");
-    let mut auth_bearer: reqwest::header::HeaderValue = ("Bearer ".to_owned()
-        + auth_token.secret())
-    .try_into()
-    .unwrap();
-    auth_bearer.set_sensitive(true);
-    info!("Auth header set!");
+/// A file to attach to a comment on a student's submission, given in the `grade` input as
+/// `<CANVAS_USER_ID>: attach: <PATH>`.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub user_id: StudentId,
+    pub path: PathBuf,
+}
+
+impl FromStr for Attachment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (user_id, path) = s
+            .split_once(": attach: ")
+            .context("Unable to parse attachment line.")?;
 
+        Ok(Self {
+            user_id: user_id.parse()?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// A client authenticated with `auth_token`, sending requests with `transport`, and recording
+/// them to or replaying them from `cassette` if given.
+pub fn create_client(
+    auth_token: AccessToken,
+    cassette: Option<&Cassette>,
+    transport: Arc<dyn Transport>,
+) -> Result<ClientWithMiddleware> {
+    let cassette = cassette.map(Cassette::middleware).transpose()?;
+    Ok(client_with(Some(&auth_token), cassette, transport))
+}
+
+/// A client sending requests with `transport` through the cassette's middleware, if given.
+/// Without `auth_token` requests are not authenticated, as for file downloads, whose URLs
+/// carry their own verifier.
+pub(crate) fn client_with(
+    auth_token: Option<&AccessToken>,
+    cassette: Option<Arc<dyn Middleware>>,
+    transport: Arc<dyn Transport>,
+) -> ClientWithMiddleware {
+    info!("Building application reqwest client...");
     let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(reqwest::header::AUTHORIZATION, auth_bearer);
-    headers.insert("per_page", 100.into());
+    if let Some(auth_token) = auth_token {
+        info!("Setting auth header...");
+        let mut auth_bearer: reqwest::header::HeaderValue = ("Bearer ".to_owned()
+            + auth_token.secret())
+        .try_into()
+        .unwrap();
+        auth_bearer.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, auth_bearer);
+        headers.insert("per_page", 100.into());
+        info!("Auth header set!");
+    }
+
+    // reqwest_middleware needs a reqwest::Client to build requests with, but never sends
+    // them through it, as the transport's middleware does not call the next one. Building a
+    // client sets up TLS and a connection pool, so every client shares one which stays unused
+    static REQUEST_BUILDER: OnceLock<reqwest::Client> = OnceLock::new();
+    let request_builder = REQUEST_BUILDER.get_or_init(reqwest::Client::new).clone();
+    let mut builder = reqwest_middleware::ClientBuilder::new(request_builder);
+    if let Some(cassette) = cassette {
+        builder = builder.with_arc(cassette);
+    }
 
-    Ok(reqwest::ClientBuilder::new()
-        .default_headers(headers)
-        .build()?)
+    builder
+        .with(TransportMiddleware { transport, headers })
+        .build()
 }
@@ -1,10 +1,22 @@
-use std::{fs::File, str::FromStr, sync::Mutex};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::File,
+    sync::Mutex,
+};
 
-use anyhow::{anyhow, Result};
-use canvas_grading::{Command, Comment, Config, Grade, Submission, CLI};
+use anyhow::{anyhow, bail, Context, Result};
+use canvas_grading::{
+    extract_archive, Accommodation, ApplyArgs, AssignmentCommand, AssignmentHandle, AssignmentRef,
+    AssignmentSelector, AssignmentSpec, AutogradeResult, Autograder, CacheCommand, CanvasClient,
+    Command, CommentThread, Config, CourseHandle, ExtendCommand, GradeArgs, GradeInput, GradeStats,
+    GradeUpload, Gradebook, GradebookArgs, GradebookFormat, GradingSession, GradingTui, Group,
+    Include, ModerateCommand, OutputFormat, OverrideDates, OverrideTarget, PeerReviewCommand,
+    PostingPolicy, ReviewOptions, SimilarityOptions, SimilarityReport, Submission, User, CLI,
+};
+use chrono::{DateTime, Local, Utc};
 use clap::{CommandFactory, Parser};
 use itertools::Itertools;
-use std::io;
+use std::io::{self, Write};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -25,113 +37,800 @@ async fn main() -> Result<()> {
     let cli = CLI::try_parse()?;
 
     setup_logging();
-    let config = Config::get(&cli)?;
+    let client = CanvasClient::from_config(Config::get(&cli)?);
+    let course = client.course(client.config().course_id);
 
+    // Commands which do not act on existing assignments
+    match &cli.command {
+        Command::Assignment(AssignmentCommand::Apply(args)) => {
+            if cli.assignments.is_empty() {
+                bail!("`assignment apply` takes the name of an assignment spec");
+            }
+            for selector in &cli.assignments {
+                apply_spec(selector, args, &course).await?;
+            }
+            return Ok(());
+        }
+        Command::Gradebook(args) => return export_gradebook(args, &course).await,
+        Command::Cache(CacheCommand::Clear) => return course.config().cache.clear(),
+        _ => {}
+    }
+    if cli.assignments.is_empty() {
+        bail!("An <ASSIGNMENT_ID> is required for this command");
+    }
+    let assignment_ids = course.resolve(&cli.assignments).await?;
+    let writes_output = match &cli.command {
+        Command::Stats(args) => args.output.is_some(),
+        Command::Similarity(args) => args.output.is_some(),
+        _ => false,
+    };
+    if writes_output && assignment_ids.len() > 1 {
+        bail!("--output can only be used with a single assignment");
+    }
+
+    // Commands which fetch submissions to every assignment at once, and read grade input
+    // for every assignment
     match cli.command {
         Command::Debug => {
-            let (grades, comments) = read_grades_and_comments();
-
-            let reduced_comments = reduce_comments(comments);
-
-            dbg!(grades, reduced_comments, config);
+            for (assignment_id, mut input) in
+                GradeInput::parse(io::stdin().lock(), &assignment_ids)?
+            {
+                input.join_comments();
+                dbg!(assignment_id, input);
+            }
+            dbg!(client.config());
         }
-        Command::Submissions(option) => {
-            let submissions =
-                Submission::assignment_submissions(cli.assignment_id, &option.predicate(), &config)
-                    .await?;
-            let files: Vec<_> = submissions
-                .iter()
-                .flat_map(Submission::files)
-                .flatten()
-                .collect();
+        Command::Submissions(args) => {
+            let mut query = course.submissions().assignments(&assignment_ids);
+            if args.all_attempts {
+                query = query.include(Include::SubmissionHistory);
+            }
+            let submissions = query.state(args.state).one_per_group().list().await?;
 
             let runtime_directiory = dirs::runtime_dir()
                 .expect("Unable to get runtime directiory for system!")
                 .join("grading");
 
+            let files: Vec<_> = if args.all_attempts {
+                submissions
+                    .iter()
+                    .flat_map(Submission::attempt_files)
+                    .collect()
+            } else {
+                submissions
+                    .iter()
+                    .flat_map(Submission::files)
+                    .flatten()
+                    .collect()
+            };
             for file in files {
-                file.download(&runtime_directiory).await?;
+                let mut path = if args.all_attempts {
+                    file.download_attempt(&runtime_directiory, course.config())
+                        .await?
+                } else {
+                    file.download(&runtime_directiory, course.config()).await?
+                };
+                if let Some(options) = args.extract.options() {
+                    match extract_archive(&path, &options) {
+                        Ok(extracted) => path = extracted.unwrap_or(path),
+                        Err(e) => eprintln!("Skipping extraction: {e:#}"),
+                    }
+                }
                 println!(
                     "{}",
-                    runtime_directiory
-                        .join(file.to_string())
-                        .to_str()
+                    path.to_str()
                         .ok_or(anyhow!("Unable to convert path to string"))?
                 );
             }
-        }
-        Command::Grade => {
-            let (grades, comments) = read_grades_and_comments();
 
-            let reduced_comments = reduce_comments(comments);
-            Submission::update_grades_with_comments(
-                cli.assignment_id,
-                &grades,
-                &reduced_comments,
-                &config,
-            )
-            .await?;
+            let repositories: Vec<_> = submissions
+                .iter()
+                .filter_map(|s| s.repository(&course.config().repositories))
+                .collect();
+            for repository in repositories {
+                let path = repository
+                    .download(&runtime_directiory, args.at_submission_time)
+                    .await?;
+                println!(
+                    "{}",
+                    path.to_str()
+                        .ok_or(anyhow!("Unable to convert path to string"))?
+                );
+            }
+        }
+        Command::Grade(args) => {
+            for (assignment_id, input) in GradeInput::parse(io::stdin().lock(), &assignment_ids)? {
+                grade(&course.assignment(assignment_id), input, &args).await?;
+            }
         }
         Command::Count(option) => {
+            let query = course
+                .submissions()
+                .assignments(&assignment_ids)
+                .state(option);
+            if let [_] = assignment_ids[..] {
+                println!("{}", query.count().await?);
+            } else {
+                let counts = query.list().await?.iter().counts_by(Submission::assignment);
+                for assignment_id in &assignment_ids {
+                    println!(
+                        "{assignment_id}: {}",
+                        counts.get(assignment_id).copied().unwrap_or(0)
+                    );
+                }
+            }
+        }
+        Command::Comments(args) => {
+            let mut query = course
+                .submissions()
+                .assignments(&assignment_ids)
+                .include(Include::SubmissionComments);
+            if let Some(state) = args.state {
+                query = query.state(state);
+            }
+            let threads: Vec<_> = query
+                .list()
+                .await?
+                .iter()
+                .map(Submission::comment_thread)
+                .filter(|t| !t.comments.is_empty())
+                .collect();
+
+            let mut output: Box<dyn io::Write> = match &args.output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout()),
+            };
+            match args.format {
+                OutputFormat::Text => {
+                    for (assignment_id, threads) in &threads.iter().chunk_by(|t| t.assignment_id) {
+                        if assignment_ids.len() > 1 {
+                            writeln!(output, "Assignment {assignment_id}")?;
+                        }
+                        for thread in threads {
+                            write!(output, "{}", thread.to_text())?;
+                        }
+                    }
+                }
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut output, &threads)?;
+                    writeln!(output)?;
+                }
+                OutputFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(output);
+                    writer.write_record(CommentThread::CSV_HEADER)?;
+                    for thread in &threads {
+                        thread.write_csv(&mut writer)?;
+                    }
+                    writer.flush()?;
+                }
+            }
+        }
+        Command::History => {
+            let submissions = course
+                .submissions()
+                .assignments(&assignment_ids)
+                .include(Include::SubmissionHistory)
+                .filter(Submission::submitted)
+                .list()
+                .await?;
+
+            for (assignment_id, submissions) in &submissions.iter().chunk_by(|s| s.assignment()) {
+                if assignment_ids.len() > 1 {
+                    println!("Assignment {assignment_id}");
+                }
+                for submission in submissions {
+                    println!("{}", submission.user());
+                    for attempt in submission.history() {
+                        let score = attempt
+                            .score()
+                            .map_or("ungraded".to_owned(), |s| format!("score {s}"));
+                        let grader = attempt
+                            .grader()
+                            .map_or(String::new(), |g| format!(", graded by {g}"));
+                        println!(
+                            "    attempt {} submitted {}: {score}{grader}",
+                            attempt.attempt(),
+                            attempt.submitted_at().unwrap_or("never")
+                        );
+                    }
+                }
+            }
+        }
+        command => {
+            for &assignment_id in &assignment_ids {
+                if assignment_ids.len() > 1 {
+                    eprintln!("Assignment {assignment_id}");
+                }
+                run(command.clone(), &course.assignment(assignment_id)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run a command on one assignment.
+async fn run(command: Command, handle: &AssignmentHandle) -> Result<()> {
+    let assignment_id = handle.id();
+    let config = handle.config();
+    match command {
+        Command::PeerReviews(command) => match command {
+            PeerReviewCommand::List => {
+                for review in handle.peer_reviews().await? {
+                    println!(
+                        "{} reviews {}: {}",
+                        review.assessor_id, review.user_id, review.workflow_state
+                    );
+                }
+            }
+            PeerReviewCommand::Assign(args) => {
+                let options = ReviewOptions {
+                    count: args.count,
+                    same_section: !args.any_section,
+                    seed: args.seed,
+                    ..Default::default()
+                };
+                for (reviewer, reviewee) in handle
+                    .assign_peer_reviews(&args.avoid, options, args.dry_run)
+                    .await?
+                {
+                    println!("{reviewer} reviews {reviewee}");
+                }
+            }
+            PeerReviewCommand::Report(args) => {
+                for progress in handle.peer_review_progress().await? {
+                    let reviewer = progress.reviewer_id;
+                    let score = progress.score(args.points);
+                    println!("{reviewer}: {}", (score * 100.0).round() / 100.0);
+                    println!(
+                        "{reviewer}: Completed {} of {} peer reviews",
+                        progress.completed, progress.assigned
+                    );
+                }
+            }
+        },
+        Command::Tui(args) => {
+            let assignment = handle.get().await?;
+            let mut session = GradingSession::load(assignment_id)?;
+            if !args.upload {
+                let submissions = handle
+                    .submissions()
+                    .include(Include::SubmissionComments)
+                    .include(Include::User)
+                    .state(args.state.unwrap_or_default())
+                    .one_per_group()
+                    .list()
+                    .await?;
+                let directory = dirs::runtime_dir()
+                    .expect("Unable to get runtime directiory for system!")
+                    .join("grading");
+                session = GradingTui::new(&assignment, submissions, session, &directory)
+                    .run(config)
+                    .await?;
+            }
+
+            let rubric = assignment.rubric.as_deref().unwrap_or_default();
+            let (grades, comments) = session.pending(rubric);
+            if args.upload {
+                let count = session.upload(&assignment, config).await?;
+                println!("Uploaded grades and comments for {count} students");
+            } else if !grades.is_empty() || !comments.is_empty() {
+                println!(
+                    "{} grades and {} comments saved but not uploaded, upload them with `tui --upload`",
+                    grades.len(),
+                    comments.len()
+                );
+            }
+        }
+        Command::Cache(CacheCommand::Refresh) => {
+            if config.cache.offline {
+                bail!("Unable to refresh the cache while offline");
+            }
+            // Requests are cached as they are made, and offline requests can be answered by
+            // cached ones with more `include[]`s, so include everything used by any command
+            let assignment = handle.get().await?;
+            let assignments = handle.course().assignments().await?;
+            User::current(config).await?;
+            let students = handle.course().students().await?;
+            let mut query = handle
+                .submissions()
+                .include(Include::SubmissionHistory)
+                .include(Include::SubmissionComments)
+                .include(Include::User)
+                .include(Include::Group);
+            if assignment.moderated_grading {
+                query = query.include(Include::ProvisionalGrades);
+            }
+            let submissions = query.list().await?;
+            handle.overrides().await?;
+            handle.peer_reviews().await?;
+            if let Some(category_id) = assignment.group_category_id {
+                Group::in_category(category_id, config).await?;
+            }
+
             println!(
-                "{}",
-                Submission::count_submissions(cli.assignment_id, &option.predicate(), &config)
+                "Cached {} assignments, {} student enrollments and {} submissions",
+                assignments.len(),
+                students.len(),
+                submissions.len()
+            );
+        }
+        Command::Assignment(_) | Command::Gradebook(_) | Command::Cache(CacheCommand::Clear) => {
+            unreachable!("handled before resolving the assignment ids")
+        }
+        Command::Debug
+        | Command::Submissions(_)
+        | Command::Grade(_)
+        | Command::Count(_)
+        | Command::Comments(_)
+        | Command::History => unreachable!("handled for every assignment at once"),
+        Command::Extend(command) => match command {
+            ExtendCommand::List => {
+                for o in handle.overrides().await? {
+                    let target = match o.course_section_id {
+                        Some(section_id) => format!("section {section_id}"),
+                        None => format!("students {}", o.students().iter().join(", ")),
+                    };
+                    println!(
+                        "{} {}: {target}, due {}, locks {}",
+                        o.id,
+                        o.title.as_deref().unwrap_or_default(),
+                        format_date(o.due_at),
+                        format_date(o.lock_at)
+                    );
+                }
+            }
+            ExtendCommand::Students(args) => {
+                let assignment = handle.get().await?;
+                let dates = args.dates.resolve(&assignment)?;
+                let target = OverrideTarget::Students(args.user_ids.into_iter().collect());
+                let title = args.dates.by.map(|by| format!("Extension {by}"));
+                match handle.extend(&target, title.as_deref(), &dates).await? {
+                    Some(o) => println!(
+                        "Due {} for students {}",
+                        format_date(dates.due_at),
+                        o.students().iter().join(", ")
+                    ),
+                    None => println!("Every student already has a later due date"),
+                }
+            }
+            ExtendCommand::Section(args) => {
+                let assignment = handle.get().await?;
+                let dates = args.dates.resolve(&assignment)?;
+                let target = OverrideTarget::Section(args.section_id);
+                handle.extend(&target, None, &dates).await?;
+                println!("Due {}", format_date(dates.due_at));
+            }
+            ExtendCommand::Accommodations(args) => {
+                let accommodations = Accommodation::read(&args.file)?;
+                let assignments = if args.all_assignments {
+                    handle
+                        .course()
+                        .assignments()
+                        .await?
+                        .into_iter()
+                        .filter(|a| a.due_at.is_some())
+                        .collect()
+                } else {
+                    vec![handle.get().await?]
+                };
+                let mut sections: HashMap<u64, BTreeSet<u64>> = HashMap::new();
+                for enrollment in handle.course().students().await? {
+                    sections
+                        .entry(enrollment.user_id)
+                        .or_default()
+                        .extend(enrollment.course_section_id);
+                }
+
+                for assignment in &assignments {
+                    let assignment_handle = handle.course().assignment(assignment.id);
+                    let overrides = assignment_handle.overrides().await?;
+
+                    // Extensions apply to each student's own dates, which differ between
+                    // sections, and students with the same new dates share one override
+                    let mut by_dates: BTreeMap<(String, OverrideDates), BTreeSet<u64>> =
+                        BTreeMap::new();
+                    for accommodation in &accommodations {
+                        let dates = OverrideDates::effective(
+                            assignment,
+                            &overrides,
+                            sections
+                                .get(&accommodation.student)
+                                .unwrap_or(&BTreeSet::new()),
+                        );
+                        match accommodation.extension.apply(&dates) {
+                            Ok(dates) => {
+                                by_dates
+                                    .entry((accommodation.extension.to_string(), dates))
+                                    .or_default()
+                                    .insert(accommodation.student);
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "Skipping {} for student {}: {e}",
+                                    assignment.name, accommodation.student
+                                );
+                            }
+                        }
+                    }
+
+                    for ((extension, dates), students) in by_dates {
+                        let target = OverrideTarget::Students(students);
+                        let title = format!("Accommodation {extension}");
+                        let Some(o) = assignment_handle
+                            .extend(&target, Some(&title), &dates)
+                            .await?
+                        else {
+                            continue;
+                        };
+                        println!(
+                            "{}: {} students with {extension} due {}",
+                            assignment.name,
+                            o.students().len(),
+                            format_date(dates.due_at)
+                        );
+                    }
+                }
+            }
+        },
+        Command::Moderate(command) => match command {
+            ModerateCommand::List => {
+                let submissions = handle
+                    .submissions()
+                    .include(Include::ProvisionalGrades)
+                    .filter(Submission::submitted)
+                    .list()
+                    .await?;
+
+                for submission in submissions {
+                    println!("{}", submission.user());
+                    for grade in submission.provisional_grades() {
+                        let score = grade
+                            .score
+                            .map_or("ungraded".to_owned(), |s| format!("score {s}"));
+                        let scorer = grade
+                            .scorer_id
+                            .map_or("an anonymous grader".to_owned(), |s| s.to_string());
+                        println!(
+                            "    {}: {score} by {scorer}{}",
+                            grade.provisional_grade_id,
+                            if grade.is_final { " (moderator)" } else { "" }
+                        );
+                    }
+                }
+            }
+            ModerateCommand::Select(args) => {
+                let provisional_grade_ids = match args.grader {
+                    Some(grader) => handle
+                        .submissions()
+                        .include(Include::ProvisionalGrades)
+                        .filter(Submission::submitted)
+                        .list()
+                        .await?
+                        .iter()
+                        .filter_map(|s| {
+                            s.provisional_grades()
+                                .iter()
+                                .find(|g| g.scorer_id == Some(grader))
+                                .map(|g| g.provisional_grade_id)
+                        })
+                        .collect(),
+                    None => args.provisional_grade_ids,
+                };
+
+                for provisional_grade_id in provisional_grade_ids {
+                    handle
+                        .select_provisional_grade(provisional_grade_id)
+                        .await?;
+                }
+            }
+            ModerateCommand::Publish => {
+                handle.publish_provisional_grades().await?;
+            }
+        },
+        Command::PostingPolicy(args) => match args.policy {
+            Some(policy) => {
+                handle
+                    .set_post_policy(policy == PostingPolicy::Manual)
                     .await?
-            )
+            }
+            None => {
+                let assignment = handle.get().await?;
+                println!(
+                    "{}",
+                    if assignment.post_manually {
+                        "manual"
+                    } else {
+                        "automatic"
+                    }
+                );
+            }
+        },
+        Command::PostGrades(args) => {
+            handle
+                .post_grades(&args.sections, args.graded_only)
+                .await?
+                .wait(config)
+                .await?;
+        }
+        Command::HideGrades(args) => {
+            handle
+                .hide_grades(&args.sections)
+                .await?
+                .wait(config)
+                .await?;
+        }
+        Command::Autograde(args) => {
+            let mut autograde_config = config.autograde.clone();
+            autograde_config.command = args.command.or(autograde_config.command);
+            autograde_config.timeout = args.timeout.unwrap_or(autograde_config.timeout);
+            autograde_config.sandbox.enabled |= args.sandbox;
+            let autograder = Autograder::new(autograde_config)?;
+
+            let submissions = handle
+                .submissions()
+                .state(args.state)
+                .one_per_group()
+                .list()
+                .await?;
+
+            let autograde_directory = dirs::runtime_dir()
+                .expect("Unable to get runtime directiory for system!")
+                .join("grading")
+                .join("autograde");
+
+            let mut grades = Vec::new();
+            let mut comments = Vec::new();
+            let mut tests = HashMap::new();
+            for submission in submissions {
+                // One student's submission failing should not stop the rest being graded
+                let result = match submission
+                    .download_into(
+                        &autograde_directory,
+                        args.extract.options().as_ref(),
+                        config,
+                    )
+                    .await
+                {
+                    Ok(directory) => {
+                        autograder
+                            .run(&directory, submission.user(), assignment_id)
+                            .await
+                    }
+                    Err(e) => Err(e.context("Unable to download submission")),
+                }
+                .unwrap_or_else(|e| AutogradeResult::failed(submission.user(), &e));
+                eprintln!(
+                    "{}: {:?} with {}",
+                    result.user_id, result.outcome, result.usage
+                );
+                if !result.stderr.is_empty() {
+                    eprintln!("{}: {}", result.user_id, result.stderr.trim_end());
+                }
+
+                grades.extend(result.grade);
+                comments.extend(result.comments);
+                tests.insert(result.user_id, result.tests);
+            }
+
+            if args.upload {
+                let input = GradeInput {
+                    grades,
+                    comments,
+                    attachments: Vec::new(),
+                };
+                let upload = handle
+                    .grades()
+                    .submit(input, &tests, &args.comment.options())
+                    .await?;
+                report_skipped_comments(&upload);
+            } else {
+                for grade in grades {
+                    println!("{}: {}", grade.user_id, grade.grade);
+                }
+                for comment in comments {
+                    println!("{}: {}", comment.user_id, comment.comment);
+                }
+            }
+        }
+        Command::Similarity(args) => {
+            // Members of a group share a submission, which would match itself
+            let submissions = handle
+                .submissions()
+                .filter(Submission::submitted)
+                .one_per_group()
+                .list()
+                .await?;
+
+            let similarity_directory = dirs::runtime_dir()
+                .expect("Unable to get runtime directiory for system!")
+                .join("grading")
+                .join("similarity");
+
+            let mut directories = Vec::new();
+            for submission in submissions {
+                let directory = submission
+                    .download_into(
+                        &similarity_directory,
+                        args.extract.options().as_ref(),
+                        config,
+                    )
+                    .await?;
+                directories.push((submission.user(), directory));
+            }
+
+            let options = SimilarityOptions {
+                k: args.k,
+                window: args.window,
+                threshold: args.threshold,
+            };
+            let report =
+                SimilarityReport::compare(&directories, args.starter.as_deref(), &options)?;
+
+            for pair in &report.pairs {
+                println!(
+                    "{:.1}%\t{}\t{}",
+                    pair.similarity * 100.0,
+                    pair.first,
+                    pair.second
+                );
+            }
+            if let Some(output) = args.output {
+                std::fs::create_dir_all(&output)?;
+                std::fs::write(
+                    output.join("similarity.json"),
+                    serde_json::to_string_pretty(&report)?,
+                )?;
+                std::fs::write(output.join("similarity.html"), report.to_html())?;
+            }
+        }
+        Command::Stats(args) => {
+            let assignment = handle.get().await?;
+            let submissions = handle.submissions().list().await?;
+            let mut sections: HashMap<u64, Vec<u64>> = HashMap::new();
+            for enrollment in handle.course().students().await? {
+                sections
+                    .entry(enrollment.user_id)
+                    .or_default()
+                    .extend(enrollment.course_section_id);
+            }
+            let stats = GradeStats::new(
+                assignment.points_possible,
+                &submissions,
+                &sections,
+                args.bins,
+            );
+
+            let mut output: Box<dyn io::Write> = match &args.output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout()),
+            };
+            match args.format {
+                OutputFormat::Text => write!(output, "{}", stats.to_text())?,
+                OutputFormat::Json => {
+                    serde_json::to_writer_pretty(&mut output, &stats)?;
+                    writeln!(output)?;
+                }
+                OutputFormat::Csv => {
+                    let mut writer = csv::Writer::from_writer(output);
+                    stats.write_csv(&mut writer)?;
+                    writer.flush()?;
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-/// Reduce multiple comments to a single one
-fn reduce_comments(comments: Vec<Comment>) -> Vec<Comment> {
-    let mut reduced_comments = Vec::new();
-    let mut acc = String::new();
-    let mut user_id: Option<u64> = None;
-    for comment in comments.into_iter().sorted_unstable_by_key(|c| c.user_id) {
-        if user_id.is_none_or(|id| id == comment.user_id) {
-            acc.push_str(&comment.comment);
-            acc.push('\n');
-        } else if let Some(uid) = user_id {
-            reduced_comments.push(Comment {
-                user_id: uid,
-                comment: acc.to_owned(),
-            });
-            acc = comment.comment;
-        }
-
-        user_id = Some(comment.user_id)
+/// Upload the grades, comments and attachments given for one assignment.
+async fn grade(handle: &AssignmentHandle, input: GradeInput, args: &GradeArgs) -> Result<()> {
+    let config = handle.config();
+    let upload = handle
+        .grades()
+        .provisional(args.provisional)
+        .submit(input, &HashMap::new(), &args.comment.options())
+        .await?;
+    report_skipped_comments(&upload);
+
+    if args.post_after && !args.provisional {
+        // Grades can only be posted once the update job has applied them
+        if let Some(progress) = upload.progress {
+            progress.wait(config).await?;
+        }
+        handle.post_grades(&[], true).await?.wait(config).await?;
+        eprintln!("Posted grades of graded students");
     }
-    if let Some(user_id) = user_id {
-        reduced_comments.push(Comment {
-            user_id,
-            comment: acc.to_owned(),
-        });
+
+    Ok(())
+}
+
+fn report_skipped_comments(upload: &GradeUpload) {
+    for user_id in &upload.duplicates {
+        eprintln!("Skipped comment for {user_id} identical to one already posted");
     }
+    for user_id in &upload.not_replaced {
+        eprintln!("Unable to replace the previous comment for anonymous student {user_id}");
+    }
+}
+
+/// Create or update the assignment of a spec, printing the changes first.
+async fn apply_spec(
+    selector: &AssignmentSelector,
+    args: &ApplyArgs,
+    course: &CourseHandle,
+) -> Result<()> {
+    let AssignmentSelector::Assignment(AssignmentRef::Spec(name)) = selector else {
+        bail!("`assignment apply` takes the names of assignment specs, not \"{selector}\"");
+    };
+    let mut spec = AssignmentSpec::find(name)?;
+    let (current, overrides) = match spec.assignment_id {
+        Some(assignment_id) => {
+            let handle = course.assignment(assignment_id);
+            (Some(handle.get().await?), handle.overrides().await?)
+        }
+        None => (None, vec![]),
+    };
 
-    reduced_comments
+    let diff = spec.diff(current.as_ref(), &overrides);
+    if diff.is_empty() {
+        println!("{} is up to date", spec.name);
+        return Ok(());
+    }
+    for change in diff {
+        println!("{change}");
+    }
+    if !args.dry_run {
+        let assignment = spec.apply(course.config()).await?;
+        println!("Applied to assignment {}", assignment.id);
+    }
+
+    Ok(())
+}
+
+async fn export_gradebook(args: &GradebookArgs, course: &CourseHandle) -> Result<()> {
+    let weighted = course.get().await?.apply_assignment_group_weights;
+    let groups = course.assignment_groups().await?;
+    let assignments = course.assignments().await?;
+    let submissions = course.submissions().include(Include::User).list().await?;
+    let gradebook = Gradebook::new(&groups, &assignments, &submissions, weighted);
+
+    if args.format == GradebookFormat::Xlsx {
+        let path = args
+            .output
+            .as_ref()
+            .context("XLSX needs an --output file")?;
+        return gradebook.write_xlsx(path);
+    }
+    let mut output: Box<dyn io::Write> = match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    };
+    match args.format {
+        GradebookFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            gradebook.write_csv(&mut writer)?;
+            writer.flush()?;
+        }
+        GradebookFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &gradebook)?;
+            writeln!(output)?;
+        }
+        GradebookFormat::Xlsx => unreachable!("written above"),
+    }
+
+    Ok(())
 }
 
-fn read_grades_and_comments() -> (Vec<Grade>, Vec<Comment>) {
-    let stdin = io::stdin();
-
-    let (grades, comments): (Vec<_>, Vec<_>) = stdin
-        .lines()
-        .map_while(Result::ok)
-        .map(|line| line.trim().to_owned())
-        .map(|line| (line.to_owned(), Grade::from_str(&line).ok()))
-        .map(|(line, grade)| {
-            grade.map_or_else(
-                || (None, Comment::from_str(&line).ok()),
-                |g| (Some(g), None),
-            )
-        })
-        .unzip();
-    let grades: Vec<Grade> = grades.into_iter().flatten().collect();
-    let comments: Vec<Comment> = comments.into_iter().flatten().collect();
-
-    (grades, comments)
+/// A date in local time, as accepted by `extend --due`.
+fn format_date(date: Option<DateTime<Utc>>) -> String {
+    match date {
+        Some(date) => date
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string(),
+        None => "never".to_owned(),
+    }
 }
 
 #[allow(unused)]
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::Config;
+
+/// A grade given by one grader on a moderated assignment, which only counts once the
+/// moderator selects it and publishes the final grades.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProvisionalGrade {
+    pub provisional_grade_id: u64,
+    pub score: Option<f32>,
+    pub grade: Option<String>,
+    /// Hidden if graders are anonymous to each other
+    pub scorer_id: Option<u64>,
+    /// Whether this is the moderator's own grade
+    #[serde(rename = "final", default)]
+    pub is_final: bool,
+    pub graded_at: Option<String>,
+}
+
+impl ProvisionalGrade {
+    /// Choose this provisional grade as the student's final grade.
+    pub async fn select(
+        assignment_id: u64,
+        provisional_grade_id: u64,
+        config: &Config,
+    ) -> Result<()> {
+        info!("Selecting provisional grade {provisional_grade_id}");
+        config
+            .client
+            .put(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/provisional_grades/{provisional_grade_id}/select",
+                config.base_url, config.course_id
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Publish the selected provisional grades as the assignment's grades. This can only be
+    /// done once per assignment.
+    pub async fn publish(assignment_id: u64, config: &Config) -> Result<()> {
+        info!("Publishing provisional grades of assignment {assignment_id}");
+        config
+            .client
+            .post(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/provisional_grades/publish",
+                config.base_url, config.course_id
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,532 @@
+use std::{collections::BTreeSet, fmt, path::Path, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{api::get_paginated, Assignment, Config};
+
+/// Dates which differ from the assignment's for some students or a section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssignmentOverride {
+    pub id: u64,
+    pub title: Option<String>,
+    /// Set on overrides for individual students
+    pub student_ids: Option<Vec<u64>>,
+    /// Set on overrides for a section
+    pub course_section_id: Option<u64>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub unlock_at: Option<DateTime<Utc>>,
+    pub lock_at: Option<DateTime<Utc>>,
+}
+
+/// Who an override applies to.
+#[derive(Debug, Clone)]
+pub enum OverrideTarget {
+    Students(BTreeSet<u64>),
+    Section(u64),
+}
+
+/// Overridden dates, where `None` keeps the assignment's date.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OverrideDates {
+    pub unlock_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub lock_at: Option<DateTime<Utc>>,
+}
+
+impl OverrideDates {
+    /// The assignment's own dates.
+    pub fn of(assignment: &Assignment) -> Self {
+        Self {
+            unlock_at: assignment.unlock_at,
+            due_at: assignment.due_at,
+            lock_at: assignment.lock_at,
+        }
+    }
+
+    /// The dates a student in `sections` has before any extension of their own: those of
+    /// their section's override, or the assignment's. Like Canvas, a student in several
+    /// overridden sections gets the latest due date.
+    pub fn effective(
+        assignment: &Assignment,
+        overrides: &[AssignmentOverride],
+        sections: &BTreeSet<u64>,
+    ) -> Self {
+        let assignment = Self::of(assignment);
+        overrides
+            .iter()
+            .filter(|o| o.course_section_id.is_some_and(|id| sections.contains(&id)))
+            .map(|o| Self {
+                unlock_at: o.unlock_at.or(assignment.unlock_at),
+                due_at: o.due_at.or(assignment.due_at),
+                lock_at: o.lock_at.or(assignment.lock_at),
+            })
+            .max_by_key(|dates| dates.due_at)
+            .unwrap_or(assignment)
+    }
+}
+
+impl AssignmentOverride {
+    pub async fn list(assignment_id: u64, config: &Config) -> Result<Vec<Self>> {
+        get_paginated(
+            &format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/overrides",
+                config.base_url, config.course_id
+            ),
+            &[],
+            config,
+        )
+        .await
+    }
+
+    pub fn students(&self) -> BTreeSet<u64> {
+        self.student_ids.iter().flatten().copied().collect()
+    }
+
+    /// Give students or a section new dates, updating the override which already targets
+    /// exactly them or else creating one.
+    ///
+    /// Canvas allows a student to be in only one override per assignment, so students are
+    /// removed from any other override they are in, which is deleted once it has no students.
+    /// Students whose own override is due later than `dates` keep it, so an extension never
+    /// shortens another. Returns `None` if that leaves no students to extend.
+    pub async fn extend(
+        assignment_id: u64,
+        target: &OverrideTarget,
+        title: Option<&str>,
+        dates: &OverrideDates,
+        config: &Config,
+    ) -> Result<Option<Self>> {
+        let overrides = Self::list(assignment_id, config).await?;
+        let mut target = target.clone();
+        if let OverrideTarget::Students(student_ids) = &mut target {
+            student_ids.retain(|id| {
+                let later = overrides.iter().find(|o| {
+                    o.students().contains(id)
+                        && o.due_at.is_some_and(|due_at| dates.due_at < Some(due_at))
+                });
+                if let Some(later) = later {
+                    warn!(
+                        "Keeping the later due date of student {id} in override {}",
+                        later.id
+                    );
+                }
+                later.is_none()
+            });
+            if student_ids.is_empty() {
+                return Ok(None);
+            }
+        }
+
+        match &target {
+            OverrideTarget::Section(section_id) => {
+                if let Some(existing) = overrides
+                    .iter()
+                    .find(|o| o.course_section_id == Some(*section_id))
+                {
+                    return existing
+                        .update(assignment_id, title, None, dates, config)
+                        .await
+                        .map(Some);
+                }
+            }
+            OverrideTarget::Students(student_ids) => {
+                if let Some(existing) = overrides.iter().find(|o| o.students() == *student_ids) {
+                    return existing
+                        .update(assignment_id, title, None, dates, config)
+                        .await
+                        .map(Some);
+                }
+
+                for existing in &overrides {
+                    let students = existing.students();
+                    if students.is_disjoint(student_ids) {
+                        continue;
+                    }
+                    let remaining: Vec<u64> = students.difference(student_ids).copied().collect();
+                    if remaining.is_empty() {
+                        existing.delete(assignment_id, config).await?;
+                    } else {
+                        let dates = OverrideDates {
+                            unlock_at: existing.unlock_at,
+                            due_at: existing.due_at,
+                            lock_at: existing.lock_at,
+                        };
+                        existing
+                            .update(assignment_id, None, Some(&remaining), &dates, config)
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        Self::create(assignment_id, &target, title, dates, config)
+            .await
+            .map(Some)
+    }
+
+    pub async fn create(
+        assignment_id: u64,
+        target: &OverrideTarget,
+        title: Option<&str>,
+        dates: &OverrideDates,
+        config: &Config,
+    ) -> Result<Self> {
+        info!("Creating override on assignment {assignment_id} for {target:?}");
+        let mut form = Self::form(title, dates);
+        match target {
+            OverrideTarget::Students(student_ids) => form.extend(
+                student_ids
+                    .iter()
+                    .map(|id| ("assignment_override[student_ids][]", id.to_string())),
+            ),
+            OverrideTarget::Section(section_id) => form.push((
+                "assignment_override[course_section_id]",
+                section_id.to_string(),
+            )),
+        }
+
+        config
+            .client
+            .post(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/overrides",
+                config.base_url, config.course_id
+            ))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Unable to parse created override")
+    }
+
+    /// Replace the override's dates, and its students if given.
+    ///
+    /// Canvas drops any overridden date which is not given again, so `dates` should contain
+    /// every date to keep.
+    pub async fn update(
+        &self,
+        assignment_id: u64,
+        title: Option<&str>,
+        student_ids: Option<&[u64]>,
+        dates: &OverrideDates,
+        config: &Config,
+    ) -> Result<Self> {
+        info!(
+            "Updating override {} on assignment {assignment_id}",
+            self.id
+        );
+        let mut form = Self::form(title.or(self.title.as_deref()), dates);
+        if let Some(student_ids) = student_ids {
+            form.extend(
+                student_ids
+                    .iter()
+                    .map(|id| ("assignment_override[student_ids][]", id.to_string())),
+            );
+        }
+
+        config
+            .client
+            .put(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/overrides/{}",
+                config.base_url, config.course_id, self.id
+            ))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Unable to parse updated override")
+    }
+
+    pub async fn delete(&self, assignment_id: u64, config: &Config) -> Result<()> {
+        info!(
+            "Deleting override {} on assignment {assignment_id}",
+            self.id
+        );
+        config
+            .client
+            .delete(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/overrides/{}",
+                config.base_url, config.course_id, self.id
+            ))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    fn form(title: Option<&str>, dates: &OverrideDates) -> Vec<(&'static str, String)> {
+        let mut form = Vec::new();
+        if let Some(title) = title {
+            form.push(("assignment_override[title]", title.to_owned()));
+        }
+        if let Some(unlock_at) = dates.unlock_at {
+            form.push(("assignment_override[unlock_at]", unlock_at.to_rfc3339()));
+        }
+        if let Some(due_at) = dates.due_at {
+            form.push(("assignment_override[due_at]", due_at.to_rfc3339()));
+        }
+        if let Some(lock_at) = dates.lock_at {
+            form.push(("assignment_override[lock_at]", lock_at.to_rfc3339()));
+        }
+        form
+    }
+}
+
+/// More time on an assignment, relative to its dates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extension {
+    /// Multiply the time between the assignment unlocking and being due, written `1.5x`
+    Factor(f64),
+    /// Push the due date back, written `2d`, `36h` or `90m`
+    Delay(TimeDelta),
+}
+
+impl Extension {
+    /// `dates` pushed back by the extension. The lock date moves with the due date, so late
+    /// submissions are accepted for as long as before.
+    pub fn apply(&self, dates: &OverrideDates) -> Result<OverrideDates> {
+        let due_at = dates.due_at.context("There is no due date to extend")?;
+        let delay = match self {
+            Extension::Delay(delay) => *delay,
+            Extension::Factor(factor) => {
+                let unlock_at = dates
+                    .unlock_at
+                    .context("There is no unlock date to scale the time from")?;
+                let seconds = (due_at - unlock_at).num_seconds() as f64 * (factor - 1.0);
+                TimeDelta::seconds(seconds.round() as i64)
+            }
+        };
+
+        Ok(OverrideDates {
+            unlock_at: dates.unlock_at,
+            due_at: Some(due_at + delay),
+            lock_at: dates.lock_at.map(|lock_at| lock_at + delay),
+        })
+    }
+}
+
+impl FromStr for Extension {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let Some(unit) = s.chars().last() else {
+            bail!("Extension is empty");
+        };
+        let amount = s[..s.len() - unit.len_utf8()].trim_start_matches('+');
+        let invalid = || format!("Invalid extension {s:?}, expected e.g. 1.5x, 2d or 36h");
+        let extension = match unit {
+            'x' => {
+                let factor: f64 = amount.parse().with_context(invalid)?;
+                if factor < 1.0 {
+                    bail!("Extension factor {factor} would shorten the assignment");
+                }
+                Extension::Factor(factor)
+            }
+            'd' | 'h' | 'm' => {
+                let amount: i64 = amount.parse().with_context(invalid)?;
+                if amount <= 0 {
+                    bail!("Extension {s:?} must be longer than zero");
+                }
+                let delay = match unit {
+                    'd' => TimeDelta::try_days(amount),
+                    'h' => TimeDelta::try_hours(amount),
+                    _ => TimeDelta::try_minutes(amount),
+                };
+                Extension::Delay(delay.with_context(|| format!("Extension {s:?} is too long"))?)
+            }
+            _ => bail!(invalid()),
+        };
+
+        Ok(extension)
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Extension::Factor(factor) => write!(f, "{factor}x"),
+            Extension::Delay(delay) if delay.num_minutes() % (24 * 60) == 0 => {
+                write!(f, "{}d", delay.num_days())
+            }
+            Extension::Delay(delay) if delay.num_minutes() % 60 == 0 => {
+                write!(f, "{}h", delay.num_hours())
+            }
+            Extension::Delay(delay) => write!(f, "{}m", delay.num_minutes()),
+        }
+    }
+}
+
+/// An extension a student gets on every assignment, such as extra time.
+#[derive(Debug, Clone)]
+pub struct Accommodation {
+    pub student: u64,
+    pub extension: Extension,
+}
+
+#[derive(Deserialize)]
+struct AccommodationRecord {
+    student: u64,
+    extension: String,
+}
+
+impl Accommodation {
+    /// Read accommodations from a CSV file with `student` and `extension` columns.
+    pub fn read(path: &Path) -> Result<Vec<Self>> {
+        let mut reader = csv::Reader::from_path(path)
+            .with_context(|| format!("Unable to read accommodations {:?}", path))?;
+        reader
+            .deserialize()
+            .map(|record| {
+                let record: AccommodationRecord =
+                    record.with_context(|| format!("Unable to parse accommodations {:?}", path))?;
+                Ok(Accommodation {
+                    student: record.student,
+                    extension: record.extension.parse()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Parse a date given on the command line, either in RFC 3339 or as `YYYY-MM-DD HH:MM` in
+/// local time. A date without a time means the end of that day.
+pub fn parse_date(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.to_utc());
+    }
+
+    let naive = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap()))
+        })
+        .with_context(|| format!("Invalid date {s:?}, expected e.g. \"2025-03-01 23:59\""))?;
+
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|date| date.to_utc())
+        .with_context(|| format!("{s:?} does not exist in the local time zone"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::memory_config, MemoryTransport};
+
+    const OVERRIDES: &str = "/api/v1/courses/1/assignments/10/overrides";
+
+    fn date(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn assignment() -> Assignment {
+        serde_json::from_value(json!({
+            "id": 10,
+            "name": "Essay",
+            "unlock_at": "2025-03-01T00:00:00Z",
+            "due_at": "2025-03-11T00:00:00Z",
+            "lock_at": "2025-03-13T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn overrides() -> serde_json::Value {
+        json!([
+            {
+                "id": 1,
+                "course_section_id": 2,
+                "due_at": "2025-03-21T00:00:00Z",
+                "lock_at": "2025-03-23T00:00:00Z",
+            },
+            {
+                "id": 2,
+                "student_ids": [101],
+                "due_at": "2025-04-01T00:00:00Z",
+            },
+        ])
+    }
+
+    #[test]
+    fn extensions_apply_to_the_dates_of_the_students_section() {
+        let overrides: Vec<AssignmentOverride> = serde_json::from_value(overrides()).unwrap();
+        let assignment = assignment();
+
+        let section = OverrideDates::effective(&assignment, &overrides, &BTreeSet::from([2]));
+        let extended = Extension::Factor(1.5).apply(&section).unwrap();
+        assert_eq!(extended.due_at, Some(date("2025-03-31T00:00:00Z")));
+        assert_eq!(extended.lock_at, Some(date("2025-04-02T00:00:00Z")));
+
+        let other = OverrideDates::effective(&assignment, &overrides, &BTreeSet::from([3]));
+        assert_eq!(other, OverrideDates::of(&assignment));
+        let extended = Extension::Delay(TimeDelta::days(2)).apply(&other).unwrap();
+        assert_eq!(extended.due_at, Some(date("2025-03-13T00:00:00Z")));
+    }
+
+    #[test]
+    fn extensions_must_be_positive_and_representable() {
+        assert_eq!(
+            "+2d".parse::<Extension>().unwrap(),
+            Extension::Delay(TimeDelta::days(2))
+        );
+        assert_eq!("36h".parse::<Extension>().unwrap().to_string(), "36h");
+        assert!("0d".parse::<Extension>().is_err());
+        assert!("-3h".parse::<Extension>().is_err());
+        assert!("0.5x".parse::<Extension>().is_err());
+        assert!("99999999999999d".parse::<Extension>().is_err());
+    }
+
+    #[tokio::test]
+    async fn later_personal_extensions_are_kept() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = Arc::new(MemoryTransport::new(10));
+        transport.respond(Method::GET, OVERRIDES, overrides());
+        transport.respond(
+            Method::POST,
+            OVERRIDES,
+            json!({ "id": 3, "student_ids": [102] }),
+        );
+        let config = memory_config(transport.clone(), directory.path());
+        let dates = OverrideDates {
+            due_at: Some(date("2025-03-20T00:00:00Z")),
+            ..Default::default()
+        };
+
+        let only_101 = OverrideTarget::Students(BTreeSet::from([101]));
+        let created = AssignmentOverride::extend(10, &only_101, None, &dates, &config)
+            .await
+            .unwrap();
+        assert!(created.is_none());
+
+        let both = OverrideTarget::Students(BTreeSet::from([101, 102]));
+        let created = AssignmentOverride::extend(10, &both, None, &dates, &config)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.students(), BTreeSet::from([102]));
+
+        let changes: Vec<Method> = transport
+            .requests()
+            .into_iter()
+            .map(|(method, _)| method)
+            .filter(|method| method != Method::GET)
+            .collect();
+        assert_eq!(changes, [Method::POST]);
+    }
+}
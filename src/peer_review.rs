@@ -0,0 +1,320 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{api::get_paginated, Config, Enrollment, Submission};
+
+/// One student assigned to review another student's submission.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerReview {
+    /// The reviewer
+    pub assessor_id: u64,
+    /// The student whose submission is reviewed
+    pub user_id: u64,
+    /// Id of the reviewed submission
+    pub asset_id: u64,
+    /// `assigned` or `completed`
+    pub workflow_state: String,
+}
+
+/// Constraints for [`assign_reviewers`].
+#[derive(Debug, Clone, Default)]
+pub struct ReviewOptions {
+    /// Number of reviews each submission should get, and each student should do
+    pub count: usize,
+    /// Only pair students sharing a section
+    pub same_section: bool,
+    /// Pairs of students who must not review each other, in either direction
+    pub avoid: HashSet<(u64, u64)>,
+    /// Seed for the random order of students, for reproducible assignments
+    pub seed: Option<u64>,
+}
+
+/// How many of the peer reviews assigned to a student they completed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReviewerProgress {
+    pub reviewer_id: u64,
+    pub assigned: usize,
+    pub completed: usize,
+}
+
+impl ReviewerProgress {
+    /// The progress of each student with reviews assigned, ordered by id.
+    pub fn of(reviews: &[PeerReview]) -> Vec<Self> {
+        let mut progress: BTreeMap<u64, Self> = BTreeMap::new();
+        for review in reviews {
+            let entry = progress.entry(review.assessor_id).or_insert_with(|| Self {
+                reviewer_id: review.assessor_id,
+                assigned: 0,
+                completed: 0,
+            });
+            entry.assigned += 1;
+            entry.completed += review.completed() as usize;
+        }
+        progress.into_values().collect()
+    }
+
+    /// `points` in proportion to the reviews completed.
+    pub fn score(&self, points: f32) -> f32 {
+        points * self.completed as f32 / self.assigned as f32
+    }
+}
+
+impl PeerReview {
+    pub fn completed(&self) -> bool {
+        self.workflow_state == "completed"
+    }
+
+    pub async fn list(assignment_id: u64, config: &Config) -> Result<Vec<Self>> {
+        get_paginated(
+            &format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/peer_reviews",
+                config.base_url, config.course_id
+            ),
+            &[],
+            config,
+        )
+        .await
+        .with_context(|| format!("Unable to get peer reviews of assignment {assignment_id}"))
+    }
+
+    /// Assign `reviewer_id` to review the submission with id `submission_id`.
+    pub async fn create(
+        assignment_id: u64,
+        submission_id: u64,
+        reviewer_id: u64,
+        config: &Config,
+    ) -> Result<Self> {
+        info!("Assigning {reviewer_id} to review submission {submission_id}");
+        config
+            .client
+            .post(format!(
+                "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions/{submission_id}/peer_reviews",
+                config.base_url, config.course_id
+            ))
+            .form(&[("user_id", reviewer_id)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Unable to parse created peer review")
+    }
+
+    /// Pair the students who submitted to the assignment with [`assign_reviewers`], also
+    /// avoiding pairs who reviewed each other on `avoid_assignments`. Returns the new
+    /// `(reviewer, reviewee)` pairs, which are only created in Canvas unless `dry_run`.
+    pub async fn assign(
+        assignment_id: u64,
+        avoid_assignments: &[u64],
+        mut options: ReviewOptions,
+        dry_run: bool,
+        config: &Config,
+    ) -> Result<Vec<(u64, u64)>> {
+        let submissions: HashMap<u64, u64> =
+            Submission::for_assignments(&[assignment_id], &[], &Submission::submitted, config)
+                .await?
+                .iter()
+                .filter_map(|s| Some((s.user().user_id()?, s.id())))
+                .collect();
+
+        let mut students: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        for enrollment in Enrollment::students(config).await? {
+            if submissions.contains_key(&enrollment.user_id) {
+                students
+                    .entry(enrollment.user_id)
+                    .or_default()
+                    .extend(enrollment.course_section_id);
+            }
+        }
+
+        for &other in avoid_assignments {
+            for review in Self::list(other, config).await? {
+                options.avoid.insert((review.assessor_id, review.user_id));
+            }
+        }
+
+        let existing = Self::list(assignment_id, config).await?;
+        let pairs = assign_reviewers(&students, &existing, &options);
+        if !dry_run {
+            for &(reviewer, reviewee) in &pairs {
+                Self::create(assignment_id, submissions[&reviewee], reviewer, config).await?;
+            }
+        }
+
+        Ok(pairs)
+    }
+}
+
+/// Choose reviewers so every student reviews and is reviewed `count` times where possible,
+/// returning new `(reviewer, reviewee)` pairs. No student is given more than `count` reviews
+/// to do.
+///
+/// `students` maps each student to their sections. Existing reviews count towards the totals
+/// and are never repeated. Students never review themselves or a student they are told to
+/// avoid, so some may get fewer reviews if the constraints cannot all be met.
+pub fn assign_reviewers(
+    students: &BTreeMap<u64, BTreeSet<u64>>,
+    existing: &[PeerReview],
+    options: &ReviewOptions,
+) -> Vec<(u64, u64)> {
+    let mut rng = match options.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+
+    let mut pairs: HashSet<(u64, u64)> = existing
+        .iter()
+        .map(|r| (r.assessor_id, r.user_id))
+        .collect();
+    let mut load: HashMap<u64, usize> = HashMap::new();
+    let mut received: HashMap<u64, usize> = HashMap::new();
+    for (reviewer, reviewee) in &pairs {
+        *load.entry(*reviewer).or_default() += 1;
+        *received.entry(*reviewee).or_default() += 1;
+    }
+
+    let mut reviewees: Vec<u64> = students.keys().copied().collect();
+    reviewees.shuffle(&mut rng);
+    let mut reviewers = reviewees.clone();
+
+    let mut assigned = Vec::new();
+    for reviewee in reviewees {
+        // Reshuffle so ties in load are broken differently for each reviewee
+        reviewers.shuffle(&mut rng);
+        let mut candidates: Vec<u64> = reviewers
+            .iter()
+            .copied()
+            .filter(|&reviewer| {
+                reviewer != reviewee
+                    && load.get(&reviewer).copied().unwrap_or_default() < options.count
+                    && !pairs.contains(&(reviewer, reviewee))
+                    && !options.avoid.contains(&(reviewer, reviewee))
+                    && !options.avoid.contains(&(reviewee, reviewer))
+                    && (!options.same_section
+                        || !students[&reviewer].is_disjoint(&students[&reviewee]))
+            })
+            .collect();
+        candidates.sort_by_key(|reviewer| load.get(reviewer).copied().unwrap_or_default());
+
+        let needed = options
+            .count
+            .saturating_sub(received.get(&reviewee).copied().unwrap_or_default());
+        for reviewer in candidates.into_iter().take(needed) {
+            *load.entry(reviewer).or_default() += 1;
+            pairs.insert((reviewer, reviewee));
+            assigned.push((reviewer, reviewee));
+        }
+    }
+
+    assigned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Students 1 to `count`, in section 1 if odd and 2 if even.
+    fn students(count: u64) -> BTreeMap<u64, BTreeSet<u64>> {
+        (1..=count)
+            .map(|id| (id, BTreeSet::from([2 - id % 2])))
+            .collect()
+    }
+
+    fn options(count: usize, seed: u64) -> ReviewOptions {
+        ReviewOptions {
+            count,
+            seed: Some(seed),
+            ..Default::default()
+        }
+    }
+
+    fn review(assessor_id: u64, user_id: u64) -> PeerReview {
+        PeerReview {
+            assessor_id,
+            user_id,
+            asset_id: user_id * 10,
+            workflow_state: "assigned".to_owned(),
+        }
+    }
+
+    /// How many reviews each student does and gets.
+    fn totals(pairs: &[(u64, u64)]) -> (HashMap<u64, usize>, HashMap<u64, usize>) {
+        let mut done = HashMap::new();
+        let mut received = HashMap::new();
+        for (reviewer, reviewee) in pairs {
+            *done.entry(*reviewer).or_default() += 1;
+            *received.entry(*reviewee).or_default() += 1;
+        }
+        (done, received)
+    }
+
+    #[test]
+    fn progress_counts_completed_reviews_per_reviewer() {
+        let mut reviews = vec![review(2, 1), review(1, 2), review(1, 3)];
+        reviews[1].workflow_state = "completed".to_owned();
+
+        let progress = ReviewerProgress::of(&reviews);
+        let counts: Vec<(u64, usize, usize)> = progress
+            .iter()
+            .map(|p| (p.reviewer_id, p.assigned, p.completed))
+            .collect();
+        assert_eq!(counts, [(1, 2, 1), (2, 1, 0)]);
+        assert_eq!(progress[0].score(10.0), 5.0);
+        assert_eq!(progress[1].score(10.0), 0.0);
+    }
+
+    #[test]
+    fn nobody_reviews_more_than_count() {
+        for seed in 0..50 {
+            let assigned = assign_reviewers(&students(7), &[], &options(2, seed));
+            let (done, received) = totals(&assigned);
+
+            assert!(done.values().all(|&n| n <= 2), "seed {seed}: {assigned:?}");
+            assert!(
+                received.values().all(|&n| n <= 2),
+                "seed {seed}: {assigned:?}"
+            );
+            assert!(assigned
+                .iter()
+                .all(|(reviewer, reviewee)| reviewer != reviewee));
+            let unique: HashSet<_> = assigned.iter().collect();
+            assert_eq!(unique.len(), assigned.len());
+        }
+    }
+
+    #[test]
+    fn existing_reviews_count_towards_the_totals() {
+        let existing = [review(1, 2), review(1, 3), review(4, 1)];
+
+        let assigned = assign_reviewers(&students(5), &existing, &options(2, 1));
+        let (done, received) = totals(&assigned);
+
+        assert_eq!(done.get(&1), None);
+        assert_eq!(received[&1], 1);
+        assert!(!assigned.contains(&(1, 2)) && !assigned.contains(&(4, 1)));
+    }
+
+    #[test]
+    fn constraints_are_respected() {
+        let options = ReviewOptions {
+            same_section: true,
+            avoid: HashSet::from([(1, 3)]),
+            ..options(1, 3)
+        };
+
+        let assigned = assign_reviewers(&students(6), &[], &options);
+
+        let sections = students(6);
+        for (reviewer, reviewee) in &assigned {
+            assert_eq!(sections[reviewer], sections[reviewee]);
+            assert!(![(1, 3), (3, 1)].contains(&(*reviewer, *reviewee)));
+        }
+        // In section 1, 5 can only review one of 1 and 3, who avoid each other, and only one
+        // of them can review 5
+        assert_eq!(assigned.len(), 5);
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::Config;
+
+/// How often a running job is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a job may take before [`Progress::wait`] gives up on it.
+const TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A background job Canvas runs for bulk operations such as updating or posting grades.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Progress {
+    pub id: u64,
+    /// One of `queued`, `running`, `completed` or `failed`
+    pub workflow_state: String,
+    pub message: Option<String>,
+}
+
+impl Progress {
+    pub async fn get(id: u64, config: &Config) -> Result<Self> {
+        config
+            .client
+            .get(format!("{}/api/v1/progress/{id}", config.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Unable to parse progress {id}"))
+    }
+
+    /// Wait for the job to finish, failing if it does or takes more than 30 minutes.
+    pub async fn wait(self, config: &Config) -> Result<Self> {
+        self.wait_for(config, TIMEOUT).await
+    }
+
+    /// Wait for the job to finish, failing if it does or takes longer than `timeout`.
+    pub async fn wait_for(self, config: &Config, timeout: Duration) -> Result<Self> {
+        let deadline = Instant::now() + timeout;
+        let mut progress = self;
+        loop {
+            match progress.workflow_state.as_str() {
+                "completed" => return Ok(progress),
+                "failed" => bail!(
+                    "Canvas job {} failed: {}",
+                    progress.id,
+                    progress.message.unwrap_or_default()
+                ),
+                state if Instant::now() >= deadline => bail!(
+                    "Canvas job {} is still {state} after {timeout:?}",
+                    progress.id
+                ),
+                state => info!("Canvas job {} is {state}", progress.id),
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+            progress = Self::get(progress.id, config).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::memory_config, MemoryTransport};
+
+    #[tokio::test]
+    async fn jobs_running_past_the_timeout_fail() {
+        let transport = Arc::new(MemoryTransport::new(10));
+        let running = json!({ "id": 7, "workflow_state": "running" });
+        transport.respond(Method::GET, "/api/v1/progress/7", running.clone());
+        let directory = tempfile::tempdir().unwrap();
+        let config = memory_config(transport.clone(), directory.path());
+
+        let progress: Progress = serde_json::from_value(running).unwrap();
+        let error = progress
+            .wait_for(&config, Duration::from_millis(1500))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Canvas job 7 is still running after 1.5s"
+        );
+        assert_eq!(transport.requests().len(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tracing::info;
+
+use crate::{StudentId, Submission};
+
+/// Hosts whose URLs are recognized as git repositories even without a `.git` suffix.
+const GIT_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "codeberg.org"];
+
+/// The `[repositories]` section of the config file.
+///
+/// Submitted URLs come from students, so URLs which reach the grader's own disk or SSH keys
+/// are only cloned when allowed here.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RepositoryConfig {
+    /// Clone local `file://` URLs, which can name any repository the grader can read
+    pub allow_local: bool,
+    /// Clone `git@` URLs, which authenticate with the grader's SSH keys
+    pub allow_ssh: bool,
+}
+
+/// Name of the manifest file recording which commit was checked out for each repository.
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+/// A git repository submitted as the URL of an `online_url` submission.
+#[derive(Debug, Clone)]
+pub struct RepositorySubmission {
+    user_id: StudentId,
+    assignment_id: u64,
+    attempt: u64,
+    url: String,
+    submitted_at: Option<String>,
+    options: RepositoryConfig,
+}
+
+impl std::fmt::Display for RepositorySubmission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{}",
+            self.user_id,
+            self.assignment_id,
+            self.attempt,
+            self.name()
+        )
+    }
+}
+
+impl RepositorySubmission {
+    /// Returns `None` if the URL does not point to a git repository, or to one `options` do
+    /// not allow.
+    pub fn new(submission: &Submission, url: &str, options: &RepositoryConfig) -> Option<Self> {
+        Some(Self {
+            user_id: submission.user(),
+            assignment_id: submission.assignment(),
+            attempt: submission.attempt(),
+            url: normalize_git_url(url, options)?,
+            submitted_at: submission.submitted_at().map(str::to_owned),
+            options: options.clone(),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Name of the repository, taken from the last segment of its URL.
+    pub fn name(&self) -> &str {
+        let name = self.url.rsplit(['/', ':']).next().unwrap_or(&self.url);
+        name.strip_suffix(".git").unwrap_or(name)
+    }
+
+    /// Clone the repository into `directory`, replacing any previous clone, and record the
+    /// checked out commit in the directory's manifest.
+    ///
+    /// If `at_submission_time` is set, the last commit before the submission's `submitted_at`
+    /// is checked out instead of the default branch.
+    pub async fn download(&self, directory: &Path, at_submission_time: bool) -> Result<PathBuf> {
+        let path = directory.join(self.to_string());
+        std::fs::create_dir_all(directory)?;
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        info!("Cloning \"{}\" to {}", self.url, path.to_str().unwrap());
+        // Git also refuses protocols which are not allowed, such as in redirects
+        let allow = |allowed| if allowed { "always" } else { "never" };
+        git(
+            directory,
+            &[
+                "-c",
+                &format!("protocol.file.allow={}", allow(self.options.allow_local)),
+                "-c",
+                &format!("protocol.ssh.allow={}", allow(self.options.allow_ssh)),
+                "clone",
+                "--quiet",
+                &self.url,
+                path_str(&path)?,
+            ],
+        )
+        .await?;
+
+        let commit = match (&self.submitted_at, at_submission_time) {
+            (Some(submitted_at), true) => {
+                let commit = git(
+                    &path,
+                    &[
+                        "rev-list",
+                        "-n",
+                        "1",
+                        &format!("--before={submitted_at}"),
+                        "HEAD",
+                    ],
+                )
+                .await?;
+                if commit.is_empty() {
+                    bail!("No commits in \"{}\" before {submitted_at}", self.url);
+                }
+
+                info!("Checking out {commit} from before {submitted_at}");
+                git(&path, &["checkout", "--quiet", "--detach", &commit]).await?;
+                commit
+            }
+            (None, true) => bail!("Submission for \"{}\" has no submission time", self.url),
+            (_, false) => git(&path, &["rev-parse", "HEAD"]).await?,
+        };
+
+        let mut manifest = Manifest::read(directory)?;
+        manifest.0.insert(
+            self.to_string(),
+            ManifestEntry {
+                url: self.url.clone(),
+                commit,
+                submitted_at: self.submitted_at.clone(),
+            },
+        );
+        manifest.write(directory)?;
+
+        Ok(path)
+    }
+}
+
+/// Commits checked out for each cloned repository, keyed by the clone's directory name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest(pub BTreeMap<String, ManifestEntry>);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    pub commit: String,
+    pub submitted_at: Option<String>,
+}
+
+impl Manifest {
+    pub fn read(directory: &Path) -> Result<Self> {
+        match std::fs::read_to_string(directory.join(MANIFEST_FILE)) {
+            Ok(contents) => toml::from_str(&contents).context("Unable to parse manifest as TOML"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, directory: &Path) -> Result<()> {
+        std::fs::write(
+            directory.join(MANIFEST_FILE),
+            toml::to_string(self).context("Unable to serialize manifest")?,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Recognize git hosting URLs, returning the URL to clone from.
+///
+/// Links into a repository on a known host (e.g. `/tree/main`) are reduced to the repository
+/// itself. Local `file://` and SSH `git@` URLs are only accepted if `options` allow them.
+pub fn normalize_git_url(url: &str, options: &RepositoryConfig) -> Option<String> {
+    let url = url.trim().trim_end_matches('/');
+
+    if url.starts_with("file://") {
+        return options.allow_local.then(|| url.to_owned());
+    }
+    if url.starts_with("git@") {
+        return options.allow_ssh.then(|| url.to_owned());
+    }
+
+    let (scheme, rest) = url.split_once("://")?;
+    if scheme != "https" && scheme != "http" {
+        return None;
+    }
+    let rest = rest.split(['?', '#']).next()?;
+    let (host, path) = rest.split_once('/')?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    if GIT_HOSTS.contains(&host) {
+        let segments: Vec<_> = path
+            .split('/')
+            .take_while(|s| !s.is_empty() && *s != "-")
+            .collect();
+        let segments = match host {
+            // GitLab allows nested groups, with repository pages behind a `-` segment
+            "gitlab.com" => &segments[..],
+            _ => &segments[..segments.len().min(2)],
+        };
+        if segments.len() < 2 {
+            return None;
+        }
+
+        Some(format!("https://{host}/{}", segments.join("/")))
+    } else if path.ends_with(".git") {
+        Some(format!("{scheme}://{host}/{path}"))
+    } else {
+        None
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or(anyhow!("Unable to convert path to string"))
+}
+
+/// Run git in `directory`, returning its trimmed standard output.
+async fn git(directory: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        // Fail instead of waiting on credentials for private repositories
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .context("Unable to run git")?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,529 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// The `[autograde.sandbox]` section of the config file.
+///
+/// CPU, memory and time limits are shared with [`crate::AutogradeConfig`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    pub enabled: bool,
+    /// Maximum number of processes the test command may have running at once
+    pub max_processes: u64,
+    /// Directory replaced with an empty writable tmpfs
+    pub scratch: PathBuf,
+    /// MiB available in the scratch directory
+    pub scratch_size: u64,
+    /// Directories inside hidden ones, such as the home directory, which the test command may
+    /// still read
+    pub expose: Vec<PathBuf>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_processes: 64,
+            scratch: PathBuf::from("/tmp"),
+            scratch_size: 64,
+            expose: Vec::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::apply;
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn apply(
+    _command: &mut std::process::Command,
+    _config: &SandboxConfig,
+    _directory: &std::path::Path,
+) -> anyhow::Result<()> {
+    anyhow::bail!("Sandboxing is only supported on Linux")
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::{ffi::OsStrExt, process::CommandExt},
+        path::{Path, PathBuf},
+        process::Command,
+        ptr::null,
+    };
+
+    use anyhow::{bail, Context, Result};
+    use libc::{c_void, sock_filter};
+
+    use super::SandboxConfig;
+
+    const MOUNT_ATTR_RDONLY: u64 = 0x1;
+    const OPEN_TREE_CLONE: libc::c_uint = 0x1;
+    const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x4;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Syscalls that could be used to undo the sandbox or affect the host.
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_mount_setattr,
+        libc::SYS_open_tree,
+        libc::SYS_move_mount,
+        libc::SYS_fsopen,
+        libc::SYS_fsmount,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_kexec_load,
+        libc::SYS_reboot,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_userfaultfd,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+    ];
+
+    /// Argument to the `mount_setattr` syscall, which `libc` does not define.
+    #[repr(C)]
+    struct MountAttr {
+        attr_set: u64,
+        attr_clr: u64,
+        propagation: u64,
+        userns_fd: u64,
+    }
+
+    /// A directory mounted again after the directories hiding it are.
+    struct BindMount {
+        path: CString,
+        /// Directories to create in the empty tmpfs hiding the path, ending with the path
+        mount_points: Vec<CString>,
+        /// The detached copy of the directory, opened before it is hidden
+        tree: libc::c_int,
+    }
+
+    /// Directories holding the grader's credentials, cache, other students' submissions and
+    /// sockets to services such as SSH agents, which are replaced with empty tmpfs mounts.
+    fn hidden_directories() -> Vec<PathBuf> {
+        let mut hidden: Vec<PathBuf> = [
+            dirs::home_dir(),
+            dirs::config_dir(),
+            dirs::data_dir(),
+            dirs::data_local_dir(),
+            dirs::cache_dir(),
+            dirs::runtime_dir(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|path| path.canonicalize().ok())
+        .filter(|path| path.is_dir() && path.parent().is_some())
+        .collect();
+        hidden.sort();
+        hidden.dedup();
+
+        // Directories inside others are already hidden
+        let outermost = hidden.clone();
+        hidden.retain(|path| !outermost.iter().any(|o| o != path && path.starts_with(o)));
+        hidden
+    }
+
+    /// The directories to create so that `path` can be mounted on once `covers` are mounted
+    /// over it.
+    fn mount_points(path: &Path, covers: &[&Path]) -> Result<Vec<CString>> {
+        let Some(cover) = covers
+            .iter()
+            .filter(|c| path.starts_with(c) && path != **c)
+            .max_by_key(|c| c.components().count())
+        else {
+            return Ok(Vec::new());
+        };
+        path.ancestors()
+            .take_while(|ancestor| ancestor != cover)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .map(|ancestor| Ok(CString::new(ancestor.as_os_str().as_bytes())?))
+            .collect()
+    }
+
+    /// Run the command in new user, mount, PID, network, IPC and UTS namespaces.
+    ///
+    /// Inside, every mount is read-only except `directory` and a fresh tmpfs over the scratch
+    /// directory, the grader's home, config, data and runtime directories are replaced with
+    /// empty ones, only the command's own processes are visible, there is no network access,
+    /// the number of processes is capped, and a seccomp filter denies syscalls which could
+    /// escape the sandbox.
+    pub fn apply(command: &mut Command, config: &SandboxConfig, directory: &Path) -> Result<()> {
+        if directory.starts_with(&config.scratch) {
+            bail!(
+                "Submission directory {:?} is hidden by the sandbox scratch directory {:?}",
+                directory,
+                config.scratch
+            );
+        }
+        let hidden = hidden_directories();
+        if let Some(path) = hidden.iter().find(|path| path.starts_with(directory)) {
+            bail!(
+                "Submission directory {:?} contains {:?}, which the sandbox hides",
+                directory,
+                path
+            );
+        }
+        let mut covers: Vec<&Path> = hidden.iter().map(PathBuf::as_path).collect();
+        let scratch_mount_points = mount_points(&config.scratch, &covers)?;
+        covers.push(&config.scratch);
+
+        // Everything the child needs is allocated here, as allocating after fork is unsafe
+        let mut binds = vec![BindMount {
+            path: CString::new(directory.as_os_str().as_bytes())?,
+            mount_points: mount_points(directory, &covers)?,
+            tree: -1,
+        }];
+        for path in &config.expose {
+            let path = path
+                .canonicalize()
+                .with_context(|| format!("Unable to expose {:?} in the sandbox", path))?;
+            binds.push(BindMount {
+                path: CString::new(path.as_os_str().as_bytes())?,
+                mount_points: mount_points(&path, &covers)?,
+                tree: -1,
+            });
+        }
+        let hidden = hidden
+            .iter()
+            .map(|path| CString::new(path.as_os_str().as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let directory = CString::new(directory.as_os_str().as_bytes())?;
+        let scratch = CString::new(config.scratch.as_os_str().as_bytes())?;
+        let scratch_options = CString::new(format!("size={}m,mode=1777", config.scratch_size))?;
+        // SAFETY: getting ids has no memory safety requirements
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let uid_map = format!("{uid} {uid} 1");
+        let gid_map = format!("{gid} {gid} 1");
+        let max_processes = config.max_processes as libc::rlim_t;
+        let filter = seccomp_filter();
+
+        // SAFETY: only async-signal-safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                check(libc::unshare(
+                    libc::CLONE_NEWUSER
+                        | libc::CLONE_NEWNS
+                        | libc::CLONE_NEWPID
+                        | libc::CLONE_NEWNET
+                        | libc::CLONE_NEWIPC
+                        | libc::CLONE_NEWUTS,
+                ))?;
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+                // Only children join the new PID namespace, so the command runs in one, and
+                // this process passes on how it exits
+                enter_pid_namespace()?;
+
+                // Keep mount changes from propagating back to the host
+                check(libc::mount(
+                    null(),
+                    c"/".as_ptr(),
+                    null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    null(),
+                ))?;
+                for bind in binds.iter_mut() {
+                    bind.tree = libc::syscall(
+                        libc::SYS_open_tree,
+                        libc::AT_FDCWD,
+                        bind.path.as_ptr(),
+                        OPEN_TREE_CLONE
+                            | libc::O_CLOEXEC as libc::c_uint
+                            | libc::AT_RECURSIVE as libc::c_uint,
+                    ) as libc::c_int;
+                    check(bind.tree)?;
+                }
+                // Processes outside the namespace, and their command lines, are not listed
+                check(libc::mount(
+                    c"proc".as_ptr(),
+                    c"/proc".as_ptr(),
+                    c"proc".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    null(),
+                ))?;
+                for path in &hidden {
+                    check(libc::mount(
+                        c"tmpfs".as_ptr(),
+                        path.as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NODEV,
+                        c"size=1m,mode=755".as_ptr() as *const c_void,
+                    ))?;
+                }
+                create_mount_points(&scratch_mount_points)?;
+                check(libc::mount(
+                    c"tmpfs".as_ptr(),
+                    scratch.as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV,
+                    scratch_options.as_ptr() as *const c_void,
+                ))?;
+                for bind in &binds {
+                    create_mount_points(&bind.mount_points)?;
+                    check(libc::syscall(
+                        libc::SYS_move_mount,
+                        bind.tree,
+                        c"".as_ptr(),
+                        libc::AT_FDCWD,
+                        bind.path.as_ptr(),
+                        MOVE_MOUNT_F_EMPTY_PATH,
+                    ) as libc::c_int)?;
+                    libc::close(bind.tree);
+                }
+                set_read_only(c"/", true)?;
+                set_read_only(&directory, false)?;
+                set_read_only(&scratch, false)?;
+                // The working directory was entered before the bind mount covered it
+                check(libc::chdir(directory.as_ptr()))?;
+
+                let limit = libc::rlimit {
+                    rlim_cur: max_processes,
+                    rlim_max: max_processes,
+                };
+                check(libc::setrlimit(libc::RLIMIT_NPROC, &limit))?;
+
+                let program = libc::sock_fprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr() as *mut sock_filter,
+                };
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+                check(libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ))?;
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fork into the PID namespace created by `unshare`, returning in a child of the
+    /// namespace's init process. This process and init wait for it, and exit the same way.
+    ///
+    /// The command is not init itself, as init ignores signals it has no handler for, such as
+    /// the `SIGABRT` of a failed assertion.
+    ///
+    /// Must only be called between fork and exec.
+    unsafe fn enter_pid_namespace() -> std::io::Result<()> {
+        let mut pipe = [0; 2];
+        check(libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC))?;
+        let [read_status, write_status] = pipe;
+
+        let init = libc::fork();
+        check(init)?;
+        if init == 0 {
+            libc::close(read_status);
+            // Everything in the namespace is killed along with init
+            check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0))?;
+            let command = libc::fork();
+            check(command)?;
+            if command == 0 {
+                libc::close(write_status);
+                return Ok(());
+            }
+
+            // Orphaned processes are reparented to init, which reaps them until the command
+            // exits, and then passes on how it did
+            close_other_files(write_status);
+            let status = wait_for(command, -1);
+            libc::write(
+                write_status,
+                &status as *const libc::c_int as *const c_void,
+                std::mem::size_of::<libc::c_int>(),
+            );
+            libc::_exit(0);
+        }
+
+        libc::close(write_status);
+        close_other_files(read_status);
+
+        let mut status: libc::c_int = 0;
+        let read = libc::read(
+            read_status,
+            &mut status as *mut libc::c_int as *mut c_void,
+            std::mem::size_of::<libc::c_int>(),
+        );
+        let init_status = wait_for(init, init);
+        if read != std::mem::size_of::<libc::c_int>() as isize {
+            status = init_status;
+        }
+
+        if libc::WIFSIGNALED(status) {
+            let signal = libc::WTERMSIG(status);
+            libc::signal(signal, libc::SIG_DFL);
+            libc::kill(libc::getpid(), signal);
+            libc::_exit(128 + signal);
+        }
+        libc::_exit(libc::WEXITSTATUS(status))
+    }
+
+    /// Close every file except the standard streams and `kept`, as the pipe reporting
+    /// whether exec succeeded must only be held by the command.
+    unsafe fn close_other_files(kept: libc::c_int) {
+        if kept > 3 {
+            libc::syscall(libc::SYS_close_range, 3, kept - 1, 0);
+        }
+        libc::syscall(libc::SYS_close_range, kept + 1, libc::c_uint::MAX, 0);
+    }
+
+    /// Wait for children matching `waited` until `pid` exits, returning its status.
+    unsafe fn wait_for(pid: libc::pid_t, waited: libc::pid_t) -> libc::c_int {
+        let mut status = 0;
+        loop {
+            match libc::waitpid(waited, &mut status, 0) {
+                exited if exited == pid => return status,
+                -1 if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted => {
+                    libc::_exit(127)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Create directories in a freshly mounted tmpfs, parents first.
+    fn create_mount_points(paths: &[CString]) -> std::io::Result<()> {
+        for path in paths {
+            // SAFETY: `path` is nul terminated
+            if unsafe { libc::mkdir(path.as_ptr(), 0o755) } == -1 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() != std::io::ErrorKind::AlreadyExists {
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check(result: libc::c_int) -> std::io::Result<()> {
+        if result == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    fn write_file(path: &CStr, contents: &[u8]) -> std::io::Result<()> {
+        // SAFETY: `path` is nul terminated and `contents` is valid for its length
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            check(fd)?;
+            let written = libc::write(fd, contents.as_ptr() as *const c_void, contents.len());
+            libc::close(fd);
+            if written == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make the mount at `path` and every mount below it read-only or writable.
+    fn set_read_only(path: &CStr, read_only: bool) -> std::io::Result<()> {
+        let attr = MountAttr {
+            attr_set: if read_only { MOUNT_ATTR_RDONLY } else { 0 },
+            attr_clr: if read_only { 0 } else { MOUNT_ATTR_RDONLY },
+            propagation: 0,
+            userns_fd: 0,
+        };
+
+        // SAFETY: `path` is nul terminated and `attr` is valid for its size
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                libc::AT_RECURSIVE,
+                &attr as *const MountAttr,
+                std::mem::size_of::<MountAttr>(),
+            )
+        };
+        check(result as libc::c_int)
+    }
+
+    /// A seccomp program returning `EPERM` for [`DENIED_SYSCALLS`] and killing processes
+    /// using a foreign architecture's syscall table.
+    fn seccomp_filter() -> Vec<sock_filter> {
+        const SYSCALL_NR: u32 = 0;
+        const ARCH: u32 = 4;
+
+        let statement = |code: u32, k: u32| sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |code: u32, k: u32, jt: u8, jf: u8| sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        };
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+
+        let mut filter = vec![
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, ARCH),
+            jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                AUDIT_ARCH,
+                1,
+                0,
+            ),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SYSCALL_NR),
+        ];
+        // The x32 ABI shares the x86_64 architecture but offsets its syscall numbers
+        #[cfg(target_arch = "x86_64")]
+        filter.extend([
+            jump(
+                libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+                0x4000_0000,
+                0,
+                1,
+            ),
+            statement(libc::BPF_RET | libc::BPF_K, deny),
+        ]);
+        for syscall in DENIED_SYSCALLS {
+            filter.extend([
+                jump(
+                    libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                    *syscall as u32,
+                    0,
+                    1,
+                ),
+                statement(libc::BPF_RET | libc::BPF_K, deny),
+            ]);
+        }
+        filter.push(statement(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ALLOW,
+        ));
+
+        filter
+    }
+}
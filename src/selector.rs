@@ -0,0 +1,136 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{api::get_paginated, Assignment, AssignmentRef, Config};
+
+/// Assignments given on the command line: one assignment by id or spec name, an inclusive
+/// range of ids such as `100-110`, or every assignment in an assignment group
+/// (`group:<ID>`) or module (`module:<ID>`).
+#[derive(Debug, Clone)]
+pub enum AssignmentSelector {
+    Assignment(AssignmentRef),
+    Range(u64, u64),
+    Group(u64),
+    Module(u64),
+}
+
+/// An item of a module, which may be an assignment, page, file or other content.
+#[derive(Debug, Clone, Deserialize)]
+struct ModuleItem {
+    #[serde(rename = "type")]
+    kind: String,
+    content_id: Option<u64>,
+}
+
+impl FromStr for AssignmentSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let id = |id: &str| {
+            id.parse()
+                .with_context(|| format!("Invalid id {id:?} in {s:?}"))
+        };
+        if let Some(group_id) = s.strip_prefix("group:") {
+            return Ok(AssignmentSelector::Group(id(group_id)?));
+        }
+        if let Some(module_id) = s.strip_prefix("module:") {
+            return Ok(AssignmentSelector::Module(id(module_id)?));
+        }
+        // Spec names may contain dashes too, so only two numbers make a range
+        if let Some((Ok(first), Ok(last))) = s
+            .split_once('-')
+            .map(|(first, last)| (first.parse(), last.parse()))
+        {
+            if first > last {
+                bail!("Assignment range {s:?} ends before it starts");
+            }
+            return Ok(AssignmentSelector::Range(first, last));
+        }
+
+        Ok(AssignmentSelector::Assignment(s.parse()?))
+    }
+}
+
+impl AssignmentSelector {
+    /// The ids of every selected assignment in the order given, without duplicates.
+    ///
+    /// Ranges only select assignments which exist in the course, and the course's assignments
+    /// are only requested if a range or group needs them.
+    pub async fn resolve(selectors: &[Self], config: &Config) -> Result<Vec<u64>> {
+        let mut assignments: Option<Vec<Assignment>> = None;
+        let mut ids = Vec::new();
+        for selector in selectors {
+            let before = ids.len();
+            match selector {
+                AssignmentSelector::Assignment(assignment) => ids.push(assignment.id()?),
+                AssignmentSelector::Module(module_id) => {
+                    let items: Vec<ModuleItem> = get_paginated(
+                        &format!(
+                            "{}/api/v1/courses/{}/modules/{module_id}/items",
+                            config.base_url, config.course_id
+                        ),
+                        &[],
+                        config,
+                    )
+                    .await
+                    .with_context(|| format!("Unable to get items of module {module_id}"))?;
+                    ids.extend(
+                        items
+                            .into_iter()
+                            .filter(|i| i.kind == "Assignment")
+                            .filter_map(|i| i.content_id),
+                    );
+                }
+                AssignmentSelector::Range(first, last) => {
+                    let selected = course_assignments(&mut assignments, config)
+                        .await?
+                        .iter()
+                        .filter(|a| (*first..=*last).contains(&a.id))
+                        .map(|a| a.id)
+                        .sorted();
+                    ids.extend(selected);
+                }
+                AssignmentSelector::Group(group_id) => {
+                    let selected = course_assignments(&mut assignments, config)
+                        .await?
+                        .iter()
+                        .filter(|a| a.assignment_group_id == Some(*group_id))
+                        .sorted_by_key(|a| (a.position, a.id))
+                        .map(|a| a.id);
+                    ids.extend(selected);
+                }
+            }
+            if ids.len() == before {
+                bail!("No assignments in the course match \"{selector}\"");
+            }
+        }
+
+        Ok(ids.into_iter().unique().collect())
+    }
+}
+
+impl std::fmt::Display for AssignmentSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssignmentSelector::Assignment(AssignmentRef::Id(id)) => write!(f, "{id}"),
+            AssignmentSelector::Assignment(AssignmentRef::Spec(name)) => write!(f, "{name}"),
+            AssignmentSelector::Range(first, last) => write!(f, "{first}-{last}"),
+            AssignmentSelector::Group(id) => write!(f, "group:{id}"),
+            AssignmentSelector::Module(id) => write!(f, "module:{id}"),
+        }
+    }
+}
+
+/// The course's assignments, requested the first time they are needed.
+async fn course_assignments<'a>(
+    assignments: &'a mut Option<Vec<Assignment>>,
+    config: &Config,
+) -> Result<&'a [Assignment]> {
+    if assignments.is_none() {
+        *assignments = Some(Assignment::list(config).await?);
+    }
+    Ok(assignments.as_deref().unwrap_or_default())
+}
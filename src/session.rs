@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{Assignment, Comment, Config, Criterion, Grade, StudentId, Submission};
+
+/// Grades entered by hand, saved locally so grading can be stopped and resumed before they
+/// are uploaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GradingSession {
+    pub assignment_id: u64,
+    pub entries: Vec<SessionEntry>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub user_id: StudentId,
+    pub score: Option<f32>,
+    #[serde(default)]
+    pub comment: String,
+    /// The rating chosen for each rubric criterion
+    #[serde(default)]
+    pub ratings: Vec<Option<usize>>,
+    /// Marked for another look
+    #[serde(default)]
+    pub flagged: bool,
+    #[serde(default)]
+    pub skipped: bool,
+    /// Whether the score and comment have been uploaded since they last changed
+    #[serde(default)]
+    pub uploaded: bool,
+}
+
+impl SessionEntry {
+    fn new(user_id: StudentId) -> Self {
+        Self {
+            user_id,
+            score: None,
+            comment: String::new(),
+            ratings: Vec::new(),
+            flagged: false,
+            skipped: false,
+            uploaded: false,
+        }
+    }
+
+    /// Choose a rating for a criterion, setting the score to the total of the chosen ratings.
+    pub fn rate(&mut self, rubric: &[Criterion], criterion: usize, rating: Option<usize>) {
+        self.ratings.resize(rubric.len(), None);
+        self.ratings[criterion] = rating;
+        self.score = Some(
+            rubric
+                .iter()
+                .zip(&self.ratings)
+                .filter_map(|(c, r)| Some(c.ratings.get((*r)?)?.points))
+                .sum(),
+        );
+        self.uploaded = false;
+    }
+
+    /// The comment to upload, followed by the chosen rubric ratings.
+    pub fn full_comment(&self, rubric: &[Criterion]) -> String {
+        let ratings = rubric.iter().zip(&self.ratings).filter_map(|(c, r)| {
+            let rating = c.ratings.get((*r)?)?;
+            Some(format!(
+                "{}: {} ({}/{})",
+                c.description, rating.description, rating.points, c.points
+            ))
+        });
+
+        std::iter::once(self.comment.trim().to_owned())
+            .chain(ratings)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl GradingSession {
+    /// The saved session for the assignment, or a new one if there is none.
+    pub fn load(assignment_id: u64) -> Result<Self> {
+        let path = dirs::data_local_dir()
+            .context("Unable to get data dir for system")?
+            .join("grading")
+            .join("sessions")
+            .join(format!("{assignment_id}.json"));
+
+        let mut session = if path.exists() {
+            info!("Resuming grading session {:?}", path);
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Unable to read grading session {:?}", path))?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Unable to parse grading session {:?}", path))?
+        } else {
+            Self {
+                assignment_id,
+                ..Default::default()
+            }
+        };
+        session.path = path;
+
+        Ok(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write grading session {:?}", self.path))
+    }
+
+    pub fn entry(&self, user_id: &StudentId) -> Option<&SessionEntry> {
+        self.entries.iter().find(|e| e.user_id == *user_id)
+    }
+
+    pub fn entry_mut(&mut self, user_id: &StudentId) -> &mut SessionEntry {
+        let index = match self.entries.iter().position(|e| e.user_id == *user_id) {
+            Some(index) => index,
+            None => {
+                self.entries.push(SessionEntry::new(user_id.clone()));
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[index]
+    }
+
+    /// Scores and comments entered since they were last uploaded, holding back those of
+    /// students flagged for another look.
+    pub fn pending(&self, rubric: &[Criterion]) -> (Vec<Grade>, Vec<Comment>) {
+        let pending = self.entries.iter().filter(|e| !e.uploaded && !e.flagged);
+        let grades = pending
+            .clone()
+            .filter_map(|e| {
+                Some(Grade {
+                    user_id: e.user_id.clone(),
+                    grade: e.score?,
+                })
+            })
+            .collect();
+        let comments = pending
+            .map(|e| Comment {
+                user_id: e.user_id.clone(),
+                comment: e.full_comment(rubric),
+            })
+            .filter(|c| !c.comment.is_empty())
+            .collect();
+
+        (grades, comments)
+    }
+
+    /// Upload pending scores and comments in one batch, returning how many students they were
+    /// for.
+    ///
+    /// On group assignments graded as a group, the grade of one member applies to the group.
+    pub async fn upload(&mut self, assignment: &Assignment, config: &Config) -> Result<usize> {
+        let rubric = assignment.rubric.as_deref().unwrap_or_default();
+        let (grades, comments) = self.pending(rubric);
+        let group_comment =
+            assignment.group_category_id.is_some() && !assignment.grade_group_students_individually;
+        Submission::update_grades_with_comments(
+            assignment.id,
+            &grades,
+            &comments,
+            group_comment,
+            config,
+        )
+        .await?;
+
+        let mut count = 0;
+        for entry in &mut self.entries {
+            let uploaded = grades.iter().any(|g| g.user_id == entry.user_id)
+                || comments.iter().any(|c| c.user_id == entry.user_id);
+            if uploaded {
+                entry.uploaded = true;
+                count += 1;
+            }
+        }
+        self.save()?;
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rating;
+
+    fn rubric() -> Vec<Criterion> {
+        let criterion = |description: &str, points: f32| Criterion {
+            description: description.to_owned(),
+            points,
+            ratings: [("Full", points), ("Partial", points / 2.0), ("None", 0.0)]
+                .map(|(description, points)| Rating {
+                    description: description.to_owned(),
+                    points,
+                })
+                .to_vec(),
+        };
+        vec![criterion("Correctness", 6.0), criterion("Style", 4.0)]
+    }
+
+    #[test]
+    fn ratings_add_up_to_the_score() {
+        let rubric = rubric();
+        let mut entry = SessionEntry::new(StudentId::User(101));
+        entry.uploaded = true;
+
+        entry.rate(&rubric, 1, Some(1));
+        assert_eq!(entry.score, Some(2.0));
+        assert!(!entry.uploaded);
+
+        entry.rate(&rubric, 0, Some(0));
+        assert_eq!(entry.score, Some(8.0));
+
+        entry.rate(&rubric, 1, None);
+        assert_eq!(entry.score, Some(6.0));
+        assert_eq!(entry.ratings, [Some(0), None]);
+    }
+
+    #[test]
+    fn full_comments_list_the_chosen_ratings() {
+        let rubric = rubric();
+        let mut entry = SessionEntry::new(StudentId::User(101));
+        assert_eq!(entry.full_comment(&rubric), "");
+
+        entry.rate(&rubric, 1, Some(1));
+        assert_eq!(entry.full_comment(&rubric), "Style: Partial (2/4)");
+
+        entry.comment = "  Good work  \n".to_owned();
+        entry.rate(&rubric, 0, Some(0));
+        assert_eq!(
+            entry.full_comment(&rubric),
+            "Good work\nCorrectness: Full (6/6)\nStyle: Partial (2/4)"
+        );
+    }
+
+    #[test]
+    fn pending_holds_back_uploaded_and_flagged_entries() {
+        let mut session = GradingSession::default();
+        for (id, uploaded, flagged) in [(101, false, false), (102, true, false), (103, false, true)]
+        {
+            let entry = session.entry_mut(&StudentId::User(id));
+            entry.score = Some(id as f32);
+            entry.uploaded = uploaded;
+            entry.flagged = flagged;
+        }
+        session.entry_mut(&StudentId::User(104)).comment = "Resubmit".to_owned();
+
+        let (grades, comments) = session.pending(&[]);
+
+        let graded: Vec<(StudentId, f32)> =
+            grades.into_iter().map(|g| (g.user_id, g.grade)).collect();
+        assert_eq!(graded, [(StudentId::User(101), 101.0)]);
+        let commented: Vec<(StudentId, String)> = comments
+            .into_iter()
+            .map(|c| (c.user_id, c.comment))
+            .collect();
+        assert_eq!(commented, [(StudentId::User(104), "Resubmit".to_owned())]);
+    }
+}
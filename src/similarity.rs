@@ -0,0 +1,751 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::StudentId;
+
+pub const DEFAULT_K: usize = 15;
+pub const DEFAULT_WINDOW: usize = 8;
+
+const C_LIKE_KEYWORDS: &[&str] = &[
+    "abstract",
+    "async",
+    "await",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "fn",
+    "for",
+    "func",
+    "function",
+    "go",
+    "if",
+    "impl",
+    "implements",
+    "import",
+    "int",
+    "interface",
+    "let",
+    "long",
+    "loop",
+    "match",
+    "mut",
+    "namespace",
+    "new",
+    "null",
+    "nullptr",
+    "package",
+    "private",
+    "protected",
+    "pub",
+    "public",
+    "return",
+    "short",
+    "static",
+    "struct",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "throws",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "typedef",
+    "union",
+    "unsigned",
+    "use",
+    "var",
+    "void",
+    "while",
+    "yield",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Source languages with their own comment syntax and keywords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// C, C++, Java, JavaScript, TypeScript, Go, C#, Kotlin and Swift
+    CLike,
+    /// C-like, except that a single quote may start a lifetime or loop label
+    Rust,
+    Python,
+}
+
+impl Language {
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "c" | "h" | "cc" | "cpp" | "hpp" | "cxx" | "java" | "js" | "jsx" | "ts" | "tsx"
+            | "go" | "cs" | "kt" | "swift" | "scala" => Some(Self::CLike),
+            "rs" => Some(Self::Rust),
+            "py" => Some(Self::Python),
+            _ => None,
+        }
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            Self::CLike | Self::Rust => C_LIKE_KEYWORDS,
+            Self::Python => PYTHON_KEYWORDS,
+        }
+    }
+
+    fn line_comment(&self) -> &'static str {
+        match self {
+            Self::CLike | Self::Rust => "//",
+            Self::Python => "#",
+        }
+    }
+}
+
+/// A normalized token and the line it started on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+/// Split source code into tokens, dropping whitespace and comments.
+///
+/// Identifiers other than keywords become `ID`, string literals become `STR` and numbers
+/// become `NUM`, so that renaming variables or changing constants does not hide copying.
+pub fn tokenize(source: &str, language: Language) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let line_comment: Vec<char> = language.line_comment().chars().collect();
+    let keywords = language.keywords();
+
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start_line = line;
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if chars[i..].starts_with(&line_comment) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if language != Language::Python && chars[i..].starts_with(&['/', '*']) {
+            i += 2;
+            while i < chars.len() && !chars[i..].starts_with(&['*', '/']) {
+                line += (chars[i] == '\n') as usize;
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' && language == Language::Rust && !char_literal(&chars[i..]) {
+            // A lifetime or loop label, followed by its name
+            i += 1;
+            tokens.push(Token {
+                text: c.to_string(),
+                line: start_line,
+            });
+        } else if c == '"' || c == '\'' || c == '`' {
+            // Python's triple quoted strings become three string tokens, which is the same in
+            // every submission
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                line += (chars[i] == '\n') as usize;
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i += 1;
+            tokens.push(Token {
+                text: "STR".to_owned(),
+                line: start_line,
+            });
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token {
+                text: "NUM".to_owned(),
+                line: start_line,
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let text = if keywords.contains(&word.as_str()) {
+                word
+            } else {
+                "ID".to_owned()
+            };
+            tokens.push(Token {
+                text,
+                line: start_line,
+            });
+        } else {
+            i += 1;
+            tokens.push(Token {
+                text: c.to_string(),
+                line: start_line,
+            });
+        }
+    }
+
+    tokens
+}
+
+/// Whether the quote starting `chars` opens a character literal, such as `'a'` or `'\n'`,
+/// rather than a lifetime like `'a`.
+fn char_literal(chars: &[char]) -> bool {
+    chars.get(1) == Some(&'\\') || chars.get(2) == Some(&'\'')
+}
+
+/// Hash of a sequence of `k` tokens, and the lines it spans.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub hash: u64,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+/// Select fingerprints from the hashes of every `k` token sequence by winnowing, keeping the
+/// smallest hash in each window of `window` consecutive hashes.
+///
+/// Any match of at least `k + window - 1` tokens is guaranteed to share a fingerprint.
+pub fn winnow(tokens: &[Token], k: usize, window: usize) -> Vec<Fingerprint> {
+    let k = k.max(1);
+    let window = window.max(1);
+    if tokens.len() < k {
+        return Vec::new();
+    }
+
+    let grams: Vec<Fingerprint> = tokens
+        .windows(k)
+        .map(|gram| Fingerprint {
+            hash: fnv1a(gram.iter().map(|t| t.text.as_str())),
+            start_line: gram[0].line,
+            end_line: gram[k - 1].line,
+        })
+        .collect();
+
+    let mut selected: Vec<Fingerprint> = Vec::new();
+    let mut last_selected = None;
+    for (start, hashes) in grams.windows(window.min(grams.len())).enumerate() {
+        // The rightmost minimum, so that a minimum shared by overlapping windows is only
+        // selected once
+        let (offset, fingerprint) = hashes
+            .iter()
+            .enumerate()
+            .rev()
+            .min_by_key(|(_, f)| f.hash)
+            .expect("windows are never empty");
+        if last_selected != Some(start + offset) {
+            last_selected = Some(start + offset);
+            selected.push(*fingerprint);
+        }
+    }
+
+    selected
+}
+
+fn fnv1a<'a>(parts: impl Iterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Options for comparing submissions.
+#[derive(Debug, Clone, Copy)]
+pub struct SimilarityOptions {
+    /// Number of tokens in each fingerprinted sequence
+    pub k: usize,
+    /// Number of consecutive sequences fingerprints are selected from
+    pub window: usize,
+    /// Minimum similarity for a pair to be included in the report
+    pub threshold: f32,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        Self {
+            k: DEFAULT_K,
+            window: DEFAULT_WINDOW,
+            threshold: 0.1,
+        }
+    }
+}
+
+/// The source files of one student's submission and their fingerprints.
+#[derive(Debug)]
+struct Document {
+    user_id: StudentId,
+    files: Vec<SourceFile>,
+    /// Fingerprints keyed by hash, with the index of the file each came from
+    fingerprints: HashMap<u64, Vec<(usize, Fingerprint)>>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    contents: String,
+}
+
+impl Document {
+    fn read(user_id: StudentId, directory: &Path, options: &SimilarityOptions) -> Result<Self> {
+        let mut document = Self {
+            user_id,
+            files: Vec::new(),
+            fingerprints: HashMap::new(),
+        };
+
+        for path in source_files(directory)? {
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                warn!("Skipping {:?}, which is not UTF-8", path);
+                continue;
+            };
+            let language = Language::detect(&path).expect("only source files are listed");
+            let tokens = tokenize(&contents, language);
+
+            let index = document.files.len();
+            for fingerprint in winnow(&tokens, options.k, options.window) {
+                document
+                    .fingerprints
+                    .entry(fingerprint.hash)
+                    .or_default()
+                    .push((index, fingerprint));
+            }
+            document.files.push(SourceFile {
+                path: path.strip_prefix(directory).unwrap_or(&path).to_owned(),
+                contents,
+            });
+        }
+
+        Ok(document)
+    }
+}
+
+/// Source files below `directory`, skipping hidden files and directories such as `.git`.
+fn source_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_owned()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+            } else if Language::detect(&path).is_some() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Lines of a file matching another submission.
+#[derive(Debug, Clone, Serialize)]
+pub struct Span {
+    pub file: PathBuf,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Match {
+    pub first: Span,
+    pub second: Span,
+}
+
+/// Two students whose submissions share fingerprints.
+#[derive(Debug, Clone, Serialize)]
+pub struct Pair {
+    pub first: StudentId,
+    pub second: StudentId,
+    /// Shared fingerprints as a fraction of the smaller submission's fingerprints
+    pub similarity: f32,
+    pub shared_fingerprints: usize,
+    pub matches: Vec<Match>,
+}
+
+/// Pairs of similar submissions, from most to least similar.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarityReport {
+    pub pairs: Vec<Pair>,
+    /// Source lines of each file with a match, keyed by student and then path
+    #[serde(skip)]
+    sources: BTreeMap<StudentId, BTreeMap<PathBuf, String>>,
+}
+
+impl SimilarityReport {
+    /// Compare every pair of students' submissions, given as user ids and the directories
+    /// their files were downloaded to.
+    ///
+    /// Fingerprints also found in `starter` are ignored, so that code handed out to every
+    /// student is not reported as a match.
+    pub fn compare(
+        submissions: &[(StudentId, PathBuf)],
+        starter: Option<&Path>,
+        options: &SimilarityOptions,
+    ) -> Result<Self> {
+        let ignored: HashSet<u64> = match starter {
+            Some(starter) => Document::read(StudentId::User(0), starter, options)?
+                .fingerprints
+                .into_keys()
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let mut documents = Vec::new();
+        for (user_id, directory) in submissions {
+            let mut document = Document::read(user_id.clone(), directory, options)?;
+            document.fingerprints.retain(|h, _| !ignored.contains(h));
+            info!("Fingerprinted {} files for {user_id}", document.files.len());
+            documents.push(document);
+        }
+
+        let mut pairs: Vec<_> = documents
+            .iter()
+            .tuple_combinations()
+            .filter_map(|(first, second)| compare_documents(first, second))
+            .filter(|pair| pair.similarity >= options.threshold)
+            .collect();
+        pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+
+        // Keep the contents of matched files to show excerpts from
+        let matched: HashSet<(&StudentId, &PathBuf)> = pairs
+            .iter()
+            .flat_map(|p| {
+                p.matches
+                    .iter()
+                    .flat_map(|m| [(&p.first, &m.first.file), (&p.second, &m.second.file)])
+            })
+            .collect();
+        let mut sources: BTreeMap<StudentId, BTreeMap<PathBuf, String>> = BTreeMap::new();
+        for document in &documents {
+            for file in &document.files {
+                if matched.contains(&(&document.user_id, &file.path)) {
+                    sources
+                        .entry(document.user_id.clone())
+                        .or_default()
+                        .insert(file.path.clone(), file.contents.clone());
+                }
+            }
+        }
+
+        Ok(Self { pairs, sources })
+    }
+
+    /// Render the report as a standalone HTML page with the matched lines of each pair.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Similarity Report</title>\n<style>\n\
+             body { font-family: sans-serif; }\n\
+             table { border-collapse: collapse; }\n\
+             td, th { border: 1px solid #ccc; padding: 0.25em 0.5em; }\n\
+             .match { display: flex; gap: 1em; }\n\
+             .match > div { flex: 1; overflow-x: auto; }\n\
+             pre { background: #f6f6f6; padding: 0.5em; }\n\
+             </style>\n</head>\n<body>\n<h1>Similarity Report</h1>\n\
+             <table>\n<tr><th>Similarity</th><th>First</th><th>Second</th>\
+             <th>Shared fingerprints</th></tr>\n",
+        );
+        for (i, pair) in self.pairs.iter().enumerate() {
+            let _ = writeln!(
+                html,
+                "<tr><td><a href=\"#pair-{i}\">{:.1}%</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                pair.similarity * 100.0,
+                escape_html(&pair.first.to_string()),
+                escape_html(&pair.second.to_string()),
+                pair.shared_fingerprints
+            );
+        }
+        html.push_str("</table>\n");
+
+        for (i, pair) in self.pairs.iter().enumerate() {
+            let _ = writeln!(
+                html,
+                "<h2 id=\"pair-{i}\">{} and {} ({:.1}%)</h2>",
+                escape_html(&pair.first.to_string()),
+                escape_html(&pair.second.to_string()),
+                pair.similarity * 100.0
+            );
+            for m in &pair.matches {
+                html.push_str("<div class=\"match\">\n");
+                for (user_id, span) in [(&pair.first, &m.first), (&pair.second, &m.second)] {
+                    let _ = writeln!(
+                        html,
+                        "<div><h3>{}: {} lines {}-{}</h3>\n<pre>{}</pre></div>",
+                        escape_html(&user_id.to_string()),
+                        escape_html(&span.file.to_string_lossy()),
+                        span.start_line,
+                        span.end_line,
+                        escape_html(&self.excerpt(user_id, span))
+                    );
+                }
+                html.push_str("</div>\n");
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+
+        html
+    }
+
+    fn excerpt(&self, user_id: &StudentId, span: &Span) -> String {
+        self.sources
+            .get(user_id)
+            .and_then(|files| files.get(&span.file))
+            .map(|contents| {
+                contents
+                    .lines()
+                    .enumerate()
+                    .skip(span.start_line.saturating_sub(1))
+                    .take(span.end_line + 1 - span.start_line)
+                    .map(|(i, line)| format!("{:>5} {line}", i + 1))
+                    .join("\n")
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn compare_documents(first: &Document, second: &Document) -> Option<Pair> {
+    let shared: Vec<u64> = first
+        .fingerprints
+        .keys()
+        .filter(|h| second.fingerprints.contains_key(h))
+        .copied()
+        .collect();
+    if shared.is_empty() {
+        return None;
+    }
+    let smaller = first.fingerprints.len().min(second.fingerprints.len());
+
+    // Pair up the first occurrence of each shared fingerprint, then merge runs of
+    // fingerprints which are adjacent in both files into a single match
+    let mut occurrences: Vec<Match> = shared
+        .iter()
+        .map(|h| {
+            let (first_file, a) = first.fingerprints[h][0];
+            let (second_file, b) = second.fingerprints[h][0];
+            Match {
+                first: span(first, first_file, &a),
+                second: span(second, second_file, &b),
+            }
+        })
+        .collect();
+    occurrences.sort_by(|a, b| {
+        (
+            &a.first.file,
+            a.first.start_line,
+            &a.second.file,
+            a.second.start_line,
+        )
+            .cmp(&(
+                &b.first.file,
+                b.first.start_line,
+                &b.second.file,
+                b.second.start_line,
+            ))
+    });
+
+    let mut matches: Vec<Match> = Vec::new();
+    for occurrence in occurrences {
+        if let Some(last) = matches.last_mut() {
+            if adjacent(&last.first, &occurrence.first)
+                && adjacent(&last.second, &occurrence.second)
+            {
+                last.first.end_line = last.first.end_line.max(occurrence.first.end_line);
+                last.second.start_line = last.second.start_line.min(occurrence.second.start_line);
+                last.second.end_line = last.second.end_line.max(occurrence.second.end_line);
+                continue;
+            }
+        }
+        matches.push(occurrence);
+    }
+
+    Some(Pair {
+        first: first.user_id.clone(),
+        second: second.user_id.clone(),
+        similarity: shared.len() as f32 / smaller as f32,
+        shared_fingerprints: shared.len(),
+        matches,
+    })
+}
+
+fn span(document: &Document, file: usize, fingerprint: &Fingerprint) -> Span {
+    Span {
+        file: document.files[file].path.clone(),
+        start_line: fingerprint.start_line,
+        end_line: fingerprint.end_line,
+    }
+}
+
+/// Whether `next` starts within or directly after `span` in the same file.
+fn adjacent(span: &Span, next: &Span) -> bool {
+    span.file == next.file
+        && next.start_line + 1 >= span.start_line
+        && next.start_line <= span.end_line + 1
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source: &str, language: Language) -> Vec<String> {
+        tokenize(source, language)
+            .into_iter()
+            .map(|t| t.text)
+            .collect()
+    }
+
+    fn tokens(texts: &[&str]) -> Vec<Token> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(line, text)| Token {
+                text: text.to_string(),
+                line: line + 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokens_are_normalized_without_comments() {
+        let source = "// sum\nint total = add(x, 42); /* the\nanswer */\nputs(\"done\");\n";
+
+        assert_eq!(
+            texts(source, Language::CLike),
+            ["int", "ID", "=", "ID", "(", "ID", ",", "NUM", ")", ";", "ID", "(", "STR", ")", ";"]
+        );
+        let lines: Vec<usize> = tokenize(source, Language::CLike)
+            .iter()
+            .map(|t| t.line)
+            .collect();
+        assert_eq!(lines, [2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 4, 4, 4, 4, 4]);
+        assert_eq!(
+            texts(
+                "def f(s): # comment\n    return s + 'x'\n",
+                Language::Python
+            ),
+            ["def", "ID", "(", "ID", ")", ":", "return", "ID", "+", "STR"]
+        );
+    }
+
+    #[test]
+    fn lifetimes_are_not_char_literals() {
+        assert_eq!(
+            texts("fn f<'a>(s: &'a str) -> char { '\\'' }", Language::Rust),
+            [
+                "fn", "ID", "<", "'", "ID", ">", "(", "ID", ":", "&", "'", "ID", "ID", ")", "-",
+                ">", "char", "{", "STR", "}"
+            ]
+        );
+        assert_eq!(
+            texts("'outer: loop { break 'outer; }", Language::Rust),
+            ["'", "ID", ":", "loop", "{", "break", "'", "ID", ";", "}"]
+        );
+        assert_eq!(texts("x = 'a'", Language::Rust), ["ID", "=", "STR"]);
+    }
+
+    #[test]
+    fn single_quotes_are_strings_outside_rust() {
+        assert_eq!(
+            texts("x = 'http://a'; y", Language::CLike),
+            ["ID", "=", "STR", ";", "ID"]
+        );
+        assert_eq!(
+            texts("x = 'it\"s'; y", Language::CLike),
+            ["ID", "=", "STR", ";", "ID"]
+        );
+        assert_eq!(Language::detect(Path::new("main.rs")), Some(Language::Rust));
+        assert_eq!(Language::detect(Path::new("app.js")), Some(Language::CLike));
+    }
+
+    #[test]
+    fn short_inputs_have_no_fingerprints() {
+        assert!(winnow(&tokens(&["a", "b"]), 3, 2).is_empty());
+        assert_eq!(winnow(&tokens(&["a", "b", "c"]), 3, 4).len(), 1);
+    }
+
+    #[test]
+    fn shared_sequences_share_a_fingerprint() {
+        let shared = ["if", "(", "ID", ")", "{", "return", "NUM", ";", "}"];
+        let first: Vec<&str> = ["int", "ID", ";"].iter().chain(&shared).copied().collect();
+        let second: Vec<&str> = shared
+            .iter()
+            .chain(&["while", "(", "ID", ")"])
+            .copied()
+            .collect();
+
+        // `shared` is at least `k + window - 1` tokens long
+        let first = winnow(&tokens(&first), 4, 5);
+        let second = winnow(&tokens(&second), 4, 5);
+        assert!(first
+            .iter()
+            .any(|f| second.iter().any(|s| s.hash == f.hash)));
+        assert!(first.iter().all(|f| f.end_line - f.start_line == 3));
+    }
+
+    #[test]
+    fn report_escapes_student_ids() {
+        let report = SimilarityReport {
+            pairs: vec![Pair {
+                first: StudentId::Anonymous("<script>".to_owned()),
+                second: StudentId::User(101),
+                similarity: 0.5,
+                shared_fingerprints: 1,
+                matches: vec![],
+            }],
+            sources: BTreeMap::new(),
+        };
+
+        let html = report.to_html();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt; and 101"));
+    }
+}
@@ -0,0 +1,477 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{html, Options, Parser};
+use serde::{Deserialize, Deserializer};
+use tracing::info;
+
+use crate::{parse_date, Assignment, AssignmentOverride, Config, OverrideDates, OverrideTarget};
+
+/// An assignment kept as code in a TOML file, which `assignment apply` makes Canvas match.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssignmentSpec {
+    /// Written back once the assignment has been created
+    pub assignment_id: Option<u64>,
+    pub name: String,
+    /// Markdown, converted to HTML for Canvas
+    #[serde(default)]
+    pub description: String,
+    pub points: Option<f32>,
+    /// Such as `online_upload`, `online_url` or `none`, left as they are if empty
+    #[serde(default)]
+    pub submission_types: Vec<String>,
+    /// File extensions accepted for uploads, or any if empty
+    #[serde(default)]
+    pub allowed_extensions: Vec<String>,
+    #[serde(default, deserialize_with = "date")]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "date")]
+    pub unlock_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "date")]
+    pub lock_at: Option<DateTime<Utc>>,
+    /// Dates which differ for some sections
+    #[serde(default)]
+    pub sections: Vec<SectionDates>,
+    #[serde(default)]
+    pub rubric: Vec<Criterion>,
+    /// Makes this a group assignment
+    pub group_category_id: Option<u64>,
+    #[serde(default)]
+    pub published: bool,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SectionDates {
+    pub section_id: u64,
+    #[serde(default, deserialize_with = "date")]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "date")]
+    pub unlock_at: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "date")]
+    pub lock_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Criterion {
+    pub description: String,
+    pub points: f32,
+    #[serde(default)]
+    pub ratings: Vec<Rating>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rating {
+    pub description: String,
+    pub points: f32,
+}
+
+/// Dates in specs are written like those given to `extend`.
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|date| parse_date(&date).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// An assignment given on the command line, by id or by the name of its spec.
+#[derive(Debug, Clone)]
+pub enum AssignmentRef {
+    Id(u64),
+    Spec(String),
+}
+
+impl FromStr for AssignmentRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.parse() {
+            Ok(id) => AssignmentRef::Id(id),
+            Err(_) => AssignmentRef::Spec(s.to_owned()),
+        })
+    }
+}
+
+impl AssignmentRef {
+    /// The Canvas assignment id, read from the spec if referenced by name.
+    pub fn id(&self) -> Result<u64> {
+        match self {
+            AssignmentRef::Id(id) => Ok(*id),
+            AssignmentRef::Spec(name) => {
+                AssignmentSpec::find(name)?.assignment_id.with_context(|| {
+                    format!("Assignment spec {name:?} has not been applied to Canvas yet")
+                })
+            }
+        }
+    }
+}
+
+impl AssignmentSpec {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read assignment spec {:?}", path))?;
+        let mut spec: Self = toml::from_str(&contents)
+            .with_context(|| format!("Unable to parse assignment spec {:?}", path))?;
+        spec.path = path.to_owned();
+
+        Ok(spec)
+    }
+
+    /// The spec at `name` if it is a path to a file, or else `assignments/<NAME>.toml` in the
+    /// config directory.
+    pub fn find(name: &str) -> Result<Self> {
+        let path = Path::new(name);
+        if path.is_file() {
+            return Self::read(path);
+        }
+
+        let path = dirs::config_dir()
+            .context("Unable to get config dir for system")?
+            .join("grading")
+            .join("assignments")
+            .join(format!("{name}.toml"));
+        info!("Using assignment spec {:?}", path);
+        Self::read(&path)
+    }
+
+    /// Describe each difference between the spec and the assignment in Canvas, or everything
+    /// in the spec if the assignment does not exist yet.
+    pub fn diff(
+        &self,
+        current: Option<&Assignment>,
+        overrides: &[AssignmentOverride],
+    ) -> Vec<String> {
+        let mut diff = Vec::new();
+        let mut field = |name: &str, old: String, new: String| {
+            if old != new {
+                diff.push(format!("{name}: {old} -> {new}"));
+            }
+        };
+
+        field(
+            "name",
+            show(current.map(|a| &a.name)),
+            show(Some(&self.name)),
+        );
+        // Canvas sanitizes the HTML it is given, so only the text is compared
+        let description = current.and_then(|a| a.description.as_deref());
+        if html_text(description.unwrap_or_default()) != html_text(&self.description_html()) {
+            field(
+                "description",
+                if description.is_some() { "old" } else { "none" }.to_owned(),
+                "new".to_owned(),
+            );
+        }
+        field(
+            "points",
+            show(current.and_then(|a| a.points_possible)),
+            show(self.points),
+        );
+        if !self.submission_types.is_empty() {
+            field(
+                "submission types",
+                show(current.map(|a| sorted(&a.submission_types))),
+                show(Some(sorted(&self.submission_types))),
+            );
+        }
+        field(
+            "allowed extensions",
+            show(current.map(|a| sorted(&a.allowed_extensions))),
+            show(Some(sorted(&self.allowed_extensions))),
+        );
+        field(
+            "due",
+            show(current.and_then(|a| a.due_at)),
+            show(self.due_at),
+        );
+        field(
+            "unlock",
+            show(current.and_then(|a| a.unlock_at)),
+            show(self.unlock_at),
+        );
+        field(
+            "lock",
+            show(current.and_then(|a| a.lock_at)),
+            show(self.lock_at),
+        );
+        field(
+            "group category",
+            show(current.and_then(|a| a.group_category_id)),
+            show(self.group_category_id),
+        );
+        field(
+            "published",
+            show(current.map(|a| a.published)),
+            show(Some(self.published)),
+        );
+
+        for section in &self.sections {
+            let existing = overrides
+                .iter()
+                .find(|o| o.course_section_id == Some(section.section_id));
+            field(
+                &format!("section {} dates", section.section_id),
+                existing
+                    .map(|o| dates(o.unlock_at, o.due_at, o.lock_at))
+                    .unwrap_or_else(|| "none".to_owned()),
+                dates(section.unlock_at, section.due_at, section.lock_at),
+            );
+        }
+
+        let rubric = current
+            .and_then(|a| a.rubric.as_deref())
+            .unwrap_or_default();
+        if !self.rubric.is_empty() && rubric != self.rubric {
+            field(
+                "rubric",
+                format!("{} criteria", rubric.len()),
+                format!("{} criteria", self.rubric.len()),
+            );
+        }
+
+        diff
+    }
+
+    /// Create or update the assignment, its section dates and its rubric, writing the id of
+    /// a newly created assignment back to the spec.
+    pub async fn apply(&mut self, config: &Config) -> Result<Assignment> {
+        let form = self.form();
+        let assignment = match self.assignment_id {
+            Some(assignment_id) => Assignment::update(assignment_id, &form, config).await?,
+            None => {
+                let assignment = Assignment::create(&form, config).await?;
+                self.write_assignment_id(assignment.id)?;
+                assignment
+            }
+        };
+
+        for section in &self.sections {
+            let dates = OverrideDates {
+                unlock_at: section.unlock_at,
+                due_at: section.due_at,
+                lock_at: section.lock_at,
+            };
+            AssignmentOverride::extend(
+                assignment.id,
+                &OverrideTarget::Section(section.section_id),
+                None,
+                &dates,
+                config,
+            )
+            .await?;
+        }
+
+        let rubric = assignment.rubric.as_deref().unwrap_or_default();
+        if !self.rubric.is_empty() && rubric != self.rubric {
+            assignment.set_rubric(&self.rubric, config).await?;
+        }
+
+        Ok(assignment)
+    }
+
+    fn description_html(&self) -> String {
+        let mut description = String::new();
+        html::push_html(
+            &mut description,
+            Parser::new_ext(&self.description, Options::ENABLE_TABLES),
+        );
+        description
+    }
+
+    fn form(&self) -> Vec<(String, String)> {
+        let mut form = vec![
+            ("name", self.name.clone()),
+            ("description", self.description_html()),
+            ("published", self.published.to_string()),
+        ];
+        if let Some(points) = self.points {
+            form.push(("points_possible", points.to_string()));
+        }
+        if let Some(group_category_id) = self.group_category_id {
+            form.push(("group_category_id", group_category_id.to_string()));
+        }
+        // Canvas clears dates given as empty strings
+        for (key, date) in [
+            ("due_at", self.due_at),
+            ("unlock_at", self.unlock_at),
+            ("lock_at", self.lock_at),
+        ] {
+            form.push((key, date.map(|d| d.to_rfc3339()).unwrap_or_default()));
+        }
+
+        let mut form: Vec<(String, String)> = form
+            .into_iter()
+            .map(|(key, value)| (format!("assignment[{key}]"), value))
+            .collect();
+        for submission_type in &self.submission_types {
+            form.push((
+                "assignment[submission_types][]".to_owned(),
+                submission_type.clone(),
+            ));
+        }
+        for extension in &self.allowed_extensions {
+            form.push((
+                "assignment[allowed_extensions][]".to_owned(),
+                extension.clone(),
+            ));
+        }
+        form
+    }
+
+    /// Record the id in the spec file, keeping its formatting and comments.
+    fn write_assignment_id(&mut self, assignment_id: u64) -> Result<()> {
+        let contents = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Unable to read assignment spec {:?}", self.path))?;
+        let mut document: toml_edit::DocumentMut = contents
+            .parse()
+            .with_context(|| format!("Unable to parse assignment spec {:?}", self.path))?;
+        let Ok(id) = i64::try_from(assignment_id) else {
+            bail!("Assignment id {assignment_id} does not fit in TOML");
+        };
+        document["assignment_id"] = toml_edit::value(id);
+        std::fs::write(&self.path, document.to_string())
+            .with_context(|| format!("Unable to write assignment spec {:?}", self.path))?;
+
+        info!("Wrote assignment id {assignment_id} to {:?}", self.path);
+        self.assignment_id = Some(assignment_id);
+        Ok(())
+    }
+}
+
+fn show<T: Debug>(value: Option<T>) -> String {
+    match value {
+        Some(value) => format!("{value:?}"),
+        None => "none".to_owned(),
+    }
+}
+
+fn dates(
+    unlock_at: Option<DateTime<Utc>>,
+    due_at: Option<DateTime<Utc>>,
+    lock_at: Option<DateTime<Utc>>,
+) -> String {
+    format!(
+        "unlock {}, due {}, lock {}",
+        show(unlock_at),
+        show(due_at),
+        show(lock_at)
+    )
+}
+
+/// The text of some HTML without its tags or whitespace.
+fn html_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+fn sorted(values: &[String]) -> Vec<&str> {
+    let mut values: Vec<&str> = values.iter().map(String::as_str).collect();
+    values.sort_unstable();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const SPEC: &str = r#"
+# Due the week after the lab
+name = "Essay"
+description = "Write **300 words** about \"testing\"."
+points = 10
+submission_types = ["online_upload"]
+allowed_extensions = ["pdf", "docx"]
+due_at = "2025-03-11T00:00:00Z"
+"#;
+
+    fn spec(directory: &Path) -> AssignmentSpec {
+        let path = directory.join("essay.toml");
+        std::fs::write(&path, SPEC).unwrap();
+        AssignmentSpec::read(&path).unwrap()
+    }
+
+    #[test]
+    fn diff_ignores_how_canvas_formats_the_description() {
+        let directory = tempfile::tempdir().unwrap();
+        let spec = spec(directory.path());
+        let mut current: Assignment = serde_json::from_value(json!({
+            "id": 10,
+            "name": "Essay",
+            "description": "<p>Write <strong>300 words</strong> about \"testing\".</p>",
+            "points_possible": 10.0,
+            "submission_types": ["online_upload"],
+            "allowed_extensions": ["docx", "pdf"],
+            "due_at": "2025-03-11T00:00:00Z",
+        }))
+        .unwrap();
+        assert_eq!(spec.diff(Some(&current), &[]), Vec::<String>::new());
+
+        current.description = Some("<p>Write 200 words.</p>".to_owned());
+        current.points_possible = Some(5.0);
+        assert_eq!(
+            spec.diff(Some(&current), &[]),
+            ["description: old -> new", "points: 5.0 -> 10.0"]
+        );
+        assert!(spec
+            .diff(None, &[])
+            .contains(&"name: none -> \"Essay\"".to_owned()));
+    }
+
+    #[test]
+    fn form_uses_canvas_array_keys() {
+        let directory = tempfile::tempdir().unwrap();
+        let form = spec(directory.path()).form();
+
+        let values = |key: &str| -> Vec<&str> {
+            form.iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .collect()
+        };
+        assert_eq!(values("assignment[name]"), ["Essay"]);
+        assert_eq!(values("assignment[points_possible]"), ["10"]);
+        assert_eq!(values("assignment[submission_types][]"), ["online_upload"]);
+        assert_eq!(values("assignment[allowed_extensions][]"), ["pdf", "docx"]);
+        assert_eq!(values("assignment[due_at]"), ["2025-03-11T00:00:00+00:00"]);
+        assert_eq!(values("assignment[lock_at]"), [""]);
+    }
+
+    #[test]
+    fn created_assignment_ids_are_written_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut spec = spec(directory.path());
+
+        spec.write_assignment_id(42).unwrap();
+        assert_eq!(spec.assignment_id, Some(42));
+        let contents = std::fs::read_to_string(directory.path().join("essay.toml")).unwrap();
+        assert!(contents.contains("# Due the week after the lab"));
+        let reread = AssignmentSpec::read(&directory.path().join("essay.toml")).unwrap();
+        assert_eq!(reread.assignment_id, Some(42));
+        assert_eq!(reread.name, "Essay");
+    }
+}
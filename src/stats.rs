@@ -0,0 +1,375 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::Submission;
+
+/// Width of the longest histogram bar in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Score statistics and submission counts of an assignment, for the whole course and then
+/// for each section.
+#[derive(Debug, Clone, Serialize)]
+pub struct GradeStats {
+    pub points_possible: Option<f32>,
+    pub sections: Vec<SectionStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SectionStats {
+    /// The section id, `all` for the whole course, or `none` for students without a section
+    pub section: String,
+    pub students: usize,
+    pub submitted: usize,
+    pub graded: usize,
+    pub missing: usize,
+    pub late: usize,
+    /// Statistics of the graded submissions' scores, if any are graded
+    pub scores: Option<ScoreSummary>,
+    pub histogram: Vec<Bin>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreSummary {
+    pub count: usize,
+    pub mean: f32,
+    pub median: f32,
+    /// Population standard deviation
+    pub std_dev: f32,
+    pub min: f32,
+    pub q1: f32,
+    pub q3: f32,
+    pub max: f32,
+}
+
+/// Scores from `from` up to but not including `to`, except for the last bin, which also
+/// holds scores of `to` and above.
+#[derive(Debug, Clone, Serialize)]
+pub struct Bin {
+    pub from: f32,
+    pub to: f32,
+    pub count: usize,
+}
+
+impl ScoreSummary {
+    pub fn new(scores: &[f32]) -> Option<Self> {
+        if scores.is_empty() {
+            return None;
+        }
+        let mut sorted = scores.to_vec();
+        sorted.sort_by(f32::total_cmp);
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f32>() / count as f32;
+        let variance = sorted.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / count as f32;
+
+        Some(Self {
+            count,
+            mean,
+            median: quantile(&sorted, 0.5),
+            std_dev: variance.sqrt(),
+            min: sorted[0],
+            q1: quantile(&sorted, 0.25),
+            q3: quantile(&sorted, 0.75),
+            max: sorted[count - 1],
+        })
+    }
+}
+
+/// Interpolate between the closest ranks of sorted values, as spreadsheets do.
+fn quantile(sorted: &[f32], q: f32) -> f32 {
+    let rank = q * (sorted.len() - 1) as f32;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+/// Count scores into `bins` bins of equal width from zero to `max`.
+fn histogram(scores: &[f32], max: f32, bins: usize) -> Vec<Bin> {
+    if bins == 0 || max <= 0.0 {
+        return Vec::new();
+    }
+    let width = max / bins as f32;
+    let mut histogram: Vec<Bin> = (0..bins)
+        .map(|i| Bin {
+            from: width * i as f32,
+            to: width * (i + 1) as f32,
+            count: 0,
+        })
+        .collect();
+    for score in scores {
+        let i = ((score / width).floor().max(0.0) as usize).min(bins - 1);
+        histogram[i].count += 1;
+    }
+    histogram
+}
+
+impl SectionStats {
+    fn new(section: String, submissions: &[&Submission], max: f32, bins: usize) -> Self {
+        let scores: Vec<f32> = submissions
+            .iter()
+            .filter(|s| s.graded())
+            .filter_map(|s| s.score())
+            .collect();
+
+        Self {
+            section,
+            students: submissions.len(),
+            submitted: submissions.iter().filter(|s| s.submitted()).count(),
+            graded: scores.len(),
+            missing: submissions.iter().filter(|s| s.missing()).count(),
+            late: submissions.iter().filter(|s| s.late()).count(),
+            scores: ScoreSummary::new(&scores),
+            histogram: histogram(&scores, max, bins),
+        }
+    }
+}
+
+impl GradeStats {
+    /// `sections` maps each student's user id to the sections they are enrolled in. Students
+    /// in several sections are counted in each.
+    pub fn new(
+        points_possible: Option<f32>,
+        submissions: &[Submission],
+        sections: &HashMap<u64, Vec<u64>>,
+        bins: usize,
+    ) -> Self {
+        // Without points possible, bins span up to the highest score
+        let max = points_possible.filter(|p| *p > 0.0).unwrap_or_else(|| {
+            submissions
+                .iter()
+                .filter_map(Submission::score)
+                .fold(0.0, f32::max)
+        });
+
+        let mut by_section: BTreeMap<Option<u64>, Vec<&Submission>> = BTreeMap::new();
+        for submission in submissions {
+            let ids = submission
+                .user()
+                .user_id()
+                .and_then(|id| sections.get(&id))
+                .filter(|ids| !ids.is_empty());
+            match ids {
+                Some(ids) => {
+                    for id in ids {
+                        by_section.entry(Some(*id)).or_default().push(submission);
+                    }
+                }
+                None => by_section.entry(None).or_default().push(submission),
+            }
+        }
+
+        let all: Vec<&Submission> = submissions.iter().collect();
+        let mut stats = vec![SectionStats::new("all".to_owned(), &all, max, bins)];
+        // Sections are sorted by id, with students without a section last
+        let (none, some): (Vec<_>, Vec<_>) =
+            by_section.into_iter().partition(|(id, _)| id.is_none());
+        for (id, submissions) in some.into_iter().chain(none) {
+            let name = id.map_or("none".to_owned(), |id| id.to_string());
+            stats.push(SectionStats::new(name, &submissions, max, bins));
+        }
+
+        Self {
+            points_possible,
+            sections: stats,
+        }
+    }
+
+    /// The course's score statistics and histogram, followed by a table of each section.
+    pub fn to_text(&self) -> String {
+        let all = &self.sections[0];
+        let mut text = String::new();
+
+        match &all.scores {
+            Some(s) => {
+                for (name, value) in [
+                    ("Count", s.count as f32),
+                    ("Mean", s.mean),
+                    ("Median", s.median),
+                    ("Std dev", s.std_dev),
+                    ("Min", s.min),
+                    ("Q1", s.q1),
+                    ("Q3", s.q3),
+                    ("Max", s.max),
+                ] {
+                    text.push_str(&format!("{name:<8} {}\n", round(value)));
+                }
+            }
+            None => text.push_str("No graded submissions\n"),
+        }
+
+        let most = all.histogram.iter().map(|b| b.count).max().unwrap_or(0);
+        if most > 0 {
+            text.push('\n');
+            for bin in &all.histogram {
+                let bar = "█".repeat((bin.count * BAR_WIDTH).div_ceil(most));
+                let range = format!("{} - {}", round(bin.from), round(bin.to));
+                text.push_str(&format!("{range:>15} | {bar} {}\n", bin.count));
+            }
+        }
+
+        text.push_str(&format!(
+            "\n{:<10} {:>8} {:>9} {:>6} {:>7} {:>4} {:>7} {:>7}\n",
+            "Section", "Students", "Submitted", "Graded", "Missing", "Late", "Mean", "Median"
+        ));
+        for section in &self.sections {
+            let (mean, median) = match &section.scores {
+                Some(s) => (round(s.mean).to_string(), round(s.median).to_string()),
+                None => ("-".to_owned(), "-".to_owned()),
+            };
+            text.push_str(&format!(
+                "{:<10} {:>8} {:>9} {:>6} {:>7} {:>4} {:>7} {:>7}\n",
+                section.section,
+                section.students,
+                section.submitted,
+                section.graded,
+                section.missing,
+                section.late,
+                mean,
+                median
+            ));
+        }
+
+        text
+    }
+
+    /// Write one row per section, with a column for the count of each histogram bin.
+    pub fn write_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
+        let mut header: Vec<String> = [
+            "section",
+            "students",
+            "submitted",
+            "graded",
+            "missing",
+            "late",
+            "count",
+            "mean",
+            "median",
+            "std_dev",
+            "min",
+            "q1",
+            "q3",
+            "max",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        header.extend(
+            self.sections[0]
+                .histogram
+                .iter()
+                .map(|b| format!("{}-{}", round(b.from), round(b.to))),
+        );
+        writer.write_record(&header)?;
+
+        for section in &self.sections {
+            let mut record = vec![
+                section.section.clone(),
+                section.students.to_string(),
+                section.submitted.to_string(),
+                section.graded.to_string(),
+                section.missing.to_string(),
+                section.late.to_string(),
+            ];
+            match &section.scores {
+                Some(s) => record.extend(
+                    [
+                        s.count as f32,
+                        s.mean,
+                        s.median,
+                        s.std_dev,
+                        s.min,
+                        s.q1,
+                        s.q3,
+                        s.max,
+                    ]
+                    .map(|v| round(v).to_string()),
+                ),
+                None => record.extend(std::iter::repeat_n(String::new(), 8)),
+            }
+            record.extend(section.histogram.iter().map(|b| b.count.to_string()));
+            writer.write_record(&record)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Round to two decimal places for display.
+fn round(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A graded submission if given a score, and otherwise one which was never submitted.
+    fn submission(user_id: u64, score: Option<f32>) -> Submission {
+        serde_json::from_value(json!({
+            "id": user_id * 10,
+            "user_id": user_id,
+            "assignment_id": 10,
+            "grader_id": score.map(|_| 900),
+            "score": score,
+            "workflow_state": if score.is_some() { "graded" } else { "unsubmitted" },
+            "attachments": score.map(|_| json!([])),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn summaries_interpolate_quartiles() {
+        let summary = ScoreSummary::new(&[4.0, 1.0, 3.0, 2.0]).unwrap();
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.mean, 2.5);
+        assert_eq!(summary.median, 2.5);
+        assert_eq!(summary.q1, 1.75);
+        assert_eq!(summary.q3, 3.25);
+        assert_eq!((summary.min, summary.max), (1.0, 4.0));
+        // Population rather than sample standard deviation, which would be 1.29
+        assert!((summary.std_dev - 1.25f32.sqrt()).abs() < 1e-6);
+
+        assert_eq!(ScoreSummary::new(&[7.0]).unwrap().std_dev, 0.0);
+        assert!(ScoreSummary::new(&[]).is_none());
+    }
+
+    #[test]
+    fn histogram_bins_include_their_lower_edge() {
+        let bins = histogram(&[0.0, 2.5, 5.0, 9.9, 10.0, 12.0], 10.0, 4);
+        let edges: Vec<(f32, f32)> = bins.iter().map(|b| (b.from, b.to)).collect();
+        assert_eq!(edges, [(0.0, 2.5), (2.5, 5.0), (5.0, 7.5), (7.5, 10.0)]);
+        let counts: Vec<usize> = bins.iter().map(|b| b.count).collect();
+        // Scores of the maximum and above land in the last bin
+        assert_eq!(counts, [1, 1, 1, 3]);
+
+        assert!(histogram(&[1.0], 0.0, 4).is_empty());
+        assert!(histogram(&[1.0], 10.0, 0).is_empty());
+    }
+
+    #[test]
+    fn sections_are_sorted_with_unenrolled_students_last() {
+        let submissions = [
+            submission(1, Some(6.0)),
+            submission(2, Some(8.0)),
+            submission(3, None),
+            submission(4, None),
+        ];
+        let sections = HashMap::from([(1, vec![20]), (2, vec![10, 20]), (3, vec![])]);
+
+        let stats = GradeStats::new(Some(10.0), &submissions, &sections, 5);
+        let names: Vec<&str> = stats.sections.iter().map(|s| s.section.as_str()).collect();
+        assert_eq!(names, ["all", "10", "20", "none"]);
+
+        let all = &stats.sections[0];
+        assert_eq!((all.students, all.submitted, all.graded), (4, 2, 2));
+        assert_eq!(all.scores.as_ref().unwrap().mean, 7.0);
+        assert_eq!(stats.sections[2].scores.as_ref().unwrap().count, 2);
+
+        let none = &stats.sections[3];
+        assert_eq!((none.students, none.submitted), (2, 0));
+        assert!(none.scores.is_none());
+        assert!(none.histogram.iter().all(|b| b.count == 0));
+    }
+}
@@ -1,26 +1,111 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
-use serde::Deserialize;
-use tracing::info;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use tracing::{info, warn};
 
 use crate::{
+    api::get_paginated,
+    comments::{mark, CommentThread, SubmissionComment},
+    extract::{extract_archive, ExtractOptions},
     file::{CanvasFile, FileSubmission},
-    Comment, Config, Grade,
+    moderation::ProvisionalGrade,
+    repository::{RepositoryConfig, RepositorySubmission},
+    upload::upload_comment_file,
+    Attachment, Comment, Config, Grade, Progress, StudentId, User,
 };
 
 #[derive(Debug, Deserialize)]
 pub struct Submission {
-    user_id: u64,
+    id: u64,
+    /// The `user_id`, or the `anonymous_id` on anonymous assignments, where Canvas hides
+    /// user ids
+    #[serde(flatten, deserialize_with = "student_id")]
+    student: StudentId,
     assignment_id: u64,
     attempt: Option<u64>,
     /// None if submission has not been graded
     grader_id: Option<u64>,
     score: Option<f32>,
+    /// Points taken off by the course's late policy
+    points_deducted: Option<f32>,
     workflow_state: WorkflowState,
+    /// Not included in entries of `submission_history`
+    #[serde(default)]
     redo_request: bool,
     attachments: Option<Vec<CanvasFile>>,
+    submission_type: Option<String>,
+    /// Set for `online_url` submissions
+    url: Option<String>,
+    submitted_at: Option<String>,
+    /// The student's due date, taking overrides for them or their section into account
+    cached_due_date: Option<DateTime<Utc>>,
+    graded_at: Option<String>,
+    /// Every attempt of the submission, if requested with [`Include::SubmissionHistory`]
+    submission_history: Option<Vec<Submission>>,
+    /// Requested with [`Include::SubmissionComments`]
+    submission_comments: Option<Vec<SubmissionComment>>,
+    /// Requested with [`Include::User`]
+    user: Option<User>,
+    /// Requested with [`Include::ProvisionalGrades`] on moderated assignments
+    provisional_grades: Option<Vec<ProvisionalGrade>>,
+    /// Requested with [`Include::Group`], with no id unless this is a group assignment
+    group: Option<SubmissionGroup>,
+}
+
+/// A submission's student, failing if Canvas gave neither id.
+fn student_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StudentId, D::Error> {
+    #[derive(Deserialize)]
+    struct Ids {
+        user_id: Option<u64>,
+        anonymous_id: Option<String>,
+    }
+
+    match Ids::deserialize(deserializer)? {
+        Ids {
+            user_id: Some(id), ..
+        } => Ok(StudentId::User(id)),
+        Ids {
+            anonymous_id: Some(id),
+            ..
+        } if !id.is_empty() => Ok(StudentId::Anonymous(id)),
+        _ => Err(D::Error::custom(
+            "submission has neither a user_id nor an anonymous_id",
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmissionGroup {
+    id: Option<u64>,
+    name: Option<String>,
+}
+
+/// Associations which can be requested alongside submissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Include {
+    SubmissionHistory,
+    SubmissionComments,
+    User,
+    ProvisionalGrades,
+    Group,
+}
+
+impl std::fmt::Display for Include {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Include::SubmissionHistory => write!(f, "submission_history"),
+            Include::SubmissionComments => write!(f, "submission_comments"),
+            Include::User => write!(f, "user"),
+            Include::ProvisionalGrades => write!(f, "provisional_grades"),
+            Include::Group => write!(f, "group"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,7 +122,7 @@ impl std::fmt::Display for Submission {
         write!(
             f,
             "{}_{}_{}",
-            self.user_id,
+            self.user(),
             self.assignment_id,
             self.attempt()
         )
@@ -61,7 +146,29 @@ impl Submission {
     }
 
     pub fn unsubmitted(&self) -> bool {
-        matches!(self.workflow_state, WorkflowState::Unsubmitted) || self.attachments.is_none()
+        matches!(self.workflow_state, WorkflowState::Unsubmitted)
+            || (self.attachments.is_none() && self.url.is_none())
+    }
+
+    /// Whether the submission was made after the student's own due date, so extensions are
+    /// not counted as late.
+    pub fn late(&self) -> bool {
+        let submitted_at = self
+            .submitted_at
+            .as_deref()
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok());
+        match (submitted_at, self.cached_due_date) {
+            (Some(submitted_at), Some(due_at)) => self.submitted() && submitted_at > due_at,
+            _ => false,
+        }
+    }
+
+    /// Whether nothing was submitted and the student's own due date has passed.
+    pub fn missing(&self) -> bool {
+        self.unsubmitted()
+            && self
+                .cached_due_date
+                .is_some_and(|due_at| due_at < Utc::now())
     }
 
     pub fn grade_100(&self) -> bool {
@@ -72,6 +179,16 @@ impl Submission {
         self.submitted() && !self.grade_100()
     }
 
+    /// Whether the student wrote the most recent comment, which requires the submission to be
+    /// requested with [`Include::SubmissionComments`].
+    pub fn unanswered_comment(&self) -> bool {
+        self.comment_thread().unanswered()
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     pub fn assignment(&self) -> u64 {
         self.assignment_id
     }
@@ -80,8 +197,115 @@ impl Submission {
         self.attempt.unwrap_or(0)
     }
 
-    pub fn user(&self) -> u64 {
-        self.user_id
+    /// The student's user id, or their anonymous id if Canvas hides it.
+    pub fn user(&self) -> StudentId {
+        self.student.clone()
+    }
+
+    pub fn submitted_at(&self) -> Option<&str> {
+        self.submitted_at.as_deref()
+    }
+
+    /// The student's due date, including any extension they were given.
+    pub fn due_at(&self) -> Option<DateTime<Utc>> {
+        self.cached_due_date
+    }
+
+    /// The submitted URL of `online_url` submissions.
+    pub fn submitted_url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn graded_at(&self) -> Option<&str> {
+        self.graded_at.as_deref()
+    }
+
+    pub fn score(&self) -> Option<f32> {
+        self.score
+    }
+
+    pub fn grader(&self) -> Option<u64> {
+        self.grader_id
+    }
+
+    pub fn points_deducted(&self) -> Option<f32> {
+        self.points_deducted
+    }
+
+    /// Grades given by each grader of a moderated assignment, which are only present if
+    /// requested with [`Include::ProvisionalGrades`].
+    pub fn provisional_grades(&self) -> &[ProvisionalGrade] {
+        self.provisional_grades.as_deref().unwrap_or_default()
+    }
+
+    /// The group the student submitted with, which is only present on group assignments
+    /// if requested with [`Include::Group`].
+    pub fn group_id(&self) -> Option<u64> {
+        self.group.as_ref()?.id
+    }
+
+    pub fn group_name(&self) -> Option<&str> {
+        self.group.as_ref()?.name.as_deref()
+    }
+
+    /// The student, which is only present if requested with [`Include::User`].
+    pub fn student(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    /// The student's name, which is only present if requested with [`Include::User`].
+    pub fn student_name(&self) -> Option<&str> {
+        self.user.as_ref().map(|u| u.name.as_str())
+    }
+
+    /// Comments on the submission, which are only present if requested with
+    /// [`Include::SubmissionComments`].
+    pub fn comment_thread(&self) -> CommentThread {
+        CommentThread {
+            assignment_id: self.assignment_id,
+            user_id: self.user(),
+            comments: self
+                .submission_comments
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .sorted_by(|a, b| a.created_at.cmp(&b.created_at))
+                .collect(),
+        }
+    }
+
+    /// Submitted attempts from oldest to newest.
+    ///
+    /// Only the current attempt is returned unless the submission was requested with
+    /// [`Include::SubmissionHistory`].
+    pub fn history(&self) -> Vec<&Submission> {
+        match &self.submission_history {
+            Some(history) => history
+                .iter()
+                .filter(|s| s.attempt.is_some())
+                .sorted_by_key(|s| s.attempt())
+                .collect(),
+            None => vec![self],
+        }
+    }
+
+    /// Files from every attempt in [`Submission::history`].
+    pub fn attempt_files(&self) -> Vec<FileSubmission> {
+        self.history()
+            .into_iter()
+            .flat_map(Submission::files)
+            .flatten()
+            .collect()
+    }
+
+    /// The submitted repository, if this is a URL submission pointing to a git repository
+    /// which `options` allow cloning.
+    pub fn repository(&self, options: &RepositoryConfig) -> Option<RepositorySubmission> {
+        if self.submission_type.as_deref() != Some("online_url") {
+            return None;
+        }
+
+        RepositorySubmission::new(self, self.url.as_deref()?, options)
     }
 
     pub fn files(&self) -> Option<Vec<FileSubmission>> {
@@ -94,112 +318,152 @@ impl Submission {
         )
     }
 
+    /// Download every file and repository of the submission into its own directory within
+    /// `directory`, returning that directory.
+    ///
+    /// Files keep their original names, and archives are extracted alongside them if
+    /// `extract` is set. Archives which cannot be extracted are kept as they are.
+    pub async fn download_into(
+        &self,
+        directory: &Path,
+        extract: Option<&ExtractOptions>,
+        config: &Config,
+    ) -> Result<PathBuf> {
+        let directory = directory.join(self.to_string());
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory)?;
+        }
+        std::fs::create_dir_all(&directory)?;
+
+        for file in self.files().unwrap_or_default() {
+            let path = directory.join(file.filename());
+            file.download_as(&path, config).await?;
+            if let Some(options) = extract {
+                // The archive is still graded as it was submitted
+                if let Err(e) = extract_archive(&path, options) {
+                    warn!("Skipping extraction: {e:#}");
+                }
+            }
+        }
+        if let Some(repository) = self.repository(&config.repositories) {
+            repository.download(&directory, false).await?;
+        }
+
+        Ok(directory)
+    }
+
+    /// Keep one submission per group, as every member of a group shares the same submission.
+    ///
+    /// Submissions must be requested with [`Include::Group`]. Students without a group are
+    /// all kept, and so are submissions to different assignments.
+    pub fn one_per_group(submissions: Vec<Self>) -> Vec<Self> {
+        let mut groups = HashSet::new();
+        submissions
+            .into_iter()
+            .filter(|s| {
+                s.group_id()
+                    .is_none_or(|id| groups.insert((s.assignment_id, id)))
+            })
+            .collect()
+    }
+
     pub async fn assignment_submissions(
         assignment_id: u64,
+        include: &[Include],
         predicate: &dyn Fn(&Self) -> bool,
         config: &Config,
     ) -> Result<Vec<Self>> {
+        let mut res: Vec<_> = Self::fetch(assignment_id, include, config)
+            .await?
+            .into_iter()
+            .sorted_unstable_by_key(|r| r.user())
+            .sorted_by_key(|r| -(r.attempt() as i64))
+            .filter(predicate)
+            .collect();
+        res.dedup_by_key(|r| r.user());
+
+        Ok(res)
+    }
+
+    pub async fn count_submissions(
+        assignment_id: u64,
+        include: &[Include],
+        predicate: &dyn Fn(&Self) -> bool,
+        config: &Config,
+    ) -> Result<usize> {
+        Ok(Self::fetch(assignment_id, include, config)
+            .await?
+            .into_iter()
+            .filter(predicate)
+            .count())
+    }
+
+    async fn fetch(assignment_id: u64, include: &[Include], config: &Config) -> Result<Vec<Self>> {
         let url = format!(
             "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions",
             config.base_url, config.course_id
         );
 
-        let mut next_page_exists = true;
-        let mut page = 1;
-        let mut responses: Vec<Self> = Vec::new();
-        while next_page_exists {
-            let page_str = page.to_string();
-            let form = HashMap::from([
-                ("workflow_state", "submitted"),
-                // ("per_page", "50"),
-                ("page", &page_str),
-            ]);
-
-            info!("Requesting from \"{url}\", page {page}");
-            let response = config.client.get(&url).query(&form).send().await?;
-            let headers = response.headers().clone();
-
-            info!("Getting body from response...");
-            let body = response.text().await?;
-            let untyped: serde_json::Value =
-                serde_json::from_str(&body).context("Failed to parse invalid JSON body.")?;
-            info!("Parsed into untyped JSON");
-
-            info!("Attempting to parse JSON into structured data type...");
-
-            let mut structured = serde_json::from_str(&body).with_context(|| {
-                format!("Unable to parse response to data type: {:#?}", untyped)
-            })?;
-            responses.append(&mut structured);
-
-            next_page_exists = headers
-                .get("Link")
-                .context("Failed to get link header.")?
-                .to_str()
-                .context("Failed to stringify link header")?
-                .contains("next");
-            page += 1;
+        let mut query = vec![("workflow_state", "submitted".to_owned())];
+        query.extend(include.iter().map(|i| ("include[]", i.to_string())));
+
+        get_paginated(&url, &query, config).await
+    }
+
+    /// The latest submission of each student to each assignment meeting the predicate,
+    /// sorted by assignment in the order given.
+    ///
+    /// Submissions to several assignments are fetched in one paginated pass over the bulk
+    /// endpoint, instead of one pass per assignment.
+    pub async fn for_assignments(
+        assignment_ids: &[u64],
+        include: &[Include],
+        predicate: &dyn Fn(&Self) -> bool,
+        config: &Config,
+    ) -> Result<Vec<Self>> {
+        if let [assignment_id] = assignment_ids {
+            return Self::assignment_submissions(*assignment_id, include, predicate, config).await;
         }
 
-        let mut res: Vec<_> = responses
+        let mut res: Vec<_> = Self::fetch_bulk(assignment_ids, include, config)
+            .await?
             .into_iter()
-            .sorted_unstable_by_key(|r| r.user_id)
+            .sorted_unstable_by_key(|r| r.user())
             .sorted_by_key(|r| -(r.attempt() as i64))
+            .sorted_by_key(|r| assignment_ids.iter().position(|&id| id == r.assignment_id))
             .filter(predicate)
             .collect();
-        res.dedup_by_key(|r| r.user_id);
+        res.dedup_by_key(|r| (r.assignment_id, r.user()));
 
         Ok(res)
     }
 
-    pub async fn count_submissions(
-        assignment_id: u64,
-        predicate: &dyn Fn(&Self) -> bool,
+    /// Every student's submissions to every assignment in the course, fetched in bulk.
+    pub async fn course_submissions(include: &[Include], config: &Config) -> Result<Vec<Self>> {
+        Self::fetch_bulk(&[], include, config).await
+    }
+
+    /// Every student's submissions to the given assignments, or to all of them if none are
+    /// given.
+    async fn fetch_bulk(
+        assignment_ids: &[u64],
+        include: &[Include],
         config: &Config,
-    ) -> Result<usize> {
+    ) -> Result<Vec<Self>> {
         let url = format!(
-            "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions",
+            "{}/api/v1/courses/{}/students/submissions",
             config.base_url, config.course_id
         );
 
-        let mut next_page_exists = true;
-        let mut page = 1;
-        let mut responses: Vec<Self> = Vec::new();
-        while next_page_exists {
-            let page_str = page.to_string();
-            let form = HashMap::from([
-                ("workflow_state", "submitted"),
-                // ("per_page", "50"),
-                ("page", &page_str),
-            ]);
-
-            info!("Requesting from \"{url}\", page {page}");
-            let response = config.client.get(&url).query(&form).send().await?;
-            let headers = response.headers().clone();
-
-            info!("Getting body from response...");
-            let body = response.text().await?;
-            let untyped: serde_json::Value =
-                serde_json::from_str(&body).context("Failed to parse invalid JSON body.")?;
-            info!("Parsed into untyped JSON");
-
-            info!("Attempting to parse JSON into structured data type...");
-
-            let mut structured = serde_json::from_str(&body).with_context(|| {
-                format!("Unable to parse response to data type: {:#?}", untyped)
-            })?;
-            responses.append(&mut structured);
-
-            next_page_exists = headers
-                .get("Link")
-                .context("Failed to get link header.")?
-                .to_str()
-                .context("Failed to stringify link header")?
-                .contains("next");
-            page += 1;
-        }
+        let mut query = vec![("student_ids[]", "all".to_owned())];
+        query.extend(
+            assignment_ids
+                .iter()
+                .map(|id| ("assignment_ids[]", id.to_string())),
+        );
+        query.extend(include.iter().map(|i| ("include[]", i.to_string())));
 
-        Ok(responses.into_iter().filter(predicate).count())
+        get_paginated(&url, &query, config).await
     }
 
     pub async fn update_grades(
@@ -207,45 +471,78 @@ impl Submission {
         grades: &[Grade],
         config: &Config,
     ) -> Result<()> {
-        let form = HashMap::<String, f32>::from_iter(
-            grades
-                .iter()
-                .map(|g| (format!("grade_data[{}][posted_grade]", g.user_id), g.grade)),
-        );
-
-        config
-            .client
-            .post(format!(
-                "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions/update_grades",
-                config.base_url, config.course_id
-            ))
-            .form(&form)
-            .send()
-            .await?;
+        Self::update_grades_with_comments(assignment_id, grades, &[], false, config).await?;
 
         Ok(())
     }
 
+    /// Returns the job applying the grades and comments, which finishes in the background,
+    /// or `None` if every student is anonymous.
+    ///
+    /// The bulk endpoint only accepts user ids, so students with anonymous ids are updated
+    /// one at a time with [`Submission::update`]. On group assignments, `group_comment` sends
+    /// each comment to the student's whole group.
     pub async fn update_grades_with_comments(
         assignment_id: u64,
         grades: &[Grade],
         comments: &[Comment],
+        group_comment: bool,
         config: &Config,
-    ) -> Result<()> {
-        let form_grades = grades.iter().map(|g| {
-            (
-                format!("grade_data[{}][posted_grade]", g.user_id),
-                g.grade.to_string(),
-            )
-        });
-        let form_comments = comments.iter().map(|c| {
-            (
-                format!("grade_data[{}][text_comment]", c.user_id),
-                c.comment.to_owned(),
+    ) -> Result<Option<Progress>> {
+        let anonymous: BTreeSet<&StudentId> = grades
+            .iter()
+            .map(|g| &g.user_id)
+            .chain(comments.iter().map(|c| &c.user_id))
+            .filter(|id| id.user_id().is_none())
+            .collect();
+        for user_id in &anonymous {
+            Self::update(
+                assignment_id,
+                user_id,
+                grades
+                    .iter()
+                    .rfind(|g| &&g.user_id == user_id)
+                    .map(|g| g.grade),
+                comments
+                    .iter()
+                    .find(|c| &&c.user_id == user_id)
+                    .map(|c| c.comment.as_str()),
+                false,
+                config,
             )
-        });
+            .await?;
+        }
+
+        let form_grades = grades
+            .iter()
+            .filter(|g| !anonymous.contains(&g.user_id))
+            .map(|g| {
+                (
+                    format!("grade_data[{}][posted_grade]", g.user_id),
+                    g.grade.to_string(),
+                )
+            });
+        let form_comments = comments
+            .iter()
+            .filter(|c| !anonymous.contains(&c.user_id))
+            .flat_map(|c| {
+                let mut fields = vec![(
+                    format!("grade_data[{}][text_comment]", c.user_id),
+                    mark(&c.comment),
+                )];
+                if group_comment {
+                    fields.push((
+                        format!("grade_data[{}][group_comment]", c.user_id),
+                        "true".to_owned(),
+                    ));
+                }
+                fields
+            });
 
         let form = HashMap::<String, String>::from_iter(form_grades.chain(form_comments));
+        if form.is_empty() {
+            return Ok(None);
+        }
 
         config
             .client
@@ -255,8 +552,160 @@ impl Submission {
             ))
             .form(&form)
             .send()
-            .await?;
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .map(Some)
+            .context("Unable to parse grade update progress")
+    }
+
+    /// Grade and comment on a single student's submission.
+    ///
+    /// On moderated assignments, `provisional` records the grade as this grader's provisional
+    /// grade, to be selected as the final grade by the moderator.
+    pub async fn update(
+        assignment_id: u64,
+        user_id: &StudentId,
+        grade: Option<f32>,
+        comment: Option<&str>,
+        provisional: bool,
+        config: &Config,
+    ) -> Result<()> {
+        let mut form = Vec::new();
+        if let Some(grade) = grade {
+            form.push(("submission[posted_grade]", grade.to_string()));
+        }
+        if let Some(comment) = comment {
+            form.push(("comment[text_comment]", mark(comment)));
+        }
+        if provisional {
+            form.push(("submission[provisional]", "true".to_owned()));
+        }
+
+        info!("Updating submission of {user_id}");
+        config
+            .client
+            .put(Self::url(assignment_id, user_id, config)?)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?;
 
         Ok(())
     }
+
+    /// URL of a single student's submission, which is addressed by anonymous id on anonymous
+    /// assignments.
+    fn url(assignment_id: u64, user_id: &StudentId, config: &Config) -> Result<String> {
+        let endpoint = match user_id {
+            StudentId::User(_) => "submissions",
+            StudentId::Anonymous(_) => "anonymous_submissions",
+            StudentId::Group(key) => bail!("Group \"{key}\" was not resolved to its members"),
+        };
+
+        Ok(format!(
+            "{}/api/v1/courses/{}/assignments/{assignment_id}/{endpoint}/{user_id}",
+            config.base_url, config.course_id
+        ))
+    }
+
+    /// Comment on a single student's submission with files attached.
+    ///
+    /// Unlike [`Submission::update_grades_with_comments`], which only supports text, each file
+    /// is uploaded with [`upload_comment_file`] before posting the comment.
+    pub async fn comment_with_attachments(
+        assignment_id: u64,
+        user_id: &StudentId,
+        comment: Option<&str>,
+        attachments: &[Attachment],
+        config: &Config,
+    ) -> Result<()> {
+        let Some(id) = user_id.user_id() else {
+            bail!("Unable to attach files to the comment for anonymous student {user_id}");
+        };
+
+        let mut form = vec![(
+            "comment[text_comment]".to_owned(),
+            mark(comment.unwrap_or_default()),
+        )];
+        for attachment in attachments {
+            let file_id = upload_comment_file(assignment_id, id, &attachment.path, config).await?;
+            form.push(("comment[file_ids][]".to_owned(), file_id.to_string()));
+        }
+
+        config
+            .client
+            .put(Self::url(assignment_id, user_id, config)?)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::Submission;
+    use crate::StudentId;
+
+    const DUE: &str = "2025-01-31T23:59:59Z";
+
+    fn submission(user_id: u64, attempt: u64, state: &str, submitted_at: Option<&str>) -> Value {
+        json!({
+            "id": user_id * 10 + attempt,
+            "user_id": user_id,
+            "assignment_id": 10,
+            "attempt": submitted_at.map(|_| attempt),
+            "grader_id": (state == "graded").then_some(900),
+            "score": (state == "graded").then_some(8.0),
+            "workflow_state": state,
+            "attachments": submitted_at.map(|_| json!([])),
+            "submitted_at": submitted_at,
+            "cached_due_date": DUE,
+        })
+    }
+
+    fn parse(value: Value) -> Submission {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn predicates_follow_state_and_dates() {
+        let on_time = parse(submission(1, 1, "graded", Some("2025-01-30T12:00:00Z")));
+        assert!(on_time.submitted() && on_time.graded());
+        assert!(!on_time.ungraded() && !on_time.late() && !on_time.missing());
+
+        let late = parse(submission(3, 1, "submitted", Some("2025-02-02T12:00:00Z")));
+        assert!(late.ungraded() && late.late());
+
+        let missing = parse(submission(4, 0, "unsubmitted", None));
+        assert!(missing.unsubmitted() && missing.missing());
+        assert!(!missing.late() && !missing.ungraded());
+    }
+
+    #[test]
+    fn redo_requests_are_not_graded() {
+        let mut value = submission(1, 1, "graded", Some("2025-01-30T12:00:00Z"));
+        value["redo_request"] = json!(true);
+
+        assert!(parse(value).ungraded());
+    }
+
+    #[test]
+    fn submissions_need_a_user_or_anonymous_id() {
+        let mut value = submission(1, 1, "graded", Some("2025-01-30T12:00:00Z"));
+        value.as_object_mut().unwrap().remove("user_id");
+        assert!(serde_json::from_value::<Submission>(value.clone()).is_err());
+
+        value["anonymous_id"] = json!("x7Kq2");
+        assert_eq!(
+            parse(value).user(),
+            StudentId::Anonymous("x7Kq2".to_owned())
+        );
+    }
 }
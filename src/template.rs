@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use handlebars::Handlebars;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize, Serializer};
+use tracing::info;
+
+use crate::{autograde::TestResult, StudentId};
+
+/// A feedback comment rendered for each student, read from a TOML file.
+///
+/// The body is Markdown with Handlebars expressions such as `{{name}}`,
+/// `{{#if late_deduction}}` and `{{#each tests}}`, using the variables of
+/// [`TemplateContext`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommentTemplate {
+    pub body: String,
+    /// Post the rendered Markdown as is instead of converting it to plain text
+    #[serde(default)]
+    pub markdown: bool,
+}
+
+/// Variables available to a [`CommentTemplate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateContext {
+    pub user_id: StudentId,
+    pub name: Option<String>,
+    #[serde(serialize_with = "number")]
+    pub score: Option<f32>,
+    #[serde(serialize_with = "number")]
+    pub points_possible: Option<f32>,
+    /// Points taken off by the course's late policy
+    #[serde(serialize_with = "number")]
+    pub late_deduction: Option<f32>,
+    /// Comment lines given for the student
+    pub comments: Vec<String>,
+    /// Results reported by the autograder
+    pub tests: Vec<TestResult>,
+}
+
+impl CommentTemplate {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read template {:?}", path))?;
+        let template: Self = toml::from_str(&contents)
+            .with_context(|| format!("Unable to parse template {:?}", path))?;
+        handlebars::Template::compile(&template.body)
+            .with_context(|| format!("Invalid template body in {:?}", path))?;
+
+        Ok(template)
+    }
+
+    /// The template at `path` if given, or else `templates/<ASSIGNMENT_ID>.toml` in the
+    /// config directory if it exists.
+    pub fn find(assignment_id: u64, path: Option<&Path>) -> Result<Option<Self>> {
+        if let Some(path) = path {
+            return Self::read(path).map(Some);
+        }
+
+        let default = dirs::config_dir()
+            .context("Unable to get config dir for system")?
+            .join("grading")
+            .join("templates")
+            .join(format!("{assignment_id}.toml"));
+        if !default.exists() {
+            return Ok(None);
+        }
+
+        info!("Using comment template {:?}", default);
+        Self::read(&default).map(Some)
+    }
+
+    pub fn render(&self, context: &TemplateContext) -> Result<String> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        let rendered = handlebars
+            .render_template(&self.body, context)
+            .with_context(|| format!("Unable to render template for {}", context.user_id))?;
+
+        Ok(if self.markdown {
+            rendered.trim().to_owned()
+        } else {
+            markdown_to_text(&rendered)
+        })
+    }
+}
+
+/// Serialize whole numbers without a fractional part, so `10` is not rendered as `10.0`.
+fn number<S: Serializer>(value: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) if value.fract() == 0.0 => serializer.serialize_i64(*value as i64),
+        // Going through the shortest decimal representation avoids `0.1` becoming
+        // `0.10000000149011612`
+        Some(value) => serializer.serialize_f64(value.to_string().parse().unwrap_or_default()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Convert Markdown to the plain text layout Canvas displays comments in.
+///
+/// Emphasis markers are dropped, headings are underlined, list items keep their bullets or
+/// numbers, code blocks are indented, and links are followed by their URL.
+pub fn markdown_to_text(markdown: &str) -> String {
+    let mut writer = PlainText::default();
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        writer.event(event);
+    }
+
+    writer.text.trim_end().to_owned()
+}
+
+#[derive(Default)]
+struct PlainText {
+    text: String,
+    /// Line breaks to write before the next text
+    pending: usize,
+    /// Written at the start of each line, for list item continuations and code blocks
+    indent: String,
+    /// The next number of each open list, or `None` for bulleted lists
+    lists: Vec<Option<u64>>,
+    /// Indentation to restore when each open list item or code block ends
+    indents: Vec<String>,
+    links: Vec<String>,
+    heading_start: usize,
+}
+
+impl PlainText {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => {
+                self.write("");
+                self.heading_start = self.text.len();
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                let width = self.text[self.heading_start..].chars().count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.line_break(1);
+                self.write(&underline.repeat(width));
+                self.end_block();
+            }
+            Event::End(TagEnd::Paragraph) => self.end_block(),
+            Event::Start(Tag::List(start)) => {
+                self.line_break(1);
+                self.lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                self.lists.pop();
+                self.end_block();
+            }
+            Event::Start(Tag::Item) => {
+                self.line_break(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+                self.write(&marker);
+                self.indents.push(self.indent.clone());
+                self.indent.push_str(&" ".repeat(marker.len()));
+            }
+            Event::End(TagEnd::Item) => {
+                self.indent = self.indents.pop().unwrap_or_default();
+                self.line_break(1);
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                self.line_break(1);
+                self.indents.push(self.indent.clone());
+                self.indent.push_str("    ");
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                self.indent = self.indents.pop().unwrap_or_default();
+                self.end_block();
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                self.line_break(1);
+                self.indents.push(self.indent.clone());
+                self.indent.push_str("> ");
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                self.indent = self.indents.pop().unwrap_or_default();
+                self.end_block();
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => self.links.push(dest_url.into_string()),
+            Event::End(TagEnd::Link) => {
+                if let Some(url) = self.links.pop() {
+                    if !self.text.ends_with(&url) {
+                        self.write(&format!(" ({url})"));
+                    }
+                }
+            }
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                for (i, line) in text.trim_end_matches('\n').split('\n').enumerate() {
+                    if i > 0 {
+                        self.line_break(1);
+                    }
+                    self.write(line);
+                }
+            }
+            Event::Code(code) => self.write(&format!("`{code}`")),
+            Event::SoftBreak | Event::HardBreak => self.line_break(1),
+            Event::Rule => {
+                self.line_break(1);
+                self.write("----");
+                self.end_block();
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if !self.text.is_empty() && self.pending > 0 {
+            self.text.push_str(&"\n".repeat(self.pending));
+            self.text.push_str(&self.indent);
+        }
+        self.pending = 0;
+        self.text.push_str(text);
+    }
+
+    fn line_break(&mut self, count: usize) {
+        self.pending = self.pending.max(count);
+    }
+
+    /// Separate blocks by a blank line, except between the items of a tight list.
+    fn end_block(&mut self) {
+        self.line_break(if self.lists.is_empty() { 2 } else { 1 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_keep_their_markers_and_indent_continuations() {
+        let markdown = "- one\n- two\n  continued\n\n1. first\n2. second\n   - nested\n";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "- one\n- two\n  continued\n\n1. first\n2. second\n   - nested"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_indented() {
+        let markdown = "Before:\n\n```rust\nfn main() {\n    todo!()\n}\n```\n\nAfter";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "Before:\n\n    fn main() {\n        todo!()\n    }\n\nAfter"
+        );
+    }
+
+    #[test]
+    fn links_show_their_url_once() {
+        let markdown = "See [the rubric](https://example.com/rubric) or <https://example.com>.";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "See the rubric (https://example.com/rubric) or https://example.com."
+        );
+    }
+
+    #[test]
+    fn tables_keep_their_rows() {
+        let markdown = "| Test | Result |\n|------|--------|\n| parse | pass |\n| run | fail |\n";
+        assert_eq!(
+            markdown_to_text(markdown),
+            "| Test | Result |\n|------|--------|\n| parse | pass |\n| run | fail |"
+        );
+    }
+}
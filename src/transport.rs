@@ -0,0 +1,194 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{
+    header::{self, HeaderMap},
+    Method, Request, Response, Url,
+};
+use reqwest_middleware::{Middleware, Next};
+use serde_json::{json, Value};
+
+use crate::cache::fnv1a;
+
+/// Sends requests to Canvas and returns its responses. Every request made with a [`Config`]'s
+/// client, and every file downloaded or uploaded, goes through its transport.
+///
+/// [`reqwest::Client`] is the default, and can be replaced with one using custom TLS or
+/// proxy settings, or with a [`MemoryTransport`] in tests.
+///
+/// [`Config`]: crate::Config
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    async fn send(&self, request: Request) -> Result<Response>;
+}
+
+#[async_trait]
+impl Transport for reqwest::Client {
+    async fn send(&self, request: Request) -> Result<Response> {
+        Ok(self.execute(request).await?)
+    }
+}
+
+/// The last middleware of the client in `Config`, adding the headers every request to Canvas
+/// needs and sending it with the transport.
+pub(crate) struct TransportMiddleware {
+    pub transport: Arc<dyn Transport>,
+    pub headers: HeaderMap,
+}
+
+#[async_trait]
+impl Middleware for TransportMiddleware {
+    async fn handle(
+        &self,
+        mut request: Request,
+        _extensions: &mut Extensions,
+        _next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        for (name, value) in &self.headers {
+            if !request.headers().contains_key(name) {
+                request.headers_mut().insert(name, value.clone());
+            }
+        }
+
+        Ok(self.transport.send(request).await?)
+    }
+}
+
+/// Canvas in memory, answering requests with the JSON given for their method and path, so
+/// code making requests can be tested without a server.
+///
+/// Arrays are paginated like Canvas, `page_size` elements per page selected with the `page`
+/// parameter, and a `Link` header naming the next page if there is one. Responses have an
+/// `ETag`, and requests sending it back in `If-None-Match` get `304 Not Modified` while the
+/// response is unchanged. Requests to paths without a response fail with `404 Not Found`.
+#[derive(Debug)]
+pub struct MemoryTransport {
+    page_size: usize,
+    responses: Mutex<Vec<(Method, String, Value)>>,
+    requests: Mutex<Vec<(Method, Url)>>,
+}
+
+impl MemoryTransport {
+    pub fn new(page_size: usize) -> Self {
+        Self {
+            page_size,
+            responses: Mutex::new(Vec::new()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answer requests with `method` to `path` with `body`, replacing any previous response.
+    pub fn respond(&self, method: Method, path: &str, body: Value) {
+        let mut responses = self.responses.lock().unwrap();
+        responses.retain(|(m, p, _)| !(m == method && p == path));
+        responses.push((method, path.to_owned(), body));
+    }
+
+    /// Every request sent, in order.
+    pub fn requests(&self) -> Vec<(Method, Url)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn page(&self, url: &Url, elements: &[Value]) -> (Value, String) {
+        let page: usize = url
+            .query_pairs()
+            .find(|(key, _)| key == "page")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(1)
+            .max(1);
+        let last = elements.len().div_ceil(self.page_size).max(1);
+
+        let link_to = |page: usize, rel: &str| {
+            let mut url = url.clone();
+            let query: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(key, _)| key != "page")
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect();
+            url.query_pairs_mut()
+                .clear()
+                .extend_pairs(query)
+                .append_pair("page", &page.to_string());
+            format!("<{url}>; rel=\"{rel}\"")
+        };
+        let mut links = vec![link_to(page, "current"), link_to(1, "first")];
+        if page < last {
+            links.push(link_to(page + 1, "next"));
+        }
+        links.push(link_to(last, "last"));
+
+        let elements = elements
+            .iter()
+            .skip((page - 1) * self.page_size)
+            .take(self.page_size)
+            .cloned()
+            .collect();
+        (Value::Array(elements), links.join(","))
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let url = request.url().clone();
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.method().clone(), url.clone()));
+
+        let body = self
+            .responses
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(method, path, _)| method == request.method() && path == url.path())
+            .map(|(_, _, body)| body.clone());
+        let response = http::Response::builder().header(header::CONTENT_TYPE, "application/json");
+        let (response, body) = match body {
+            Some(Value::Array(elements)) => {
+                let (page, link) = self.page(&url, &elements);
+                (response.header(header::LINK, link), page.to_string())
+            }
+            Some(body) => (response, body.to_string()),
+            None => {
+                let body = json!({ "errors": [{ "message": "Not found" }] });
+                return Ok(response.status(404).body(body.to_string())?.into());
+            }
+        };
+
+        let etag = format!("\"{:016x}\"", fnv1a(&body));
+        let response = response.header(header::ETAG, &etag);
+        let unchanged = request
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|value| value.as_bytes() == etag.as_bytes());
+        let response = if unchanged {
+            response.status(304).body(String::new())?
+        } else {
+            response.body(body)?
+        };
+
+        Ok(response.into())
+    }
+}
+
+/// Settings for a course whose requests are answered by `transport`, caching in `directory`.
+#[cfg(test)]
+pub(crate) fn memory_config(
+    transport: Arc<MemoryTransport>,
+    directory: &std::path::Path,
+) -> crate::Config {
+    crate::Config::new(
+        crate::AccessToken::new("test-token"),
+        1,
+        "https://canvas.test",
+        crate::Cache::new(directory, false),
+    )
+    .and_then(|config| config.with_transport(transport))
+    .unwrap()
+}
@@ -0,0 +1,477 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{bail, Context, Result};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use tracing::info;
+
+use crate::{
+    file::FileSubmission, Assignment, Config, Criterion, GradingSession, StudentId, Submission,
+};
+
+const HELP: &str = "↑/↓ student  s score  r rubric  c comment  tab file  e edit  o open  x skip  f flag  u upload  q quit";
+
+/// Walks through submissions one student at a time for grading by hand, saving each score
+/// and comment to a [`GradingSession`] as it is entered.
+pub struct GradingTui<'a> {
+    assignment: &'a Assignment,
+    submissions: Vec<Submission>,
+    session: GradingSession,
+    directory: PathBuf,
+    selected: usize,
+    file: usize,
+    mode: Mode,
+    status: String,
+}
+
+enum Mode {
+    Normal,
+    /// Typing a score
+    Score(String),
+    /// Choosing ratings with the given criterion selected
+    Rubric(usize),
+}
+
+impl<'a> GradingTui<'a> {
+    /// Files opened from the UI are downloaded into `directory`.
+    pub fn new(
+        assignment: &'a Assignment,
+        submissions: Vec<Submission>,
+        session: GradingSession,
+        directory: &Path,
+    ) -> Self {
+        Self {
+            assignment,
+            submissions,
+            session,
+            directory: directory.to_owned(),
+            selected: 0,
+            file: 0,
+            mode: Mode::Normal,
+            status: HELP.to_owned(),
+        }
+    }
+
+    /// Run until the grader quits, returning the session with everything entered.
+    pub async fn run(mut self, config: &Config) -> Result<GradingSession> {
+        if self.submissions.is_empty() {
+            bail!("No submissions to grade");
+        }
+
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal, config).await;
+        ratatui::restore();
+        result?;
+
+        self.session.save()?;
+        Ok(self.session)
+    }
+
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal, config: &Config) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let rubric = self.rubric();
+            let submission = &self.submissions[self.selected];
+            let user_id = submission.user();
+            match &mut self.mode {
+                Mode::Score(input) => match key.code {
+                    KeyCode::Char(c) if c.is_ascii_digit() || c == '.' || c == '-' => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Enter => {
+                        let score = if input.is_empty() {
+                            None
+                        } else {
+                            match input.parse() {
+                                Ok(score) => Some(score),
+                                Err(_) => {
+                                    self.status = format!("Invalid score {input:?}");
+                                    continue;
+                                }
+                            }
+                        };
+                        let entry = self.session.entry_mut(&user_id);
+                        entry.score = score;
+                        entry.skipped = false;
+                        entry.uploaded = false;
+                        self.session.save()?;
+                        self.mode = Mode::Normal;
+                        self.status = HELP.to_owned();
+                    }
+                    KeyCode::Esc => {
+                        self.mode = Mode::Normal;
+                        self.status = HELP.to_owned();
+                    }
+                    _ => {}
+                },
+                Mode::Rubric(criterion) => {
+                    let ratings = rubric[*criterion].ratings.len();
+                    let current = self
+                        .session
+                        .entry(&user_id)
+                        .and_then(|e| e.ratings.get(*criterion).copied().flatten());
+                    match key.code {
+                        KeyCode::Up => *criterion = criterion.saturating_sub(1),
+                        KeyCode::Down => *criterion = (*criterion + 1).min(rubric.len() - 1),
+                        // Ratings are listed from the most points down
+                        KeyCode::Right if ratings > 0 => {
+                            let rating = current.map_or(0, |r| (r + 1).min(ratings - 1));
+                            self.session.entry_mut(&user_id).rate(
+                                &rubric,
+                                *criterion,
+                                Some(rating),
+                            );
+                        }
+                        KeyCode::Left => {
+                            let rating = current.and_then(|r| r.checked_sub(1));
+                            self.session
+                                .entry_mut(&user_id)
+                                .rate(&rubric, *criterion, rating);
+                        }
+                        KeyCode::Enter | KeyCode::Esc => {
+                            self.session.save()?;
+                            self.mode = Mode::Normal;
+                            self.status = HELP.to_owned();
+                        }
+                        _ => {}
+                    }
+                }
+                Mode::Normal => match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down | KeyCode::Char('n') => self.select(self.selected + 1),
+                    KeyCode::Up | KeyCode::Char('p') => {
+                        self.select(self.selected.saturating_sub(1))
+                    }
+                    KeyCode::Char('s') => {
+                        let score = self.session.entry(&user_id).and_then(|e| e.score);
+                        self.mode = Mode::Score(score.map(|s| s.to_string()).unwrap_or_default());
+                        self.status = "Type a score, enter to save, esc to cancel".to_owned();
+                    }
+                    KeyCode::Char('r') if rubric.is_empty() => {
+                        self.status = "The assignment has no rubric".to_owned();
+                    }
+                    KeyCode::Char('r') => {
+                        self.mode = Mode::Rubric(0);
+                        self.status = "↑/↓ criterion  ←/→ rating  enter when done".to_owned();
+                    }
+                    KeyCode::Char('c') => {
+                        if let Err(e) = self.edit_comment(terminal, &user_id) {
+                            self.status = format!("Editing the comment failed: {e:#}");
+                        }
+                    }
+                    KeyCode::Tab => {
+                        let count = submission.files().unwrap_or_default().len();
+                        self.file = if count == 0 {
+                            0
+                        } else {
+                            (self.file + 1) % count
+                        };
+                    }
+                    KeyCode::Char(c @ ('e' | 'o')) => {
+                        let Some(file) = submission
+                            .files()
+                            .unwrap_or_default()
+                            .get(self.file)
+                            .cloned()
+                        else {
+                            self.status = "The submission has no files".to_owned();
+                            continue;
+                        };
+                        if let Err(e) = self.open_file(terminal, &file, c == 'e', config).await {
+                            self.status = format!("Opening {} failed: {e:#}", file.filename());
+                        }
+                    }
+                    KeyCode::Char('x') => {
+                        let entry = self.session.entry_mut(&user_id);
+                        entry.skipped = !entry.skipped;
+                        let skipped = entry.skipped;
+                        self.session.save()?;
+                        if skipped {
+                            self.select(self.selected + 1);
+                        }
+                    }
+                    KeyCode::Char('f') => {
+                        let entry = self.session.entry_mut(&user_id);
+                        entry.flagged = !entry.flagged;
+                        self.session.save()?;
+                    }
+                    KeyCode::Char('u') => {
+                        self.status = match self.session.upload(self.assignment, config).await {
+                            Ok(count) => {
+                                format!("Uploaded grades and comments for {count} students")
+                            }
+                            Err(e) => format!("Upload failed: {e:#}"),
+                        };
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Edit the student's comment in `$EDITOR`, through a file only the grader can read.
+    fn edit_comment(&mut self, terminal: &mut DefaultTerminal, user_id: &StudentId) -> Result<()> {
+        let comment = self
+            .session
+            .entry(user_id)
+            .map(|e| e.comment.clone())
+            .unwrap_or_default();
+        let file = tempfile::Builder::new()
+            .prefix("grading-comment-")
+            .suffix(".md")
+            .tempfile()
+            .context("Unable to create a file for the comment")?;
+        std::fs::write(file.path(), comment)?;
+        edit(terminal, file.path())?;
+
+        let entry = self.session.entry_mut(user_id);
+        entry.comment = std::fs::read_to_string(file.path())?.trim().to_owned();
+        entry.uploaded = false;
+        self.session.save()
+    }
+
+    /// Download a submitted file, and open it in `$EDITOR` if `edit`, or else the system's
+    /// viewer.
+    async fn open_file(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        file: &FileSubmission,
+        edit: bool,
+        config: &Config,
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+        let path = file.download(&self.directory, config).await?;
+        if edit {
+            self::edit(terminal, &path)
+        } else {
+            open(&path)?;
+            self.status = format!("Opened {}", file.filename());
+            Ok(())
+        }
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.submissions.len() - 1);
+        self.file = 0;
+    }
+
+    fn rubric(&self) -> Vec<Criterion> {
+        self.assignment.rubric.clone().unwrap_or_default()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [students, details] =
+            Layout::horizontal([Constraint::Length(32), Constraint::Min(0)]).areas(main);
+
+        let items: Vec<ListItem> = self
+            .submissions
+            .iter()
+            .map(|submission| {
+                let entry = self.session.entry(&submission.user());
+                let marker = match entry {
+                    Some(e) if e.flagged => "!",
+                    Some(e) if e.skipped => "-",
+                    Some(e) if e.uploaded => "✓",
+                    Some(e) if e.score.is_some() => "•",
+                    _ => " ",
+                };
+                let name = submission
+                    .group_name()
+                    .or(submission.student_name())
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| submission.user().to_string());
+                ListItem::new(format!("{marker} {name}"))
+            })
+            .collect();
+        let graded = self
+            .submissions
+            .iter()
+            .filter(|s| {
+                self.session
+                    .entry(&s.user())
+                    .is_some_and(|e| e.score.is_some())
+            })
+            .count();
+        let list = List::new(items)
+            .block(Block::bordered().title(format!(" {graded}/{} graded ", self.submissions.len())))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, students, &mut state);
+
+        let details_block = Block::bordered().title(format!(" {} ", self.assignment.name));
+        frame.render_widget(
+            Paragraph::new(self.details())
+                .block(details_block)
+                .wrap(Wrap { trim: false }),
+            details,
+        );
+        frame.render_widget(Line::from(self.status.as_str()).dim(), status);
+    }
+
+    fn details(&self) -> Text<'_> {
+        let submission = &self.submissions[self.selected];
+        let user_id = submission.user();
+        let entry = self.session.entry(&user_id);
+        let mut lines: Vec<Line> = Vec::new();
+
+        let name = submission.student_name().unwrap_or_default();
+        lines.push(Line::from(format!("{name} ({user_id})")).bold());
+        if let Some(group) = submission.group_name() {
+            lines.push(Line::from(format!("Group: {group}")));
+        }
+        lines.push(Line::from(format!(
+            "Attempt {}, submitted {}{}",
+            submission.attempt(),
+            submission.submitted_at().unwrap_or("never"),
+            if submission.late() { " (late)" } else { "" }
+        )));
+        if let Some(score) = submission.score() {
+            lines.push(Line::from(format!("Score in Canvas: {score}")));
+        }
+
+        let points = self
+            .assignment
+            .points_possible
+            .map(|p| format!(" / {p}"))
+            .unwrap_or_default();
+        let score = match &self.mode {
+            Mode::Score(input) => format!("Score: {input}_{points}"),
+            _ => match entry.and_then(|e| e.score) {
+                Some(score) => format!("Score: {score}{points}"),
+                None => "Score: none".to_owned(),
+            },
+        };
+        lines.push(Line::default());
+        lines.push(Line::from(score).bold());
+        if let Some(e) = entry {
+            let flags: Vec<&str> = [
+                (e.flagged, "flagged"),
+                (e.skipped, "skipped"),
+                (e.uploaded, "uploaded"),
+            ]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .collect();
+            if !flags.is_empty() {
+                lines.push(Line::from(flags.join(", ")).italic());
+            }
+        }
+
+        let files = submission.files().unwrap_or_default();
+        if !files.is_empty() || submission.submitted_url().is_some() {
+            lines.push(Line::default());
+            lines.push(Line::from("Files").underlined());
+        }
+        for (i, file) in files.iter().enumerate() {
+            let marker = if i == self.file { ">" } else { " " };
+            lines.push(Line::from(format!("{marker} {}", file.filename())));
+        }
+        if let Some(url) = submission.submitted_url() {
+            lines.push(Line::from(format!("  {url}")));
+        }
+
+        let rubric = self.rubric();
+        if !rubric.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from("Rubric").underlined());
+        }
+        for (i, criterion) in rubric.iter().enumerate() {
+            let rating = entry
+                .and_then(|e| e.ratings.get(i).copied().flatten())
+                .and_then(|r| criterion.ratings.get(r));
+            let line = Line::from(match rating {
+                Some(rating) => format!(
+                    "{}: {} ({}/{})",
+                    criterion.description, rating.description, rating.points, criterion.points
+                ),
+                None => format!("{}: unrated (/{})", criterion.description, criterion.points),
+            });
+            lines.push(match self.mode {
+                Mode::Rubric(selected) if selected == i => line.reversed(),
+                _ => line,
+            });
+        }
+
+        if let Some(comment) = entry.map(|e| e.comment.as_str()).filter(|c| !c.is_empty()) {
+            lines.push(Line::default());
+            lines.push(Line::from("Comment").underlined());
+            lines.extend(comment.lines().map(|l| Line::from(l.to_owned())));
+        }
+
+        let thread = submission.comment_thread();
+        if !thread.comments.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from("Previous comments").underlined());
+        }
+        for comment in &thread.comments {
+            lines.push(
+                Line::from(format!("[{}] {}:", comment.created_at, comment.author_name)).dim(),
+            );
+            lines.extend(comment.text().lines().map(|l| Line::from(format!("  {l}"))));
+        }
+
+        Text::from(lines)
+    }
+}
+
+/// Open `path` in `$VISUAL` or `$EDITOR`, handing the terminal over until it exits.
+fn edit(terminal: &mut DefaultTerminal, path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("$EDITOR is empty")?;
+    info!("Opening {:?} with {editor}", path);
+
+    ratatui::restore();
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status();
+    *terminal = ratatui::init();
+    terminal.clear()?;
+
+    if !status
+        .with_context(|| format!("Unable to run {editor}"))?
+        .success()
+    {
+        bail!("{editor} exited unsuccessfully");
+    }
+    Ok(())
+}
+
+/// Open `path` in the system's default viewer without waiting for it.
+fn open(path: &Path) -> Result<()> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program)
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Unable to run {program}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{multipart, StatusCode};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::Config;
+
+/// Where and how to upload a file, returned by Canvas for step one of its upload flow.
+#[derive(Debug, Deserialize)]
+struct UploadSlot {
+    upload_url: String,
+    upload_params: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadedFile {
+    id: u64,
+}
+
+/// Upload a file to attach to a comment on a student's submission, returning its file id.
+///
+/// Canvas uploads happen in three steps: requesting an upload slot, posting the file to the
+/// slot's URL, and confirming the upload if Canvas redirects to a confirmation URL.
+pub async fn upload_comment_file(
+    assignment_id: u64,
+    user_id: u64,
+    path: &Path,
+    config: &Config,
+) -> Result<u64> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or(anyhow!("Unable to get file name of {:?}", path))?;
+    let contents = std::fs::read(path).with_context(|| format!("Unable to read {:?}", path))?;
+
+    info!("Requesting upload slot for \"{name}\"");
+    let slot: UploadSlot = config
+        .client
+        .post(format!(
+            "{}/api/v1/courses/{}/assignments/{assignment_id}/submissions/{user_id}/comments/files",
+            config.base_url, config.course_id
+        ))
+        .form(&[("name", name), ("size", &contents.len().to_string())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Unable to parse upload slot")?;
+
+    // The upload URL may belong to a file store which rejects Canvas access tokens, and
+    // Canvas expects the file to be the last field of the form
+    let form = slot
+        .upload_params
+        .into_iter()
+        .fold(multipart::Form::new(), |form, (key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            form.text(key, value)
+        })
+        .part(
+            "file",
+            multipart::Part::bytes(contents).file_name(name.to_owned()),
+        );
+
+    info!("Uploading \"{name}\" to \"{}\"", slot.upload_url);
+    let response = config
+        .uploads
+        .post(&slot.upload_url)
+        .multipart(form)
+        .send()
+        .await?;
+
+    let file: UploadedFile = if response.status().is_redirection() {
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .context("Upload redirect has no location")?
+            .to_str()?
+            .to_owned();
+
+        info!("Confirming upload at \"{location}\"");
+        config
+            .client
+            .get(location)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?
+    } else if response.status() == StatusCode::OK || response.status() == StatusCode::CREATED {
+        response.json().await?
+    } else {
+        bail!(
+            "Uploading \"{name}\" failed with status {}",
+            response.status()
+        );
+    };
+
+    Ok(file.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{transport::memory_config, MemoryTransport};
+
+    const SLOT: &str = "/api/v1/courses/1/assignments/10/submissions/101/comments/files";
+
+    #[tokio::test]
+    async fn files_are_uploaded_through_the_transport() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("feedback.txt");
+        std::fs::write(&path, "See the rubric").unwrap();
+        let transport = Arc::new(MemoryTransport::new(10));
+        transport.respond(
+            Method::POST,
+            SLOT,
+            json!({
+                "upload_url": "https://files.canvas.test/upload",
+                "upload_params": { "filename": "feedback.txt" },
+            }),
+        );
+        transport.respond(Method::POST, "/upload", json!({ "id": 7 }));
+        let config = memory_config(transport.clone(), directory.path());
+
+        let file_id = upload_comment_file(10, 101, &path, &config).await.unwrap();
+
+        assert_eq!(file_id, 7);
+        let requests: Vec<String> = transport
+            .requests()
+            .into_iter()
+            .map(|(method, url)| format!("{method} {url}"))
+            .collect();
+        assert_eq!(
+            requests,
+            [
+                format!("POST https://canvas.test{SLOT}"),
+                "POST https://files.canvas.test/upload".to_owned(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{api::get, Config};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub id: u64,
+    pub name: String,
+    pub sortable_name: Option<String>,
+}
+
+impl User {
+    /// The user the access token belongs to.
+    pub async fn current(config: &Config) -> Result<Self> {
+        get(&format!("{}/api/v1/users/self", config.base_url), config)
+            .await
+            .context("Unable to get current user")
+    }
+}
@@ -0,0 +1,417 @@
+mod support;
+
+use std::{
+    path::Path,
+    process::{Output, Stdio},
+};
+
+use serde_json::{json, Value};
+use support::{MockCanvas, ACCESS_TOKEN};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// The CLI keeping its config, data and downloads in `directory`.
+fn command(directory: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_canvas-grading"));
+    command
+        .env("XDG_CONFIG_HOME", directory.join("config"))
+        .env("XDG_DATA_HOME", directory.join("data"))
+        .env("XDG_RUNTIME_DIR", directory.join("runtime"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Run the CLI against the mock with `stdin` as its input, keeping its config, data and
+/// downloads in the test's directory.
+async fn run(canvas: &MockCanvas, args: &[&str], stdin: &str) -> Output {
+    let mut command = command(canvas.directory.path());
+    command
+        .args(["--access-token", ACCESS_TOKEN, "--course-id", "1"])
+        .args(["--base-url", &canvas.base_url])
+        .args(args);
+    output(command, stdin).await
+}
+
+async fn output(mut command: Command, stdin: &str) -> Output {
+    let mut child = command.spawn().unwrap();
+    let mut input = child.stdin.take().unwrap();
+    input.write_all(stdin.as_bytes()).await.unwrap();
+    drop(input);
+
+    child.wait_with_output().await.unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[tokio::test]
+async fn count_prints_matching_submissions() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["10", "count", "submitted"], "").await;
+
+    assert_eq!(stdout(&output), "4\n");
+}
+
+#[tokio::test]
+async fn count_prints_each_assignment() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["10,11", "count", "graded"], "").await;
+
+    assert_eq!(stdout(&output), "10: 2\n11: 1\n");
+}
+
+#[tokio::test]
+async fn submissions_are_downloaded() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["10", "submissions", "ungraded"], "").await;
+
+    let contents: Vec<String> = stdout(&output)
+        .lines()
+        .map(|path| std::fs::read_to_string(path).unwrap())
+        .collect();
+    assert_eq!(contents, ["print('Alan')\n", "print('Grace')\n"]);
+}
+
+#[tokio::test]
+async fn autograde_continues_after_a_failed_download() {
+    let canvas = MockCanvas::start(2).await;
+    canvas.course().files.remove(&2);
+
+    let output = run(
+        &canvas,
+        &["10", "autograde", "--command", "echo points: 5", "ungraded"],
+        "",
+    )
+    .await;
+
+    assert_eq!(stdout(&output), "103: 5\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("102: Failed"), "{stderr}");
+}
+
+/// Enable the sandbox, with its scratch directory outside the temporary directory the test
+/// keeps its files in, and a test script in the hidden config directory exposed.
+#[cfg(target_os = "linux")]
+fn configure_sandbox(canvas: &MockCanvas) {
+    let directory = canvas.directory.path();
+    let scratch = directory.join("scratch");
+    let tests = directory.join("config/tests");
+    std::fs::create_dir_all(&scratch).unwrap();
+    std::fs::create_dir_all(&tests).unwrap();
+    std::fs::write(tests.join("points.sh"), "echo points: 1\n").unwrap();
+    std::fs::create_dir_all(directory.join("config/grading")).unwrap();
+    std::fs::write(
+        directory.join("config/grading/config.toml"),
+        format!(
+            "access_token = \"{ACCESS_TOKEN}\"\n\n\
+             [autograde.sandbox]\nenabled = true\nscratch = {scratch:?}\nexpose = [{tests:?}]\n"
+        ),
+    )
+    .unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sandbox_hides_the_config_and_other_submissions() {
+    let canvas = MockCanvas::start(2).await;
+    configure_sandbox(&canvas);
+
+    let command = "cat \"$XDG_CONFIG_HOME/grading/config.toml\" && echo comment: config read; \
+                   echo \"comment: $(ls .. | wc -l) submissions\"; \
+                   sh \"$XDG_CONFIG_HOME/tests/points.sh\"";
+    let output = run(
+        &canvas,
+        &["10", "autograde", "--command", command, "ungraded"],
+        "",
+    )
+    .await;
+
+    assert_eq!(
+        stdout(&output),
+        "102: 1\n103: 1\n102: 1 submissions\n103: 1 submissions\n"
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn sandbox_cannot_kill_outside_processes() {
+    let canvas = MockCanvas::start(2).await;
+    configure_sandbox(&canvas);
+    let mut outside = std::process::Command::new("sleep")
+        .arg("60")
+        .spawn()
+        .unwrap();
+
+    let command = format!(
+        "kill -9 {} && echo comment: killed; echo points: 1",
+        outside.id()
+    );
+    let output = run(
+        &canvas,
+        &["10", "autograde", "--command", &command, "graded"],
+        "",
+    )
+    .await;
+
+    let survived = outside.try_wait().unwrap().is_none();
+    outside.kill().unwrap();
+    assert!(survived);
+    assert_eq!(stdout(&output), "101: 1\n105: 1\n");
+}
+
+#[tokio::test]
+async fn history_lists_attempts() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["11", "history"], "").await;
+
+    assert_eq!(
+        stdout(&output),
+        "101\n    attempt 1 submitted 2025-02-06T12:00:00Z: ungraded\n\
+         103\n    attempt 1 submitted 2025-02-05T12:00:00Z: score 7.5, graded by 900\n"
+    );
+}
+
+#[tokio::test]
+async fn grade_uploads_grades_and_comments() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(
+        &canvas,
+        &["10", "grade"],
+        "102: 8\n103: 6\n103: Submitted late\n103: @Grace see the rubric\n",
+    )
+    .await;
+    stdout(&output);
+
+    assert_eq!(canvas.submission(10, 102)["score"], 8.0);
+    let grace = canvas.submission(10, 103);
+    assert_eq!(grace["score"], 6.0);
+    assert_eq!(
+        grace["submission_comments"][0]["comment"],
+        "Submitted late\n@Grace see the rubric\n\u{2063}"
+    );
+}
+
+#[tokio::test]
+async fn grade_skips_comments_already_posted() {
+    let canvas = MockCanvas::start(2).await;
+
+    for _ in 0..2 {
+        let output = run(&canvas, &["10", "grade"], "104: Nothing was submitted\n").await;
+        stdout(&output);
+    }
+
+    let comments = canvas.submission(10, 104)["submission_comments"].clone();
+    assert_eq!(comments.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn replaced_comments_are_deleted_after_posting() {
+    let canvas = MockCanvas::start(2).await;
+    let output = run(&canvas, &["10", "grade"], "103: Needs tests\n").await;
+    stdout(&output);
+    // Written in Canvas rather than posted with the tool
+    let grader = canvas.course().current_user.clone();
+    canvas
+        .course()
+        .submissions
+        .iter_mut()
+        .find(|s| s["assignment_id"] == 10 && s["user_id"] == 103)
+        .unwrap()["submission_comments"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!({
+            "id": 10,
+            "author_id": grader["id"],
+            "author_name": grader["name"],
+            "comment": "Ask me about the tests",
+            "created_at": "2025-03-02T12:00:00Z",
+        }));
+
+    let output = run(
+        &canvas,
+        &["10", "grade", "--replace-comments"],
+        "103: Well tested\n",
+    )
+    .await;
+    stdout(&output);
+
+    let comments: Vec<Value> = canvas.submission(10, 103)["submission_comments"]
+        .as_array()
+        .unwrap()
+        .clone();
+    assert_eq!(
+        comments.iter().map(|c| &c["comment"]).collect::<Vec<_>>(),
+        ["Ask me about the tests", "Well tested\n\u{2063}"]
+    );
+    let requests = canvas.course().requests.clone();
+    let posted = requests
+        .iter()
+        .rposition(|r| r.starts_with("POST") && r.contains("update_grades"))
+        .unwrap();
+    let deleted = requests
+        .iter()
+        .position(|r| r.starts_with("DELETE") && r.ends_with("/submissions/103/comments/1"))
+        .unwrap();
+    assert!(posted < deleted);
+}
+
+#[tokio::test]
+async fn grade_input_names_assignments() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["10,11", "grade"], "10/102: 7\n11/101: 9.5\n").await;
+    stdout(&output);
+
+    assert_eq!(canvas.submission(10, 102)["score"], 7.0);
+    assert_eq!(canvas.submission(11, 101)["score"], 9.5);
+    assert!(canvas.submission(11, 102)["score"].is_null());
+}
+
+#[tokio::test]
+async fn grade_input_without_assignment_fails_for_several_assignments() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["10,11", "grade"], "102: 7\n").await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs an <ASSIGNMENT_ID>/ prefix"));
+    assert!(canvas
+        .requests("/api/v1/courses/1/assignments/10/submissions/update_grades")
+        .is_empty());
+}
+
+#[tokio::test]
+async fn grade_input_rejects_malformed_lines() {
+    let canvas = MockCanvas::start(2).await;
+
+    for (input, error) in [
+        (
+            "103: 6\nSee the notes: they help\n",
+            "\"See the notes: they help\"",
+        ),
+        (
+            "group:Team 1: 9\n",
+            "Unknown student or group \"group:Team 1\"",
+        ),
+    ] {
+        let output = run(&canvas, &["10", "grade"], input).await;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(error));
+    }
+    assert!(canvas.submission(10, 103)["score"].is_null());
+}
+
+#[tokio::test]
+async fn commands_require_an_assignment() {
+    let canvas = MockCanvas::start(2).await;
+
+    let output = run(&canvas, &["count", "submitted"], "").await;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("<ASSIGNMENT_ID> is required"));
+}
+
+#[tokio::test]
+async fn recorded_requests_replay_without_canvas() {
+    let canvas = MockCanvas::start(2).await;
+    let cassette = canvas.directory.path().join("cassette");
+    let cassette = cassette.to_str().unwrap();
+
+    let feedback = canvas.directory.path().join("ada-feedback.txt");
+    std::fs::write(&feedback, "See the rubric").unwrap();
+    let grades = format!("102: 9\n102: attach: {}\n", feedback.display());
+    let commands: [(&[&str], &str); 2] = [
+        (&["10,11", "count", "graded"], ""),
+        (&["10", "grade"], &grades),
+    ];
+
+    let mut recorded = Vec::new();
+    for (args, stdin) in commands {
+        let output = run(&canvas, &[&["--record", cassette], args].concat(), stdin).await;
+        recorded.push(stdout(&output));
+    }
+    assert_eq!(recorded[0], "10: 2\n11: 1\n");
+    assert_eq!(canvas.requests("/files/upload").len(), 1);
+    let comments = canvas.submission(10, 102)["submission_comments"].clone();
+    let file_id = comments[0]["attachments"][0]["id"].as_u64().unwrap();
+    assert!(canvas.course().files[&file_id].contains("See the rubric"));
+    let requests = canvas.course().requests.len();
+    let contents: String = std::fs::read_dir(cassette)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    assert!(!contents.contains(ACCESS_TOKEN));
+    assert!(!contents.contains("Ada Lovelace"));
+    assert!(!contents.contains("ada-feedback"));
+    assert!(!contents.contains(&canvas.base_url));
+
+    // Without an access token or base URL, nothing can reach the mock
+    for ((args, stdin), recorded) in commands.into_iter().zip(recorded) {
+        let mut replay = command(canvas.directory.path());
+        replay
+            .args(["--course-id", "1", "--replay", cassette])
+            .args(args);
+        let replayed = output(replay, stdin).await;
+        assert_eq!(stdout(&replayed), recorded);
+    }
+    assert_eq!(canvas.course().requests.len(), requests);
+}
+
+#[tokio::test]
+async fn recorded_downloads_replay_without_their_contents() {
+    let canvas = MockCanvas::start(2).await;
+    let cassette = canvas.directory.path().join("cassette");
+    let cassette = cassette.to_str().unwrap();
+
+    let recorded = run(
+        &canvas,
+        &["--record", cassette, "10", "submissions", "ungraded"],
+        "",
+    )
+    .await;
+    stdout(&recorded);
+    let downloads = canvas.requests("/files/").len();
+    assert_eq!(downloads, 2);
+    let contents: String = std::fs::read_dir(cassette)
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    for personal in ["print('Alan')", "lab1.py", "preview=1"] {
+        assert!(!contents.contains(personal), "{personal} was recorded");
+    }
+
+    let replayed = command(canvas.directory.path())
+        .args(["--course-id", "1", "--replay", cassette])
+        .args(["10", "submissions", "ungraded"])
+        .output()
+        .await
+        .unwrap();
+    let files: Vec<(String, String)> = stdout(&replayed)
+        .lines()
+        .map(|path| {
+            let name = Path::new(path).file_name().unwrap().to_str().unwrap();
+            (name.to_owned(), std::fs::read_to_string(path).unwrap())
+        })
+        .collect();
+    assert_eq!(
+        files,
+        [
+            ("102_10_1_file-2.py".to_owned(), "[redacted]".to_owned()),
+            ("103_10_1_file-3.py".to_owned(), "[redacted]".to_owned())
+        ]
+    );
+    assert_eq!(canvas.requests("/files/").len(), downloads);
+}
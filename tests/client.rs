@@ -0,0 +1,83 @@
+mod support;
+
+use canvas_grading::{CanvasClient, Comment, Grade, StudentId, Submission, SubmissionState};
+use support::MockCanvas;
+
+#[tokio::test]
+async fn handles_list_submissions() {
+    let canvas = MockCanvas::start(2).await;
+    let client = CanvasClient::from_config(canvas.config());
+    let course = client.course(1);
+
+    let graded: Vec<StudentId> = course
+        .assignment(10)
+        .submissions()
+        .filter(Submission::graded)
+        .list()
+        .await
+        .unwrap()
+        .iter()
+        .map(Submission::user)
+        .collect();
+    assert_eq!(graded.len(), 2);
+
+    let assignments: Vec<u64> = course
+        .submissions()
+        .assignments(&[11, 10])
+        .state(SubmissionState::Submitted)
+        .list()
+        .await
+        .unwrap()
+        .iter()
+        .map(Submission::assignment)
+        .collect();
+    assert_eq!(assignments, [11, 11, 10, 10, 10, 10]);
+}
+
+#[tokio::test]
+async fn filters_combine() {
+    let canvas = MockCanvas::start(2).await;
+    let client = CanvasClient::from_config(canvas.config());
+    let submissions = client.course(1).assignment(10).submissions();
+
+    let count = submissions
+        .clone()
+        .filter(Submission::submitted)
+        .filter(Submission::late)
+        .count()
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(submissions.count().await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn grades_upload_through_the_assignment() {
+    let canvas = MockCanvas::start(10).await;
+    let client = CanvasClient::from_config(canvas.config());
+    let assignment = client.course(1).assignment(11);
+
+    let progress = assignment
+        .grades()
+        .upload(
+            &[Grade {
+                user_id: StudentId::User(101),
+                grade: 9.0,
+            }],
+            &[Comment {
+                user_id: StudentId::User(101),
+                comment: "Well done".to_owned(),
+            }],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    progress.wait(client.config()).await.unwrap();
+
+    let ada = canvas.submission(11, 101);
+    assert_eq!(ada["score"], 9.0);
+    assert_eq!(
+        ada["submission_comments"][0]["comment"],
+        "Well done\u{2063}"
+    );
+}
@@ -0,0 +1,113 @@
+use std::{path::Path, process::Command};
+
+use canvas_grading::{Manifest, RepositoryConfig, Submission};
+use serde_json::json;
+use tempfile::TempDir;
+
+fn git(directory: &Path, args: &[&str], date: &str) -> String {
+    let output = Command::new("git")
+        .args([
+            "-c",
+            "user.name=Student",
+            "-c",
+            "user.email=student@example.com",
+        ])
+        .args(args)
+        .current_dir(directory)
+        .env("GIT_AUTHOR_DATE", date)
+        .env("GIT_COMMITTER_DATE", date)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+/// A repository with a commit on each of two days, returning the id of each commit.
+fn student_repository(directory: &Path) -> [String; 2] {
+    git(directory, &["init", "--quiet"], "2024-01-01T00:00:00Z");
+    ["2024-01-01T12:00:00Z", "2024-01-03T12:00:00Z"].map(|date| {
+        std::fs::write(directory.join("main.rs"), date).unwrap();
+        git(directory, &["add", "main.rs"], date);
+        git(directory, &["commit", "--quiet", "-m", date], date);
+        git(directory, &["rev-parse", "HEAD"], date)
+    })
+}
+
+fn url_submission(url: &str) -> Submission {
+    serde_json::from_value(json!({
+        "id": 1,
+        "user_id": 101,
+        "assignment_id": 10,
+        "attempt": 1,
+        "workflow_state": "submitted",
+        "submission_type": "online_url",
+        "url": url,
+        "submitted_at": "2024-01-02T00:00:00Z",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn local_and_ssh_urls_need_opting_in() {
+    let local = url_submission("file:///home/grader/.config/grading");
+    let ssh = url_submission("git@github.com:student/project.git");
+    let https = url_submission("https://github.com/student/project/tree/main");
+
+    let default = RepositoryConfig::default();
+    assert!(local.repository(&default).is_none());
+    assert!(ssh.repository(&default).is_none());
+    assert_eq!(
+        https.repository(&default).unwrap().url(),
+        "https://github.com/student/project"
+    );
+
+    let allowed = RepositoryConfig {
+        allow_local: true,
+        allow_ssh: true,
+    };
+    assert!(local.repository(&allowed).is_some());
+    assert!(ssh.repository(&allowed).is_some());
+}
+
+#[tokio::test]
+async fn local_repositories_are_cloned_when_allowed() {
+    let source = TempDir::new().unwrap();
+    let [_, latest] = student_repository(source.path());
+    let submission = url_submission(&format!("file://{}", source.path().display()));
+    let repository = submission
+        .repository(&RepositoryConfig {
+            allow_local: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let downloads = TempDir::new().unwrap();
+    let clone = repository.download(downloads.path(), false).await.unwrap();
+
+    assert!(clone.join("main.rs").exists());
+    let manifest = Manifest::read(downloads.path()).unwrap();
+    assert_eq!(manifest.0[&repository.to_string()].commit, latest);
+}
+
+#[tokio::test]
+async fn clones_check_out_the_commit_before_submission() {
+    let source = TempDir::new().unwrap();
+    let [submitted, _] = student_repository(source.path());
+    let submission = url_submission(&format!("file://{}", source.path().display()));
+    let repository = submission
+        .repository(&RepositoryConfig {
+            allow_local: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+    let downloads = TempDir::new().unwrap();
+    let clone = repository.download(downloads.path(), true).await.unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(clone.join("main.rs")).unwrap(),
+        "2024-01-01T12:00:00Z"
+    );
+    let manifest = Manifest::read(downloads.path()).unwrap();
+    assert_eq!(manifest.0[&repository.to_string()].commit, submitted);
+}
@@ -0,0 +1,154 @@
+mod support;
+
+use canvas_grading::{Comment, Grade, Include, StudentId, Submission};
+use support::MockCanvas;
+
+#[tokio::test]
+async fn assignment_submissions_follow_pagination() {
+    let canvas = MockCanvas::start(2).await;
+    let config = canvas.config();
+
+    let submissions = Submission::assignment_submissions(10, &[], &|_| true, &config)
+        .await
+        .unwrap();
+
+    let mut users: Vec<StudentId> = submissions.iter().map(Submission::user).collect();
+    users.sort();
+    assert_eq!(
+        users,
+        [101, 102, 103, 104, 105].map(StudentId::User).to_vec()
+    );
+    assert_eq!(
+        canvas
+            .requests("/api/v1/courses/1/assignments/10/submissions")
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn predicates_filter_submissions() {
+    let canvas = MockCanvas::start(2).await;
+    let config = canvas.config();
+
+    let count = |predicate: fn(&Submission) -> bool| {
+        let config = config.clone();
+        async move {
+            Submission::count_submissions(10, &[], &predicate, &config)
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(count(Submission::submitted).await, 4);
+    assert_eq!(count(Submission::unsubmitted).await, 1);
+    assert_eq!(count(Submission::graded).await, 2);
+    assert_eq!(count(Submission::ungraded).await, 2);
+    assert_eq!(count(Submission::late).await, 1);
+}
+
+#[tokio::test]
+async fn included_users_are_parsed() {
+    let canvas = MockCanvas::start(10).await;
+    let config = canvas.config();
+
+    let submissions = Submission::assignment_submissions(10, &[Include::User], &|_| true, &config)
+        .await
+        .unwrap();
+    let ada = submissions
+        .iter()
+        .find(|s| s.user() == StudentId::User(101))
+        .unwrap();
+
+    assert_eq!(ada.student_name(), Some("Ada Lovelace"));
+    assert_eq!(ada.score(), Some(9.0));
+    assert!(
+        canvas.requests("/api/v1/courses/1/assignments/10/submissions")[0]
+            .contains("include%5B%5D=user")
+    );
+}
+
+#[tokio::test]
+async fn several_assignments_are_fetched_in_one_pass() {
+    let canvas = MockCanvas::start(3).await;
+    let config = canvas.config();
+
+    Submission::for_assignments(&[11, 10], &[], &Submission::submitted, &config)
+        .await
+        .unwrap();
+
+    // All ten submissions take four pages of the bulk endpoint before filtering
+    assert_eq!(
+        canvas
+            .requests("/api/v1/courses/1/students/submissions")
+            .len(),
+        4
+    );
+    assert!(canvas.requests("/api/v1/courses/1/assignments/").is_empty());
+}
+
+#[tokio::test]
+async fn submitted_files_download() {
+    let canvas = MockCanvas::start(10).await;
+    let config = canvas.config();
+    let directory = canvas.directory.path().join("downloads");
+
+    let submissions = Submission::assignment_submissions(10, &[], &Submission::submitted, &config)
+        .await
+        .unwrap();
+    let grace = submissions
+        .iter()
+        .find(|s| s.user() == StudentId::User(103))
+        .unwrap();
+    let files = grace.files().unwrap();
+    let path = files[0].download(&directory, &config).await.unwrap();
+
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "print('Grace')\n");
+}
+
+#[tokio::test]
+async fn grades_and_comments_are_uploaded() {
+    let canvas = MockCanvas::start(10).await;
+    let config = canvas.config();
+
+    let progress = Submission::update_grades_with_comments(
+        10,
+        &[Grade {
+            user_id: StudentId::User(102),
+            grade: 8.5,
+        }],
+        &[Comment {
+            user_id: StudentId::User(103),
+            comment: "Submitted after the due date".to_owned(),
+        }],
+        false,
+        &config,
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(progress.workflow_state, "queued");
+    let progress = progress.wait(&config).await.unwrap();
+    assert_eq!(progress.workflow_state, "completed");
+
+    let alan = canvas.submission(10, 102);
+    assert_eq!(alan["score"], 8.5);
+    assert_eq!(alan["workflow_state"], "graded");
+    assert_eq!(alan["grader_id"], 900);
+    let grace = canvas.submission(10, 103);
+    assert_eq!(
+        grace["submission_comments"][0]["comment"],
+        "Submitted after the due date\u{2063}"
+    );
+    assert!(grace["score"].is_null());
+}
+
+#[tokio::test]
+async fn requests_without_the_access_token_fail() {
+    let canvas = MockCanvas::start(10).await;
+    let mut config = canvas.config();
+    config.client = reqwest::Client::new().into();
+
+    let result = Submission::assignment_submissions(10, &[], &|_| true, &config).await;
+
+    assert!(result.is_err());
+}